@@ -1,9 +1,230 @@
+use std::collections::BTreeMap;
+use std::{error, fmt, fs};
+
+/// Options that take their value from the following argument. These are needed to determine
+/// where the main class is, as `@argfiles` following it are passed to the program untouched.
+const WHITESPACE_OPTIONS: [&'static str; 14] = [
+    "-cp",
+    "-classpath",
+    "-e",
+    "--env-file",
+    "-H",
+    "-host",
+    "-jar",
+    "-p",
+    "--module-path",
+    "--add-modules",
+    "--add-opens",
+    "--add-exports",
+    "-m",
+    "--module",
+];
+
+/// The number of times that `-resubmit` resubmits a process, unless a number is provided
+pub const DEFAULT_RESUBMIT_ATTEMPTS: u32 = 5;
+
+/// Options whose value determines what is to be executed, i.e. the "main class"
+const MAIN_OPTIONS: [&'static str; 3] = ["-jar", "-m", "--module"];
+
+/// Options that `java` refuses to accept from `JDK_JAVA_OPTIONS`, as they either specify the
+/// main class or cause it to exit without running one.
+const ENV_DISALLOWED_OPTIONS: [&'static str; 6] =
+    ["-jar", "-m", "--module", "-?", "-help", "-version"];
+
+/// An error encountered while parsing arguments. Where present, `position` is the index of the
+/// offending argument within those parsed, i.e. after `@argfiles` have been expanded and any
+/// `JAVA_TOOL_OPTIONS` and `JDK_JAVA_OPTIONS` prepended. Errors from `@argfiles` themselves
+/// refer to the arguments as given.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ParseError {
+    ArgFileNotFound { path: String, position: usize },
+    InvalidEnvVar { arg: String, position: usize },
+    InvalidHost { option: String, position: usize },
+    InvalidProfile { message: String },
+    InvalidProperty { arg: String, position: usize },
+    InvalidResubmit { arg: String, position: usize },
+    InvalidSize { arg: String, position: usize },
+    MainClassInEnv { variable: String },
+    MissingValue { option: String, position: usize },
+    NotAllowedInEnv { option: String, variable: String },
+    UnrecognizedOption { arg: String, position: usize },
+}
+
+impl ParseError {
+    /// The index of the offending argument, if the error relates to one
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            ParseError::ArgFileNotFound { position, .. }
+            | ParseError::InvalidEnvVar { position, .. }
+            | ParseError::InvalidHost { position, .. }
+            | ParseError::InvalidProperty { position, .. }
+            | ParseError::InvalidResubmit { position, .. }
+            | ParseError::InvalidSize { position, .. }
+            | ParseError::MissingValue { position, .. }
+            | ParseError::UnrecognizedOption { position, .. } => Some(position),
+
+            ParseError::InvalidProfile { .. }
+            | ParseError::MainClassInEnv { .. }
+            | ParseError::NotAllowedInEnv { .. } => None,
+        }
+    }
+
+    /// The status that `java` exits with upon encountering this error
+    pub fn exit_code(&self) -> i32 {
+        1
+    }
+
+    /// Whether `java` reports this error from the JVM rather than the launcher, in which case
+    /// it is followed by a message stating that the JVM could not be created
+    pub fn is_jvm_error(&self) -> bool {
+        match *self {
+            ParseError::InvalidProperty { .. }
+            | ParseError::InvalidSize { .. }
+            | ParseError::UnrecognizedOption { .. } => true,
+
+            _ => false,
+        }
+    }
+
+    /// Whether `java` follows this error with its usage
+    pub fn shows_usage(&self) -> bool {
+        match *self {
+            ParseError::MissingValue { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::ArgFileNotFound { ref path, .. } => {
+                write!(f, "Error: could not open `{}'", path)
+            }
+
+            ParseError::InvalidEnvVar { ref arg, .. } => {
+                write!(f, "Error: invalid environment variable: {}", arg)
+            }
+
+            ParseError::InvalidHost { ref option, .. } => write!(
+                f,
+                "Error: {} must begin with \"tcp://\" or \"unix://\"",
+                option
+            ),
+
+            ParseError::InvalidProfile { ref message } => write!(f, "Error: {}", message),
+
+            ParseError::InvalidProperty { ref arg, .. } => {
+                write!(f, "Invalid system property: {}", arg)
+            }
+
+            ParseError::InvalidResubmit { ref arg, .. } => {
+                write!(f, "Error: invalid number of resubmit attempts: {}", arg)
+            }
+
+            ParseError::InvalidSize { ref arg, .. } => {
+                let kind = if arg.starts_with("-Xms") {
+                    "initial heap size"
+                } else if arg.starts_with("-Xmx") {
+                    "maximum heap size"
+                } else {
+                    "thread stack size"
+                };
+
+                write!(f, "Invalid {}: {}", kind, arg)
+            }
+
+            ParseError::MainClassInEnv { ref variable } => write!(
+                f,
+                "Error: Cannot specify main class in environment variable {}",
+                variable
+            ),
+
+            ParseError::MissingValue { ref option, .. } => {
+                let requirement = match option.as_str() {
+                    "-cp" | "-classpath" => "class path specification",
+                    "-e" => "an environment variable",
+                    o if o.starts_with("--env-file") => "an environment file",
+                    "-H" | "-host" => "host specification",
+                    "-jar" => "jar file specification",
+                    "-m" | "--module" => "module name",
+                    "-p" | "--module-path" => "module path specification",
+                    "--add-modules" => "modules to be specified",
+                    o if o.starts_with("-javaagent:") => "jar file specification",
+                    _ => "an argument",
+                };
+
+                write!(f, "Error: {} requires {}", option, requirement)
+            }
+
+            ParseError::NotAllowedInEnv {
+                ref option,
+                ref variable,
+            } => write!(
+                f,
+                "Error: Option {} is not allowed in environment variable {}",
+                option, variable
+            ),
+
+            ParseError::UnrecognizedOption { ref arg, .. } => {
+                write!(f, "Unrecognized option: {}", arg)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::ArgFileNotFound { .. } => "argument file not found",
+            ParseError::InvalidEnvVar { .. } => "invalid environment variable",
+            ParseError::InvalidHost { .. } => "invalid host",
+            ParseError::InvalidProfile { .. } => "invalid profile",
+            ParseError::InvalidProperty { .. } => "invalid system property",
+            ParseError::InvalidResubmit { .. } => "invalid resubmit attempts",
+            ParseError::InvalidSize { .. } => "invalid size",
+            ParseError::MainClassInEnv { .. } => "main class specified in environment",
+            ParseError::MissingValue { .. } => "missing option value",
+            ParseError::NotAllowedInEnv { .. } => "option not allowed in environment",
+            ParseError::UnrecognizedOption { .. } => "unrecognized option",
+        }
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub enum ExecutionMode {
     Class { class: String, args: Vec<String> },
     Exit { code: i32 },
     Help { code: i32 },
     JarFile { file: String, args: Vec<String> },
+    Module { module: String, args: Vec<String> },
+}
+
+/// Heap and stack sizes (in bytes) that were requested via `-Xmx`, `-Xms` and `-Xss`. As
+/// processes share the one JVM, these cannot be applied as-is and are instead provided
+/// to landlordd as hints, should it support them.
+#[derive(PartialEq, Debug, Default)]
+pub struct ResourceHints {
+    pub initial_heap_size: Option<u64>,
+    pub max_heap_size: Option<u64>,
+    pub thread_stack_size: Option<u64>,
+}
+
+impl ResourceHints {
+    /// Returns the hints as `java` flags, with sizes in the largest unit that they're a
+    /// multiple of, e.g. `-Xmx512m`
+    pub fn flags(&self) -> Vec<String> {
+        let hints = [
+            ("-Xms", self.initial_heap_size),
+            ("-Xmx", self.max_heap_size),
+            ("-Xss", self.thread_stack_size),
+        ];
+
+        hints
+            .iter()
+            .filter_map(|&(flag, size)| size.map(|s| format!("{}{}", flag, format_size(s))))
+            .collect()
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -14,39 +235,190 @@ pub enum Host {
 
 #[derive(PartialEq, Debug)]
 pub struct JavaArgs {
+    pub add_exports: Vec<String>,
+    pub add_modules: Vec<String>,
+    pub add_opens: Vec<String>,
+    pub agents: Vec<(String, Option<String>)>,
+    pub assertions: Vec<String>,
+    pub cache: bool,
+    pub compress: bool,
+    pub compression_level: Option<u32>,
     pub cp: Vec<String>,
-    pub errors: Vec<String>,
+    pub dry_run: bool,
+    pub env: Vec<(String, Option<String>)>,
+    pub env_allow: Vec<String>,
+    pub env_deny: Vec<String>,
+    pub errors: Vec<ParseError>,
+    pub excluded_signals: Vec<i32>,
+    pub heartbeat_timeout_millis: u64,
     pub mode: ExecutionMode,
+    pub module_path: Vec<String>,
+    pub notices: Vec<String>,
     pub props: Vec<(String, String)>,
     pub host: Host,
+    pub resource_hints: ResourceHints,
+    pub resubmit_attempts: u32,
+    pub resubmit_backoff_millis: u64,
+    pub tty: bool,
     pub version: bool,
     pub wait: bool,
+    pub wait_interval_millis: u64,
+}
+
+impl Default for JavaArgs {
+    fn default() -> JavaArgs {
+        default()
+    }
 }
 
 fn default() -> JavaArgs {
     JavaArgs {
+        add_exports: vec![],
+        add_modules: vec![],
+        add_opens: vec![],
+        agents: vec![],
+        assertions: vec![],
+        cache: true,
+        compress: true,
+        compression_level: None,
         cp: vec![".".to_string()],
+        dry_run: false,
+        env: vec![],
+        env_allow: vec![],
+        env_deny: vec![],
         errors: vec![],
+        excluded_signals: vec![],
+        heartbeat_timeout_millis: 30000,
         mode: ExecutionMode::Help { code: 1 },
+        module_path: vec![],
+        notices: vec![],
         props: vec![],
         host: Host::Unix("/var/run/landlord/landlordd.sock".to_string()),
+        resource_hints: ResourceHints::default(),
+        resubmit_attempts: 0,
+        resubmit_backoff_millis: 1000,
+        tty: false,
         version: false,
         wait: false,
+        wait_interval_millis: 5000,
     }
 }
 
 pub fn parse_java_args<S: AsRef<str>>(args: &[S]) -> JavaArgs {
+    let mut jargs = default();
+
+    let args = expand_arg_files(args, &mut jargs.errors);
+
+    parse_expanded_args(jargs, &args)
+}
+
+/// Parses arguments as per `parse_java_args`, but also honours the environment variables
+/// that `java` consults, i.e. `CLASSPATH` when no class path is specified, `JDK_JAVA_OPTIONS`
+/// whose options are prepended to `args`, and the `-D` properties of `JAVA_TOOL_OPTIONS`
+/// which may be overridden by those specified elsewhere. The same "Picked up" messages that
+/// `java` prints are provided via `notices`. `env` looks up a variable by name, e.g.
+/// `|name| std::env::var(name).ok()`.
+pub fn parse_java_args_env<S, E>(args: &[S], env: E) -> JavaArgs
+where
+    S: AsRef<str>,
+    E: Fn(&str) -> Option<String>,
+{
+    parse_java_args_with(default(), args, env)
+}
+
+/// Parses arguments as per `parse_java_args_env`, applying them to `base` rather than the
+/// defaults. This allows settings from elsewhere, e.g. a configuration profile, to be
+/// overridden by the environment and command line.
+pub fn parse_java_args_with<S, E>(base: JavaArgs, args: &[S], env: E) -> JavaArgs
+where
+    S: AsRef<str>,
+    E: Fn(&str) -> Option<String>,
+{
+    let mut jargs = base;
+    let mut jdk_args = vec![];
+    let mut tool_props = vec![];
+
+    if let Some(cp) = env("CLASSPATH").filter(|cp| !cp.is_empty()) {
+        jargs.cp = cp.split(":").map(|s| s.to_string()).collect();
+    }
+
+    if let Some(options) = env("JDK_JAVA_OPTIONS").filter(|o| !o.trim().is_empty()) {
+        jargs
+            .notices
+            .push(format!("NOTE: Picked up JDK_JAVA_OPTIONS: {}", options));
+
+        let env_args = expand_arg_files(&parse_arg_file(&options), &mut jargs.errors);
+
+        let mut expecting_value = false;
+
+        for arg in env_args {
+            if ENV_DISALLOWED_OPTIONS.contains(&arg.as_str()) {
+                jargs.errors.push(ParseError::NotAllowedInEnv {
+                    option: arg.clone(),
+                    variable: "JDK_JAVA_OPTIONS".to_string(),
+                });
+            } else if arg.starts_with("-") {
+                expecting_value = WHITESPACE_OPTIONS.contains(&arg.as_str());
+            } else if !expecting_value {
+                jargs.errors.push(ParseError::MainClassInEnv {
+                    variable: "JDK_JAVA_OPTIONS".to_string(),
+                });
+            } else {
+                expecting_value = false;
+            }
+
+            jdk_args.push(arg);
+        }
+    }
+
+    if let Some(options) = env("JAVA_TOOL_OPTIONS").filter(|o| !o.trim().is_empty()) {
+        jargs
+            .notices
+            .push(format!("Picked up JAVA_TOOL_OPTIONS: {}", options));
+
+        tool_props = parse_arg_file(&options)
+            .into_iter()
+            .filter(|o| o.starts_with("-D"))
+            .collect();
+    }
+
+    // JAVA_TOOL_OPTIONS are processed by the JVM prior to any others, so its properties come first
+
+    let mut all_args = tool_props;
+
+    all_args.append(&mut jdk_args);
+    all_args.append(&mut expand_arg_files(args, &mut jargs.errors));
+
+    parse_expanded_args(jargs, &all_args)
+}
+
+fn parse_expanded_args(mut jargs: JavaArgs, args: &[String]) -> JavaArgs {
     // We want to aim to be a drop-in replacement for java, so we have to roll our own arg parser
     // because DocOpt/Clap/et al don't have the required features to match the rather strange java
     // arguments.
 
-    let noop_flags = ["-server", "-d64", "-d32"];
+    let noop_flags = ["-server", "-d64", "-d32", "--disable-@files"];
 
-    let mut jargs = default();
+    // options that only make sense for a JVM as a whole, and therefore can't be applied to
+    // a process that shares one
+
+    let jvm_flag_prefixes = [
+        "-X",
+        "-verbose",
+        "-agentlib:",
+        "-agentpath:",
+        "-esa",
+        "-dsa",
+        "-enablesystemassertions",
+        "-disablesystemassertions",
+    ];
 
-    let mut iter = args.iter().map(|r| r.as_ref());
+    let assertion_flags = ["-ea", "-da", "-enableassertions", "-disableassertions"];
+
+    let mut iter = args.iter().map(|r| r.as_str());
 
     loop {
+        let position = args.len() - iter.len();
         let next = iter.next();
 
         match next {
@@ -76,9 +448,80 @@ pub fn parse_java_args<S: AsRef<str>>(args: &[S]) -> JavaArgs {
                         args: items,
                     };
                 } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                }
+            }
+
+            Some(flag) if flag == "-m" || flag == "--module" => {
+                if let Some(module) = iter.next() {
+                    let mut items = vec![];
+
+                    while let Some(next) = iter.next() {
+                        items.push(next.to_string());
+                    }
+
+                    jargs.mode = ExecutionMode::Module {
+                        module: module.to_string(),
+                        args: items,
+                    };
+                } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                }
+            }
+
+            Some(flag)
+                if flag == "-p"
+                    || flag == "--module-path"
+                    || flag.starts_with("--module-path=") =>
+            {
+                if let Some(module_path) = option_value(flag, &mut iter) {
+                    jargs.module_path = module_path.split(":").map(|s| s.to_string()).collect();
+                } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                }
+            }
+
+            Some(flag) if flag == "--add-modules" || flag.starts_with("--add-modules=") => {
+                if let Some(modules) = option_value(flag, &mut iter) {
                     jargs
-                        .errors
-                        .push(format!("{} requires jar file specification", flag))
+                        .add_modules
+                        .extend(modules.split(",").map(|s| s.to_string()));
+                } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                }
+            }
+
+            Some(flag) if flag == "--add-opens" || flag.starts_with("--add-opens=") => {
+                if let Some(opens) = option_value(flag, &mut iter) {
+                    jargs.add_opens.push(opens.to_string());
+                } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                }
+            }
+
+            Some(flag) if flag == "--add-exports" || flag.starts_with("--add-exports=") => {
+                if let Some(exports) = option_value(flag, &mut iter) {
+                    jargs.add_exports.push(exports.to_string());
+                } else {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
                 }
             }
 
@@ -99,50 +542,182 @@ pub fn parse_java_args<S: AsRef<str>>(args: &[S]) -> JavaArgs {
                 if let Some(cp) = iter.next() {
                     jargs.cp = cp.split(":").map(|s| s.to_string()).collect();
                 } else {
-                    jargs
-                        .errors
-                        .push(format!("{} requires class path specification", flag))
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
                 }
             }
 
             Some(flag) if flag == "-H" || flag == "-host" => {
                 if let Some(host) = iter.next() {
-                    if host.starts_with("tcp://") {
-                        jargs.host = Host::Tcp(host[6..].to_string());
-                    } else if host.starts_with("unix://") {
-                        jargs.host = Host::Unix(host[7..].to_string());
+                    if let Some(host) = parse_host(host) {
+                        jargs.host = host;
                     } else {
-                        jargs.errors.push(format!(
-                            "{} must begin with \"tcp://\" or \"unix://\"",
-                            flag
-                        ))
+                        jargs.errors.push(ParseError::InvalidHost {
+                            option: flag.to_string(),
+                            position,
+                        })
                     }
                 } else {
-                    jargs
-                        .errors
-                        .push(format!("{} requires host specification", flag))
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
                 }
             }
 
             Some(flag) if flag.starts_with("-D") => {
-                if let Some(s) = flag.get(2..) {
-                    let parts: Vec<&str> = s.splitn(2, "=").collect();
+                // as with java, a property without a value is set to the empty string
 
-                    if parts.len() == 2 {
-                        jargs
-                            .props
-                            .push((parts[0].to_string(), parts[1].to_string()));
-                    }
+                let parts: Vec<&str> = flag[2..].splitn(2, "=").collect();
+
+                if parts[0].is_empty() {
+                    jargs.errors.push(ParseError::InvalidProperty {
+                        arg: flag.to_string(),
+                        position,
+                    })
+                } else {
+                    jargs.props.push((
+                        parts[0].to_string(),
+                        parts.get(1).unwrap_or(&"").to_string(),
+                    ));
                 }
             }
 
+            Some(flag) if flag.starts_with("-javaagent:") => {
+                let parts: Vec<&str> = flag[11..].splitn(2, "=").collect();
+
+                if parts[0].is_empty() {
+                    jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    })
+                } else {
+                    jargs
+                        .agents
+                        .push((parts[0].to_string(), parts.get(1).map(|o| o.to_string())));
+                }
+            }
+
+            Some(flag)
+                if assertion_flags
+                    .iter()
+                    .any(|f| flag == *f || flag.starts_with(&format!("{}:", f))) =>
+            {
+                jargs.assertions.push(flag.to_string());
+            }
+
+            Some(flag) if flag.starts_with("-Xms") => match parse_size(&flag[4..]) {
+                Some(size) => jargs.resource_hints.initial_heap_size = Some(size),
+                None => jargs.errors.push(ParseError::InvalidSize {
+                    arg: flag.to_string(),
+                    position,
+                }),
+            },
+
+            Some(flag) if flag.starts_with("-Xmx") => match parse_size(&flag[4..]) {
+                Some(size) => jargs.resource_hints.max_heap_size = Some(size),
+                None => jargs.errors.push(ParseError::InvalidSize {
+                    arg: flag.to_string(),
+                    position,
+                }),
+            },
+
+            Some(flag) if flag.starts_with("-Xss") => match parse_size(&flag[4..]) {
+                Some(size) => jargs.resource_hints.thread_stack_size = Some(size),
+                None => jargs.errors.push(ParseError::InvalidSize {
+                    arg: flag.to_string(),
+                    position,
+                }),
+            },
+
+            Some(flag)
+                if flag.len() > 2 && jvm_flag_prefixes.iter().any(|p| flag.starts_with(p)) =>
+            {
+                jargs.notices.push(format!(
+                    "landlord: ignoring {} as it cannot be applied to a shared JVM",
+                    flag
+                ));
+            }
+
             Some(flag) if flag == "-wait" => {
                 jargs.wait = true;
             }
 
+            Some(flag) if flag == "-resubmit" => {
+                jargs.resubmit_attempts = DEFAULT_RESUBMIT_ATTEMPTS;
+            }
+
+            Some(flag) if flag.starts_with("-resubmit:") => match flag[10..].parse() {
+                Ok(attempts) => jargs.resubmit_attempts = attempts,
+                Err(_) => jargs.errors.push(ParseError::InvalidResubmit {
+                    arg: flag.to_string(),
+                    position,
+                }),
+            },
+
+            Some(flag) if flag == "--dry-run" => {
+                jargs.dry_run = true;
+            }
+
+            Some(flag) if flag == "-t" => {
+                jargs.tty = true;
+            }
+
+            Some(flag) if flag == "-e" => match iter.next() {
+                Some(var) => match parse_env_var(var) {
+                    Some(var) => jargs.env.push(var),
+                    None => jargs.errors.push(ParseError::InvalidEnvVar {
+                        arg: var.to_string(),
+                        position,
+                    }),
+                },
+
+                None => jargs.errors.push(ParseError::MissingValue {
+                    option: flag.to_string(),
+                    position,
+                }),
+            },
+
+            Some(flag) if flag == "--env-file" || flag.starts_with("--env-file=") => {
+                match option_value(flag, &mut iter) {
+                    Some(path) => match fs::read_to_string(path) {
+                        Ok(contents) => {
+                            for line in contents.lines().map(|l| l.trim_start()) {
+                                if line.is_empty() || line.starts_with('#') {
+                                    continue;
+                                }
+
+                                match parse_env_var(line) {
+                                    Some(var) => jargs.env.push(var),
+                                    None => jargs.errors.push(ParseError::InvalidEnvVar {
+                                        arg: line.to_string(),
+                                        position,
+                                    }),
+                                }
+                            }
+                        }
+
+                        Err(_) => jargs.errors.push(ParseError::ArgFileNotFound {
+                            path: path.to_string(),
+                            position,
+                        }),
+                    },
+
+                    None => jargs.errors.push(ParseError::MissingValue {
+                        option: flag.to_string(),
+                        position,
+                    }),
+                }
+            }
+
             Some(flag) if noop_flags.contains(&flag) => {}
 
-            Some(flag) => jargs.errors.push(format!("Unrecognized option: {}", flag)),
+            Some(flag) => jargs.errors.push(ParseError::UnrecognizedOption {
+                arg: flag.to_string(),
+                position,
+            }),
 
             None => {
                 return jargs;
@@ -151,6 +726,319 @@ pub fn parse_java_args<S: AsRef<str>>(args: &[S]) -> JavaArgs {
     }
 }
 
+/// Parses an environment variable as given to `-e` or in an `--env-file`, i.e. `NAME=value`,
+/// or `NAME` alone if its value is to be copied from the client
+fn parse_env_var(var: &str) -> Option<(String, Option<String>)> {
+    let parts: Vec<&str> = var.splitn(2, "=").collect();
+
+    if parts[0].is_empty() {
+        None
+    } else {
+        Some((parts[0].to_string(), parts.get(1).map(|v| v.to_string())))
+    }
+}
+
+/// Resolves the environment variables that are to be forwarded to the process, given those
+/// of the client. Variables that match `env_allow` and not `env_deny` are forwarded, as are
+/// those given by `-e` and `--env-file`, which take precedence. Those that are to be copied
+/// from the client are also subject to `env_deny`, and skipped if the client hasn't set them.
+/// Patterns match a name exactly, or its prefix if they end with `*`.
+pub fn forwarded_env<I>(jargs: &JavaArgs, client_env: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let matches = |patterns: &[String], name: &str| {
+        patterns.iter().any(|p| {
+            if p.ends_with('*') {
+                name.starts_with(&p[..p.len() - 1])
+            } else {
+                p == name
+            }
+        })
+    };
+
+    let client_env = client_env
+        .into_iter()
+        .filter(|&(ref name, _)| !matches(&jargs.env_deny, name))
+        .collect::<BTreeMap<String, String>>();
+
+    let mut env = client_env
+        .iter()
+        .filter(|&(name, _)| matches(&jargs.env_allow, name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<BTreeMap<String, String>>();
+
+    for &(ref name, ref value) in &jargs.env {
+        match *value {
+            Some(ref value) => {
+                env.insert(name.clone(), value.clone());
+            }
+
+            None => {
+                if let Some(value) = client_env.get(name) {
+                    env.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    env.into_iter().collect()
+}
+
+/// Returns the system properties that describe the client's context, so that the process can
+/// behave as if it was launched locally: its working directory, user, timezone and locale. They
+/// precede any others, so can be overridden via `-D`. The timezone (`TZ`) and locale (the first
+/// of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set) are conveyed as they are, rather than as
+/// the likes of `user.timezone`, as those are read once by the JVM and so are shared by its
+/// processes.
+pub fn host_props<E>(
+    cwd: Option<String>,
+    uid: u32,
+    user: Option<String>,
+    env: E,
+) -> Vec<(String, String)>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    let mut props = vec![];
+
+    if let Some(cwd) = cwd {
+        props.push(("landlord.client.cwd".to_string(), cwd));
+    }
+
+    props.push(("landlord.client.uid".to_string(), uid.to_string()));
+
+    if let Some(user) = user.or_else(|| env("USER")) {
+        props.push(("landlord.client.user".to_string(), user));
+    }
+
+    // a leading colon denotes a path in the timezone database, as is the norm anyway
+
+    if let Some(timezone) = env("TZ")
+        .map(|tz| tz.trim_start_matches(':').to_string())
+        .filter(|tz| !tz.is_empty())
+    {
+        props.push(("landlord.client.timezone".to_string(), timezone));
+    }
+
+    if let Some(locale) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env(name))
+        .find(|locale| !locale.is_empty())
+    {
+        props.push(("landlord.client.locale".to_string(), locale));
+    }
+
+    props
+}
+
+/// Parses a host URI, i.e. one beginning with `tcp://` or `unix://`
+pub fn parse_host(host: &str) -> Option<Host> {
+    if host.starts_with("tcp://") {
+        Some(Host::Tcp(host[6..].to_string()))
+    } else if host.starts_with("unix://") {
+        Some(Host::Unix(host[7..].to_string()))
+    } else {
+        None
+    }
+}
+
+/// Parses a size as accepted by `-Xmx` and friends, i.e. a number of bytes optionally
+/// followed by a `k`, `m`, `g` or `t` unit (in either case).
+fn parse_size(size: &str) -> Option<u64> {
+    let (digits, multiplier) = match size.chars().last() {
+        Some('k') | Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 1 << 30),
+        Some('t') | Some('T') => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+}
+
+/// Formats a size in the form that `parse_size` accepts
+fn format_size(size: u64) -> String {
+    let units = [
+        ("t", 1 << 40),
+        ("g", 1 << 30),
+        ("m", 1 << 20),
+        ("k", 1 << 10),
+    ];
+
+    match units.iter().find(|&&(_, n)| size > 0 && size % n == 0) {
+        Some(&(unit, n)) => format!("{}{}", size / n, unit),
+        None => size.to_string(),
+    }
+}
+
+/// Returns the value of an option that has been specified either as `--name=value` or
+/// as `--name value`, consuming the next argument in the latter case.
+fn option_value<'a, I>(flag: &'a str, iter: &mut I) -> Option<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match flag.find('=') {
+        Some(i) if flag.starts_with("--") => Some(&flag[i + 1..]),
+        _ => iter.next(),
+    }
+}
+
+/// Expands any `@argfiles` in the manner of the JDK launcher. Expansion stops once the main
+/// class (or jar file) has been found, or after `--disable-@files` is specified. An argument
+/// beginning with `@@` is passed through with its first `@` removed.
+fn expand_arg_files<S: AsRef<str>>(args: &[S], errors: &mut Vec<ParseError>) -> Vec<String> {
+    let mut expanded = vec![];
+    let mut disabled = false;
+    let mut expecting_value = false;
+    let mut main_found = false;
+
+    for (position, arg) in args.iter().map(|a| a.as_ref()).enumerate() {
+        let entries = if main_found || disabled || !arg.starts_with('@') || arg.len() == 1 {
+            vec![arg.to_string()]
+        } else if arg.starts_with("@@") {
+            vec![arg[1..].to_string()]
+        } else {
+            match fs::read_to_string(&arg[1..]) {
+                Ok(contents) => parse_arg_file(&contents),
+
+                Err(_) => {
+                    errors.push(ParseError::ArgFileNotFound {
+                        path: arg[1..].to_string(),
+                        position,
+                    });
+
+                    vec![]
+                }
+            }
+        };
+
+        for entry in entries {
+            if entry.starts_with('-') {
+                expecting_value = WHITESPACE_OPTIONS.contains(&entry.as_str())
+                    && !MAIN_OPTIONS.contains(&entry.as_str());
+
+                if entry == "--disable-@files" {
+                    disabled = true;
+                }
+            } else {
+                main_found = main_found || !expecting_value;
+                expecting_value = false;
+            }
+
+            expanded.push(entry);
+        }
+    }
+
+    expanded
+}
+
+#[derive(Clone, Copy)]
+enum ArgFileState {
+    FindNext,
+    InComment,
+    InEscape(char),
+    InQuote(char),
+    InToken,
+    SkipLeadingWhitespace(char),
+}
+
+/// Tokenizes the contents of an `@argfile` as per the JDK launcher. Arguments are separated
+/// by whitespace and may be (partially) quoted with `"` or `'`. Within quotes, `\` escapes the
+/// next character (`\n`, `\r`, `\t` and `\f` are supported), and a `\` at the end of a line
+/// continues the argument on the next line with its leading whitespace removed. Outside of
+/// quotes, `#` begins a comment that runs until the end of the line.
+pub fn parse_arg_file(contents: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut token = String::new();
+    let mut state = ArgFileState::FindNext;
+
+    for c in contents.chars() {
+        let is_whitespace =
+            c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\x0b' || c == '\x0c';
+
+        state = match state {
+            ArgFileState::FindNext if is_whitespace => ArgFileState::FindNext,
+
+            ArgFileState::SkipLeadingWhitespace(quote) if is_whitespace => {
+                ArgFileState::SkipLeadingWhitespace(quote)
+            }
+
+            ArgFileState::InComment if c == '\n' || c == '\r' => ArgFileState::FindNext,
+
+            ArgFileState::InComment => ArgFileState::InComment,
+
+            ArgFileState::InEscape(quote) if c == '\n' || c == '\r' => {
+                ArgFileState::SkipLeadingWhitespace(quote)
+            }
+
+            ArgFileState::InEscape(quote) => {
+                token.push(match c {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'f' => '\x0c',
+                    other => other,
+                });
+
+                ArgFileState::InQuote(quote)
+            }
+
+            ArgFileState::InQuote(_) if c == '\n' || c == '\r' => {
+                args.push(token.split_off(0));
+
+                ArgFileState::FindNext
+            }
+
+            ArgFileState::InQuote(quote) | ArgFileState::SkipLeadingWhitespace(quote) => {
+                if c == quote {
+                    ArgFileState::InToken
+                } else if c == '\\' {
+                    ArgFileState::InEscape(quote)
+                } else {
+                    token.push(c);
+
+                    ArgFileState::InQuote(quote)
+                }
+            }
+
+            ArgFileState::FindNext | ArgFileState::InToken => match c {
+                ' ' | '\t' | '\x0c' | '\n' | '\r' => {
+                    args.push(token.split_off(0));
+
+                    ArgFileState::FindNext
+                }
+
+                '#' => {
+                    if let ArgFileState::InToken = state {
+                        args.push(token.split_off(0));
+                    }
+
+                    ArgFileState::InComment
+                }
+
+                '"' | '\'' => ArgFileState::InQuote(c),
+
+                other => {
+                    token.push(other);
+
+                    ArgFileState::InToken
+                }
+            },
+        };
+    }
+
+    match state {
+        ArgFileState::FindNext | ArgFileState::InComment => {}
+        _ => args.push(token),
+    }
+
+    args
+}
+
 #[test]
 fn test_parse_java_args_help() {
     assert_eq!(
@@ -269,6 +1157,13 @@ fn test_all() {
             "-d64",
             "-server",
             "-wait",
+            "-resubmit",
+            "--dry-run",
+            "-t",
+            "-e",
+            "DATABASE_URL=jdbc:h2:mem:",
+            "-e",
+            "HOME",
             "-host",
             "unix:///dev/null",
             "-cp",
@@ -278,19 +1173,43 @@ fn test_all() {
             "myargtwo",
         ]),
         JavaArgs {
+            add_exports: vec![],
+            add_modules: vec![],
+            add_opens: vec![],
+            agents: vec![],
+            assertions: vec![],
+            cache: true,
+            compress: true,
+            compression_level: None,
             cp: vec!["/lib".to_string(), "/usr/lib".to_string()],
+            dry_run: true,
+            env: vec![
+                ("DATABASE_URL".to_string(), Some("jdbc:h2:mem:".to_string())),
+                ("HOME".to_string(), None),
+            ],
+            env_allow: vec![],
+            env_deny: vec![],
             errors: vec![],
+            excluded_signals: vec![],
+            heartbeat_timeout_millis: 30000,
             mode: ExecutionMode::Class {
                 class: "com.hello.Example".to_string(),
                 args: vec!["myarg one".to_string(), "myargtwo".to_string()],
             },
+            module_path: vec![],
+            notices: vec![],
             props: vec![
                 ("key1".to_string(), "value1".to_string()),
                 ("key2".to_string(), "value2".to_string()),
             ],
             host: Host::Unix("/dev/null".to_string()),
+            resource_hints: ResourceHints::default(),
+            resubmit_attempts: DEFAULT_RESUBMIT_ATTEMPTS,
+            resubmit_backoff_millis: 1000,
+            tty: true,
             version: false,
             wait: true,
+            wait_interval_millis: 5000,
         }
     );
 }
@@ -300,7 +1219,10 @@ fn test_invalid_flags() {
     assert_eq!(
         parse_java_args(&["-hello-world", "com.hello.Example"]),
         JavaArgs {
-            errors: vec!["Unrecognized option: -hello-world".to_string()],
+            errors: vec![ParseError::UnrecognizedOption {
+                arg: "-hello-world".to_string(),
+                position: 0,
+            }],
             mode: ExecutionMode::Class {
                 class: "com.hello.Example".to_string(),
                 args: vec![],
@@ -309,3 +1231,527 @@ fn test_invalid_flags() {
         }
     );
 }
+
+#[test]
+fn test_parse_arg_file() {
+    assert_eq!(
+        parse_arg_file(
+            "-cp lib/one.jar # the class path\n  -Dgreeting='hello world'\r\n\"com.example.Main\"\t\"\""
+        ),
+        vec![
+            "-cp".to_string(),
+            "lib/one.jar".to_string(),
+            "-Dgreeting=hello world".to_string(),
+            "com.example.Main".to_string(),
+            "".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_arg_file_escapes() {
+    assert_eq!(
+        parse_arg_file(
+            "\"a\\tb\\\\c\\\"d\" e\\f \"lib/one.jar:\\\n    lib/two.jar\" 'x#y' \"unterminated\nnext"
+        ),
+        vec![
+            "a\tb\\c\"d".to_string(),
+            "e\\f".to_string(),
+            "lib/one.jar:lib/two.jar".to_string(),
+            "x#y".to_string(),
+            "unterminated".to_string(),
+            "next".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_arg_files() {
+    let path = std::env::temp_dir().join(format!("landlord-test-{}.args", std::process::id()));
+
+    fs::write(&path, "-cp /lib\ncom.hello.Example \"arg one\"").unwrap();
+
+    let arg_file = format!("@{}", path.display());
+
+    let parsed = parse_java_args(&[arg_file.as_str(), "@argtwo"]);
+
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        parsed,
+        JavaArgs {
+            cp: vec!["/lib".to_string()],
+            mode: ExecutionMode::Class {
+                class: "com.hello.Example".to_string(),
+                args: vec!["arg one".to_string(), "@argtwo".to_string()],
+            },
+            ..default()
+        }
+    );
+}
+
+#[test]
+fn test_arg_files_escaped_and_disabled() {
+    assert_eq!(
+        parse_java_args(&["@@Example"]).mode,
+        ExecutionMode::Class {
+            class: "@Example".to_string(),
+            args: vec![],
+        }
+    );
+
+    assert_eq!(
+        parse_java_args(&["--disable-@files", "@Example"]),
+        JavaArgs {
+            mode: ExecutionMode::Class {
+                class: "@Example".to_string(),
+                args: vec![],
+            },
+            ..default()
+        }
+    );
+
+    assert_eq!(
+        parse_java_args(&["@/non-existent/landlord.args"]).errors,
+        vec![ParseError::ArgFileNotFound {
+            path: "/non-existent/landlord.args".to_string(),
+            position: 0,
+        }]
+    );
+}
+
+#[test]
+fn test_env_file() {
+    let path = std::env::temp_dir().join(format!("landlord-test-{}.env", std::process::id()));
+
+    fs::write(&path, "# comment\nONE=1\n\n  TWO=a=b\r\nTHREE\n=bogus\n").unwrap();
+
+    let env_file = format!("--env-file={}", path.display());
+
+    let parsed = parse_java_args(&[env_file.as_str(), "-e", "ONE=2", "com.hello.Example"]);
+
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        parsed.env,
+        vec![
+            ("ONE".to_string(), Some("1".to_string())),
+            ("TWO".to_string(), Some("a=b".to_string())),
+            ("THREE".to_string(), None),
+            ("ONE".to_string(), Some("2".to_string())),
+        ]
+    );
+
+    assert_eq!(
+        parsed.errors,
+        vec![ParseError::InvalidEnvVar {
+            arg: "=bogus".to_string(),
+            position: 0,
+        }]
+    );
+
+    assert_eq!(
+        parse_java_args(&["--env-file", "/non-existent/landlord.env"]).errors,
+        vec![ParseError::ArgFileNotFound {
+            path: "/non-existent/landlord.env".to_string(),
+            position: 0,
+        }]
+    );
+
+    assert_eq!(
+        parse_java_args(&["-e"]).errors,
+        vec![ParseError::MissingValue {
+            option: "-e".to_string(),
+            position: 0,
+        }]
+    );
+}
+
+#[test]
+fn test_forwarded_env() {
+    let client_env = || {
+        vec![
+            ("APP_NAME".to_string(), "client".to_string()),
+            ("APP_SECRET".to_string(), "hunter2".to_string()),
+            ("HOME".to_string(), "/home/user".to_string()),
+            ("PATH".to_string(), "/bin".to_string()),
+        ]
+    };
+
+    let jargs = parse_java_args(&["-e", "HOME", "-e", "UNSET", "-e", "DEBUG=1", "Example"]);
+
+    assert_eq!(
+        forwarded_env(&jargs, client_env()),
+        vec![
+            ("DEBUG".to_string(), "1".to_string()),
+            ("HOME".to_string(), "/home/user".to_string()),
+        ]
+    );
+
+    let jargs = JavaArgs {
+        env: vec![
+            ("APP_NAME".to_string(), Some("cli".to_string())),
+            ("APP_SECRET".to_string(), None),
+        ],
+        env_allow: vec!["APP_*".to_string()],
+        env_deny: vec!["APP_SECRET".to_string()],
+        ..default()
+    };
+
+    assert_eq!(
+        forwarded_env(&jargs, client_env()),
+        vec![("APP_NAME".to_string(), "cli".to_string())]
+    );
+}
+
+#[test]
+fn test_host_props() {
+    let env = |name: &str| match name {
+        "LANG" => Some("en_US.UTF-8".to_string()),
+        "LC_MESSAGES" => Some("de_DE.UTF-8@euro".to_string()),
+        "TZ" => Some(":Europe/Berlin".to_string()),
+        "USER" => Some("env".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(
+        host_props(
+            Some("/home/user".to_string()),
+            1000,
+            Some("user".to_string()),
+            env
+        ),
+        vec![
+            ("landlord.client.cwd".to_string(), "/home/user".to_string()),
+            ("landlord.client.uid".to_string(), "1000".to_string()),
+            ("landlord.client.user".to_string(), "user".to_string()),
+            (
+                "landlord.client.timezone".to_string(),
+                "Europe/Berlin".to_string()
+            ),
+            (
+                "landlord.client.locale".to_string(),
+                "de_DE.UTF-8@euro".to_string()
+            ),
+        ]
+    );
+
+    let env = |name: &str| match name {
+        "LC_ALL" => Some("".to_string()),
+        "LANG" => Some("en_US.UTF-8".to_string()),
+        "TZ" => Some("".to_string()),
+        "USER" => Some("env".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(
+        host_props(None, 0, None, env),
+        vec![
+            ("landlord.client.uid".to_string(), "0".to_string()),
+            ("landlord.client.user".to_string(), "env".to_string()),
+            (
+                "landlord.client.locale".to_string(),
+                "en_US.UTF-8".to_string()
+            ),
+        ]
+    );
+
+    assert_eq!(
+        host_props(None, 0, None, |_| None),
+        vec![("landlord.client.uid".to_string(), "0".to_string())]
+    );
+}
+
+#[test]
+fn test_parse_java_args_env() {
+    let env = |name: &str| match name {
+        "CLASSPATH" => Some("/env/lib:/env/classes".to_string()),
+        "JAVA_TOOL_OPTIONS" => Some("-Xmx64m -Dkey1=tool -Dkey2=tool".to_string()),
+        "JDK_JAVA_OPTIONS" => Some("-Dkey2=jdk '-Dkey3=jdk opts'".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(
+        parse_java_args_env(&["-Dkey3=cli", "com.hello.Example"], env),
+        JavaArgs {
+            cp: vec!["/env/lib".to_string(), "/env/classes".to_string()],
+            mode: ExecutionMode::Class {
+                class: "com.hello.Example".to_string(),
+                args: vec![],
+            },
+            notices: vec![
+                "NOTE: Picked up JDK_JAVA_OPTIONS: -Dkey2=jdk '-Dkey3=jdk opts'".to_string(),
+                "Picked up JAVA_TOOL_OPTIONS: -Xmx64m -Dkey1=tool -Dkey2=tool".to_string(),
+            ],
+            props: vec![
+                ("key1".to_string(), "tool".to_string()),
+                ("key2".to_string(), "tool".to_string()),
+                ("key2".to_string(), "jdk".to_string()),
+                ("key3".to_string(), "jdk opts".to_string()),
+                ("key3".to_string(), "cli".to_string()),
+            ],
+            ..default()
+        }
+    );
+
+    assert_eq!(
+        parse_java_args_env(&["-cp", "/cli", "com.hello.Example"], env).cp,
+        vec!["/cli".to_string()]
+    );
+
+    assert_eq!(
+        parse_java_args_env(&["com.hello.Example"], |_| None),
+        parse_java_args(&["com.hello.Example"])
+    );
+}
+
+#[test]
+fn test_parse_java_args_env_disallowed() {
+    assert_eq!(
+        parse_java_args_env(&["com.hello.Example"], |name| match name {
+            "JDK_JAVA_OPTIONS" => Some("-cp /lib com.hello.Other -jar".to_string()),
+            _ => None,
+        }).errors,
+        vec![
+            ParseError::MainClassInEnv {
+                variable: "JDK_JAVA_OPTIONS".to_string(),
+            },
+            ParseError::NotAllowedInEnv {
+                option: "-jar".to_string(),
+                variable: "JDK_JAVA_OPTIONS".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_java_module() {
+    assert_eq!(
+        parse_java_args(&[
+            "--module-path",
+            "mods:lib",
+            "--add-modules=java.sql,java.xml",
+            "--add-modules",
+            "jdk.unsupported",
+            "--add-opens",
+            "java.base/java.lang=ALL-UNNAMED",
+            "--add-exports=java.base/sun.nio.ch=ALL-UNNAMED",
+            "-m",
+            "com.acme.svc/com.acme.Main",
+            "arg1",
+        ]),
+        JavaArgs {
+            add_exports: vec!["java.base/sun.nio.ch=ALL-UNNAMED".to_string()],
+            add_modules: vec![
+                "java.sql".to_string(),
+                "java.xml".to_string(),
+                "jdk.unsupported".to_string(),
+            ],
+            add_opens: vec!["java.base/java.lang=ALL-UNNAMED".to_string()],
+            mode: ExecutionMode::Module {
+                module: "com.acme.svc/com.acme.Main".to_string(),
+                args: vec!["arg1".to_string()],
+            },
+            module_path: vec!["mods".to_string(), "lib".to_string()],
+            ..default()
+        }
+    );
+
+    assert_eq!(
+        parse_java_args(&["-p", "mods", "--module", "com.acme.svc"]),
+        JavaArgs {
+            mode: ExecutionMode::Module {
+                module: "com.acme.svc".to_string(),
+                args: vec![],
+            },
+            module_path: vec!["mods".to_string()],
+            ..default()
+        }
+    );
+
+    assert_eq!(
+        parse_java_args(&["-p"]).errors,
+        vec![ParseError::MissingValue {
+            option: "-p".to_string(),
+            position: 0,
+        }]
+    );
+
+    assert_eq!(
+        parse_java_args(&["-m"]).errors,
+        vec![ParseError::MissingValue {
+            option: "-m".to_string(),
+            position: 0,
+        }]
+    );
+}
+
+#[test]
+fn test_parse_jvm_options() {
+    assert_eq!(
+        parse_java_args(&[
+            "-javaagent:/opt/agent.jar",
+            "-javaagent:lib/other.jar=opt1=a,opt2",
+            "-ea",
+            "-da:com.example...",
+            "-enableassertions:com.example.Main",
+            "-Xms64m",
+            "-Xmx1G",
+            "-Xss512k",
+            "-XX:+UseG1GC",
+            "-Xshare:off",
+            "-verbose:gc",
+            "-esa",
+            "-agentlib:jdwp=transport=dt_socket",
+            "com.hello.Example",
+        ]),
+        JavaArgs {
+            agents: vec![
+                ("/opt/agent.jar".to_string(), None),
+                ("lib/other.jar".to_string(), Some("opt1=a,opt2".to_string())),
+            ],
+            assertions: vec![
+                "-ea".to_string(),
+                "-da:com.example...".to_string(),
+                "-enableassertions:com.example.Main".to_string(),
+            ],
+            mode: ExecutionMode::Class {
+                class: "com.hello.Example".to_string(),
+                args: vec![],
+            },
+            notices: vec![
+                "landlord: ignoring -XX:+UseG1GC as it cannot be applied to a shared JVM"
+                    .to_string(),
+                "landlord: ignoring -Xshare:off as it cannot be applied to a shared JVM"
+                    .to_string(),
+                "landlord: ignoring -verbose:gc as it cannot be applied to a shared JVM"
+                    .to_string(),
+                "landlord: ignoring -esa as it cannot be applied to a shared JVM".to_string(),
+                "landlord: ignoring -agentlib:jdwp=transport=dt_socket as it cannot be applied to a shared JVM"
+                    .to_string(),
+            ],
+            resource_hints: ResourceHints {
+                initial_heap_size: Some(64 * 1024 * 1024),
+                max_heap_size: Some(1024 * 1024 * 1024),
+                thread_stack_size: Some(512 * 1024),
+            },
+            ..default()
+        }
+    );
+
+    assert_eq!(
+        parse_java_args(&["-Xmx1q", "-javaagent:", "-eax"]).errors,
+        vec![
+            ParseError::InvalidSize {
+                arg: "-Xmx1q".to_string(),
+                position: 0,
+            },
+            ParseError::MissingValue {
+                option: "-javaagent:".to_string(),
+                position: 1,
+            },
+            ParseError::UnrecognizedOption {
+                arg: "-eax".to_string(),
+                position: 2,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    let parsed = parse_java_args(&[
+        "-Dflag", "-cp", "/lib", "-D=value", "-host", "ftp://x", "-cp",
+    ]);
+
+    assert_eq!(parsed.props, vec![("flag".to_string(), "".to_string())]);
+    assert_eq!(
+        parsed.errors,
+        vec![
+            ParseError::InvalidProperty {
+                arg: "-D=value".to_string(),
+                position: 3,
+            },
+            ParseError::InvalidHost {
+                option: "-host".to_string(),
+                position: 4,
+            },
+            ParseError::MissingValue {
+                option: "-cp".to_string(),
+                position: 6,
+            },
+        ]
+    );
+
+    let messages: Vec<String> = parsed.errors.iter().map(|e| e.to_string()).collect();
+
+    assert_eq!(
+        messages,
+        vec![
+            "Invalid system property: -D=value".to_string(),
+            "Error: -host must begin with \"tcp://\" or \"unix://\"".to_string(),
+            "Error: -cp requires class path specification".to_string(),
+        ]
+    );
+
+    assert!(parsed.errors[0].is_jvm_error());
+    assert!(parsed.errors[2].shows_usage());
+    assert_eq!(parsed.errors[2].position(), Some(6));
+    assert_eq!(parsed.errors[2].exit_code(), 1);
+}
+
+#[test]
+fn test_resubmit() {
+    assert_eq!(parse_java_args(&["Main"]).resubmit_attempts, 0);
+    assert_eq!(
+        parse_java_args(&["-resubmit", "Main"]).resubmit_attempts,
+        DEFAULT_RESUBMIT_ATTEMPTS
+    );
+    assert_eq!(
+        parse_java_args(&["-resubmit", "-resubmit:10", "Main"]).resubmit_attempts,
+        10
+    );
+
+    let mut base = JavaArgs::default();
+    base.resubmit_attempts = 3;
+
+    let parsed = parse_java_args_with(base, &["-resubmit:0", "-resubmit:x", "Main"], |_| None);
+
+    assert_eq!(parsed.resubmit_attempts, 0);
+    assert_eq!(
+        parsed.errors,
+        vec![ParseError::InvalidResubmit {
+            arg: "-resubmit:x".to_string(),
+            position: 1,
+        }]
+    );
+    assert_eq!(
+        parsed.errors[0].to_string(),
+        "Error: invalid number of resubmit attempts: -resubmit:x"
+    );
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1024"), Some(1024));
+    assert_eq!(parse_size("2k"), Some(2048));
+    assert_eq!(parse_size("3M"), Some(3 * 1024 * 1024));
+    assert_eq!(parse_size("1t"), Some(1024 * 1024 * 1024 * 1024));
+    assert_eq!(parse_size(""), None);
+    assert_eq!(parse_size("m"), None);
+    assert_eq!(parse_size("1.5g"), None);
+}
+
+#[test]
+fn test_resource_hint_flags() {
+    let hints = ResourceHints {
+        initial_heap_size: Some(64 * 1024 * 1024),
+        max_heap_size: Some(2 * 1024 * 1024 * 1024),
+        thread_stack_size: Some(1000),
+    };
+
+    assert_eq!(hints.flags(), vec!["-Xms64m", "-Xmx2g", "-Xss1000"]);
+    assert_eq!(format_size(0), "0");
+    assert_eq!(format_size(3 << 10), "3k");
+    assert_eq!(ResourceHints::default().flags(), Vec::<String>::new());
+}
@@ -1,36 +1,138 @@
 extern crate landlord;
+extern crate libc;
 
 use landlord::args::*;
 use landlord::bindings::*;
+use landlord::cache::*;
+use landlord::classpath::*;
+use landlord::config::*;
+use landlord::proto::{daemon_error, heartbeat_timed_out, DescriptorFormat, Launch, Main};
+use landlord::terminal::*;
+use std::cell::RefCell;
+use std::ffi::CStr;
 use std::io::prelude::*;
 use std::net::TcpStream;
 use std::os::unix::net::UnixStream;
-use std::sync::mpsc::*;
-use std::{env, io, process, str, time};
+use std::{env, io, mem, process, ptr, str, time};
+
+// the epoll driver is preferred when both are enabled, as it's only ever opted into
+
+#[cfg(all(feature = "async", not(feature = "epoll")))]
+use landlord::client::{run, AsyncStream as ClientStream};
+#[cfg(feature = "epoll")]
+use landlord::epoll::{run, EventedStream as ClientStream};
+
+#[cfg(not(any(feature = "async", feature = "epoll")))]
+compile_error!("landlord requires either the \"async\" or \"epoll\" feature");
 
 const CARGO_VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const RETRY_DELAY_MILLIS: u64 = 5000;
 const RELEASE_VERSION: Option<&'static str> = option_env!("RELEASE_VERSION");
 
 const USAGE: &'static str = "Usage: landlord [-options] class [args...]
            (to execute a class)
    or  landlord [-options] -jar jarfile [args...]
            (to execute a jar file)
+   or  landlord [-options] -m <module>[/<mainclass>] [args...]
+       landlord [-options] --module <module>[/<mainclass>] [args...]
+           (to execute the main class in a module)
+   or  landlord cache prune [--all]
+           (to remove cached tar segments that have gone unused for 7 days, or all of them)
 where options include:
     -cp <class search path of directories and zip/jar files> -classpath <class search path of directories and zip/jar files>
                   A : separated list of directories, JAR archives,
                   and ZIP archives to search for class files.
+    -p <module path> --module-path <module path>
+                  A : separated list of directories, each directory
+                  is a directory of modules.
+    --add-modules <module name>[,<module name>...]
+                  root modules to resolve in addition to the initial module.
+    --add-opens <module>/<package>=<target-module>(,<target-module>)*
+                  updates <module> to open <package> to <target-module>,
+                  regardless of module declaration.
+    --add-exports <module>/<package>=<target-module>(,<target-module>)*
+                  updates <module> to export <package> to <target-module>,
+                  regardless of module declaration.
+                  Module options require a landlordd that supports the module
+                  system, which is only available as of Java 9.
     -D<name>=<value>
                   set a system property
+    -ea[:<packagename>...|:<classname>]
+    -enableassertions[:<packagename>...|:<classname>]
+                  enable assertions with specified granularity
+    -da[:<packagename>...|:<classname>]
+    -disableassertions[:<packagename>...|:<classname>]
+                  disable assertions with specified granularity
+    -javaagent:<jarpath>[=<options>]
+                  load Java programming language agent, see java.lang.instrument
+    -Xms<size> -Xmx<size> -Xss<size>
+                  heap and thread stack sizes, provided to landlordd as hints.
+                  Other -X, -XX, -verbose and native agent options are ignored
+                  as they cannot be applied to a shared JVM
     -version      print product version and exit
     -showversion  print product version and continue
     -? -help      print this help message
+    @<filepaths>  one or more argument files containing options
+    --disable-@files
+                  prevent further argument file expansion
     -host | -H    host to connect to. available schemes: \"unix\", \"tcp\"
-    -wait         if provided, wait until landlordd is ready before connecting";
+    -wait         if provided, wait until landlordd is ready before connecting
+    -resubmit[:<attempts>]
+                  should the connection to landlordd be lost, wait until it's ready
+                  and resubmit the program, up to 5 times unless specified
+    --dry-run     print what would be sent to landlordd rather than connecting
+    -t            run an interactive program upon this terminal, which is put into
+                  raw mode for the duration. System.console() remains null as
+                  landlordd's processes share its JVM
+    -e <name>[=<value>]
+                  set an environment variable of the program, copying the value
+                  of this environment if none is given
+    --env-file <filepath>
+                  set the environment variables listed in a file, one per line
+
+landlord also reads named profiles from /etc/landlord/config.toml and
+$XDG_CONFIG_HOME/landlord/config.toml. The profile is selected via
+LANDLORD_PROFILE, and LANDLORD_HOST overrides its host. Environment variables
+are only forwarded if given via -e or --env-file, or allowed by the profile's
+env-allow (and not its env-deny) patterns.
+
+landlord exits with the status of the program, or is terminated by the same
+signal as it was. It exits with 69 if landlordd is unavailable or goes away, and
+76 if landlordd responds in a way that isn't understood or doesn't support what
+was asked of it, and 75 if landlordd stops responding (as per the profile's
+heartbeat-timeout-millis, 30 seconds by default). With -resubmit, a program is
+instead resubmitted when landlordd goes away, or stops responding before it has
+launched the program, after waiting for the profile's resubmit-backoff-millis
+(1 second by default), which doubles upon each attempt.";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let parsed = parse_java_args(&args[1..]);
+
+    if args.get(1).map(|a| a.as_str()) == Some("cache") {
+        process::exit(cache_command(&args[2..]));
+    }
+
+    let env_var = |name: &str| env::var(name).ok();
+    let base = load_config(env_var)
+        .and_then(|config| config.select_profile(env_var))
+        .map(|profile| {
+            let mut base = JavaArgs::default();
+
+            profile.apply(&mut base);
+
+            base
+        });
+
+    let parsed = match base {
+        Ok(base) => parse_java_args_with(base, &args[1..], env_var),
+
+        Err(e) => {
+            eprintln!("landlord: {}", e);
+
+            process::exit(1);
+        }
+    };
+
+    parsed.notices.iter().for_each(|n| eprintln!("{}", n));
 
     if parsed.version {
         let version = RELEASE_VERSION.unwrap_or_else(|| CARGO_VERSION);
@@ -43,29 +145,14 @@ fn main() {
             ExecutionMode::Class {
                 ref class,
                 ref args,
-            } => match parsed.host {
-                Host::Unix(path) => {
-                    handle_execute_class(
-                        parsed.cp.as_slice(),
-                        class,
-                        args,
-                        parsed.props.as_slice(),
-                        parsed.wait,
-                        || UnixStream::connect(&path),
-                    );
-                }
+            } => {
+                let cp = expand_class_path_wildcards(&parsed.cp);
 
-                Host::Tcp(address) => {
-                    handle_execute_class(
-                        parsed.cp.as_slice(),
-                        class,
-                        args,
-                        parsed.props.as_slice(),
-                        parsed.wait,
-                        || TcpStream::connect(&address),
-                    );
-                }
-            },
+                execute(
+                    &parsed,
+                    &launch(&parsed, cp, Main::Class(class.clone()), args),
+                );
+            }
 
             ExecutionMode::Exit { code } => {
                 process::exit(code);
@@ -77,75 +164,372 @@ fn main() {
                 process::exit(code);
             }
 
-            ExecutionMode::JarFile {
-                file: _file,
-                args: _args,
+            ExecutionMode::JarFile { ref file, ref args } => match resolve_jar(file) {
+                Ok((cp, class)) => {
+                    execute(&parsed, &launch(&parsed, cp, Main::Class(class), args));
+                }
+
+                Err(e) => {
+                    eprintln!("{}", e);
+
+                    process::exit(1);
+                }
+            },
+
+            ExecutionMode::Module {
+                ref module,
+                ref args,
             } => {
-                eprintln!("landlord: `-jar` currently unsupported");
+                let cp = expand_class_path_wildcards(&parsed.cp);
 
-                process::exit(1);
+                execute(
+                    &parsed,
+                    &launch(&parsed, cp, Main::Module(module.clone()), args),
+                );
             }
         }
     } else {
+        parsed.errors.iter().for_each(|e| eprintln!("{}", e));
+
+        if parsed.errors.iter().any(|e| e.is_jvm_error()) {
+            eprintln!("Error: Could not create the Java Virtual Machine.");
+            eprintln!("Error: A fatal exception has occurred. Program will exit.");
+        }
+
+        if parsed.errors.iter().any(|e| e.shows_usage()) {
+            eprintln!("{}", USAGE);
+        }
+
+        process::exit(parsed.errors[0].exit_code());
+    }
+}
+
+/// Runs `landlord cache`, returning the status to exit with
+fn cache_command(args: &[String]) -> i32 {
+    let max_age = match args
+        .iter()
+        .map(|a| a.as_str())
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        ["prune"] => Some(time::Duration::from_secs(CACHE_MAX_AGE_DAYS * 24 * 60 * 60)),
+        ["prune", "--all"] => None,
+
+        _ => {
+            eprintln!("Usage: landlord cache prune [--all]");
+
+            return 1;
+        }
+    };
+
+    let dir = match cache_dir(|name| env::var(name).ok()) {
+        Some(dir) => dir,
+
+        None => {
+            eprintln!(
+                "landlord: unable to locate the cache, as neither XDG_CACHE_HOME nor HOME is set"
+            );
+
+            return 1;
+        }
+    };
+
+    match Cache::new(dir.clone()).prune(max_age) {
+        Ok((files, bytes)) => {
+            eprintln!(
+                "landlord: removed {} files ({} bytes) from {}",
+                files,
+                bytes,
+                dir.display()
+            );
+
+            0
+        }
+
+        Err(e) => {
+            eprintln!("landlord: failed to prune {}: {}", dir.display(), e);
+
+            1
+        }
+    }
+}
+
+/// Creates the launch descriptor for the parsed arguments, given the resolved class path
+/// and main class or module.
+fn launch(parsed: &JavaArgs, class_path: Vec<String>, main: Main, args: &[String]) -> Launch {
+    let mut options = vec![];
+
+    if !parsed.add_modules.is_empty() {
+        options.push(format!("--add-modules={}", parsed.add_modules.join(",")));
+    }
+
+    options.extend(
         parsed
-            .errors
+            .add_opens
             .iter()
-            .for_each(|e| println!("landlord: {}", e));
+            .map(|o| format!("--add-opens={}", o)),
+    );
+    options.extend(
+        parsed
+            .add_exports
+            .iter()
+            .map(|e| format!("--add-exports={}", e)),
+    );
+
+    let uid = unsafe { libc::getuid() };
+    let cwd = env::current_dir()
+        .ok()
+        .and_then(|d| d.to_str().map(|d| d.to_string()));
+
+    let mut props = host_props(cwd, uid, username(uid), |name| env::var(name).ok());
 
-        process::exit(1);
+    props.extend(parsed.props.iter().cloned());
+
+    Launch {
+        agents: parsed.agents.clone(),
+        args: args.to_vec(),
+        assertions: parsed.assertions.clone(),
+        class_path,
+        env: forwarded_env(parsed, client_env()),
+        hints: parsed.resource_hints.flags(),
+        main,
+        module_path: parsed.module_path.clone(),
+        options,
+        props,
     }
 }
 
-fn handle_execute_class<IO, NewS, S>(
-    cp: &[S],
-    class: &S,
-    args: &[S],
-    props: &[(S, S)],
-    wait: bool,
-    mut new_stream: NewS,
+/// Returns the environment of this process, skipping any variables that aren't UTF-8 as they
+/// can't be forwarded
+fn client_env() -> impl Iterator<Item = (String, String)> {
+    env::vars_os().filter_map(
+        |(name, value)| match (name.into_string(), value.into_string()) {
+            (Ok(name), Ok(value)) => Some((name, value)),
+            _ => None,
+        },
+    )
+}
+
+/// Returns the name of the user with the provided id, if it can be looked up
+fn username(uid: libc::uid_t) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+
+    let status =
+        unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+
+    if status == 0 && !result.is_null() {
+        unsafe { CStr::from_ptr(passwd.pw_name) }
+            .to_str()
+            .ok()
+            .map(|n| n.to_string())
+    } else {
+        None
+    }
+}
+
+fn execute(parsed: &JavaArgs, launch: &Launch) {
+    let cache = if parsed.cache {
+        cache_dir(|name| env::var(name).ok()).map(Cache::new)
+    } else {
+        None
+    };
+
+    if parsed.dry_run {
+        let stdout = io::stdout();
+
+        if let Err(e) = dry_run(
+            launch,
+            DescriptorFormat::LengthPrefixed,
+            cache.as_ref(),
+            &mut stdout.lock(),
+        ) {
+            eprintln!("landlord: {}", e);
+
+            process::exit(1);
+        }
+
+        process::exit(0);
+    }
+
+    match parsed.host {
+        Host::Unix(ref path) => {
+            handle_execute(parsed, launch, cache.as_ref(), || UnixStream::connect(path));
+        }
+
+        Host::Tcp(ref address) => {
+            handle_execute(parsed, launch, cache.as_ref(), || {
+                TcpStream::connect(address)
+            });
+        }
+    }
+}
+
+fn handle_execute<IO, NewS>(
+    parsed: &JavaArgs,
+    launch: &Launch,
+    cache: Option<&Cache>,
+    mut open_stream: NewS,
 ) -> ()
 where
-    IO: IOStream + Read + Send + Write + 'static,
+    IO: ClientStream + Read + Write,
     NewS: FnMut() -> io::Result<IO>,
-    S: AsRef<str>,
 {
-    if wait {
-        wait_until_ready(
-            &mut new_stream,
-            time::Duration::from_millis(RETRY_DELAY_MILLIS),
-        )
+    let console = if parsed.tty {
+        if !stdin_is_terminal() {
+            eprintln!("landlord: -t requires stdin to be a terminal");
+
+            process::exit(1);
+        }
+
+        Some(window_size().unwrap_or((24, 80)))
+    } else {
+        None
+    };
+
+    let heartbeat_timeout = if parsed.heartbeat_timeout_millis > 0 {
+        Some(time::Duration::from_millis(parsed.heartbeat_timeout_millis))
+    } else {
+        None
+    };
+
+    // landlordd is expected to respond to what precedes the class path within the heartbeat
+    // timeout. While it unpacks the class path it sends nothing, so there's no timeout until
+    // the process has launched, after which the drivers read without blocking and apply it.
+
+    let new_stream = RefCell::new(|| {
+        open_stream()
+            .and_then(|stream| {
+                if let Some(timeout) = heartbeat_timeout {
+                    stream.set_keepalive(timeout)?;
+                    stream.set_read_timeout(Some(timeout))?;
+                }
+
+                Ok(stream)
+            })
+            .map_err(daemon_error)
+    });
+
+    let wait_interval = time::Duration::from_millis(parsed.wait_interval_millis);
+
+    if parsed.wait {
+        wait_until_ready(&mut *new_stream.borrow_mut(), wait_interval, None);
     }
 
-    match new_stream() {
-        Err(ref mut e) => {
+    let submit = || {
+        let mut new_stream = new_stream.borrow_mut();
+
+        let (mut stream, protocol) = connect(&mut *new_stream).map_err(|e| {
+            let e = read_timed_out(e, heartbeat_timeout);
+
             eprintln!("landlord: failed to connect: {:?}", e);
 
-            process::exit(1);
+            (e, false)
+        })?;
+
+        let (launch, notices) = launch.supported_by(&protocol);
+
+        for notice in notices {
+            eprintln!("{}", notice);
         }
 
-        Ok(mut stream) => {
-            let (tx, rx) = channel();
+        let compression = if parsed.compress {
+            protocol
+                .compression()
+                .map(|c| (c, c.level(parsed.compression_level)))
+        } else {
+            None
+        };
 
-            let result = install_fs_and_start(cp, props, class, args, &mut stream)
-                .and_then(|pid| stream.try_clone().map(|stream_writer| (pid, stream_writer)))
-                .and_then(|(pid, mut stream_writer)| {
-                    spawn_and_handle_signals(tx.clone());
-                    spawn_and_handle_stdin(tx.clone());
-                    spawn_and_handle_stream_read(stream, tx.clone());
+        // errors are reported along with whether the process had launched, i.e. whether
+        // landlordd may still be running it
 
-                    handle_events(pid, &mut stream_writer, rx, new_stream)
-                });
+        let failed = |e, launched| {
+            let e = read_timed_out(e, heartbeat_timeout);
 
-            let code = match result {
-                Ok(c) => c,
+            eprintln!("landlord: {:?}", e);
 
-                Err(e) => {
-                    eprintln!("landlord: {:?}", e);
-                    1
-                }
-            };
+            (e, launched)
+        };
+
+        let pid =
+            install_fs_and_start(&launch, &protocol, console, compression, cache, &mut stream)
+                .map_err(|e| failed(e, false))?;
+
+        // the terminal is restored before exiting, as process::exit skips destructors
+
+        let _raw_mode = match console {
+            Some(_) => Some(RawMode::enable().map_err(|e| failed(e, true))?),
+            None => None,
+        };
+
+        run(
+            pid,
+            stream,
+            &protocol,
+            &parsed.excluded_signals,
+            heartbeat_timeout,
+            &mut *new_stream,
+        )
+        .map_err(|e| failed(e, true))
+    };
 
-            process::exit(code);
+    // landlordd is given until the next attempt would be due to become ready, so that one that
+    // doesn't come back still uses up the attempts rather than being waited upon forever
+
+    let ready = |window: time::Duration| {
+        let ready = wait_until_ready(&mut *new_stream.borrow_mut(), wait_interval, Some(window));
+
+        if !ready {
+            eprintln!(
+                "landlord: landlordd wasn't ready within {}ms",
+                window.as_secs() * 1000 + u64::from(window.subsec_millis())
+            );
         }
+
+        ready
+    };
+
+    let notify = |attempt, delay: time::Duration| {
+        eprintln!(
+            "landlord: resubmitting in {}ms, once landlordd is ready (attempt {} of {})",
+            delay.as_secs() * 1000 + u64::from(delay.subsec_millis()),
+            attempt,
+            parsed.resubmit_attempts
+        );
+    };
+
+    let result = resubmit(
+        parsed.resubmit_attempts,
+        parsed.resubmit_backoff_millis,
+        submit,
+        ready,
+        notify,
+    );
+
+    match result {
+        Ok(status) => {
+            if let Some(description) = status.describe() {
+                eprintln!("{}", description);
+            }
+
+            if let Some(signal) = status.signal() {
+                exit_by_signal(signal);
+            }
+
+            process::exit(status.code);
+        }
+
+        Err(e) => process::exit(error_exit_code(&e)),
+    }
+}
+
+/// Converts an error from a read that timed out, which blocking reads report as `WouldBlock`,
+/// to the error for a landlordd that has stopped responding
+fn read_timed_out(error: io::Error, timeout: Option<time::Duration>) -> io::Error {
+    match timeout {
+        Some(timeout) if error.kind() == io::ErrorKind::WouldBlock => heartbeat_timed_out(timeout),
+        _ => error,
     }
 }
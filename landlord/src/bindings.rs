@@ -1,22 +1,164 @@
-use chan_signal::{notify, Signal};
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::BytesMut;
+use cache::Cache;
+use digest::*;
+use flate2;
+use flate2::write::GzEncoder;
 use libc;
 use proto::*;
 use std::io::prelude::*;
 use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
-use std::sync::mpsc::*;
-use std::{fs, io, marker, net, path, process, thread, time};
-use tar::Builder;
+use std::{fs, io, marker, mem, net, path, process, ptr, thread, time};
+use tar::{Builder, Header};
+#[cfg(feature = "zstd-compression")]
+use zstd;
+
+/// The signals that are forwarded to the process. Those that cannot be caught, or that
+/// indicate a fault within landlord itself, keep their default behavior.
+pub const FORWARDED_SIGNALS: [libc::c_int; 24] = [
+    libc::SIGABRT,
+    libc::SIGALRM,
+    libc::SIGBUS,
+    libc::SIGCHLD,
+    libc::SIGCONT,
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGIO,
+    libc::SIGPIPE,
+    libc::SIGPROF,
+    libc::SIGQUIT,
+    libc::SIGSYS,
+    libc::SIGTERM,
+    libc::SIGTRAP,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+    libc::SIGURG,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+    libc::SIGVTALRM,
+    libc::SIGWINCH,
+    libc::SIGXCPU,
+    libc::SIGXFSZ,
+];
+
+/// The exit status when landlordd cannot be reached, or goes away, i.e. `EX_UNAVAILABLE` as
+/// defined in BSD sysexits.h
+pub const EXIT_UNAVAILABLE: i32 = 69;
+
+/// The exit status when landlordd responds in a way that isn't understood, or doesn't
+/// support what was asked of it, i.e. `EX_PROTOCOL` as defined in BSD sysexits.h
+pub const EXIT_PROTOCOL: i32 = 76;
+
+/// The exit status when landlordd has stopped responding, i.e. `EX_TEMPFAIL` as defined in
+/// BSD sysexits.h
+pub const EXIT_TIMED_OUT: i32 = 75;
+
+/// Returns the exit status for an error. Those in communicating with landlordd (see
+/// `daemon_error`) are classified by their kind, and any other exits with 1.
+pub fn error_exit_code(error: &io::Error) -> i32 {
+    if !is_daemon_error(error) {
+        return 1;
+    }
+
+    match error.kind() {
+        io::ErrorKind::AddrNotAvailable
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::NotFound
+        | io::ErrorKind::PermissionDenied
+        | io::ErrorKind::UnexpectedEof => EXIT_UNAVAILABLE,
+
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => EXIT_TIMED_OUT,
+
+        _ => EXIT_PROTOCOL,
+    }
+}
+
+/// A stream to landlordd, whose errors are marked as being in communicating with it. Those
+/// that say to try again are left as they are.
+pub struct DaemonIo<S>(pub S);
+
+impl<S> DaemonIo<S> {
+    fn error(error: io::Error) -> io::Error {
+        match error.kind() {
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => error,
+            _ => daemon_error(error),
+        }
+    }
+}
+
+impl<S: Read> Read for DaemonIo<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(DaemonIo::<S>::error)
+    }
+}
+
+impl<S: Write> Write for DaemonIo<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(DaemonIo::<S>::error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(DaemonIo::<S>::error)
+    }
+}
+
+/// Terminates landlord by `signal` as the process was, so that its parent sees the same,
+/// e.g. a shell reports 128 + `signal` and stops a script upon SIGINT. Its disposition is
+/// reset and it's unblocked beforehand, as landlord would otherwise forward it. Should
+/// landlord survive, e.g. as the signal doesn't terminate by default, it exits with
+/// 128 + `signal`.
+pub fn exit_by_signal(signal: i32) -> ! {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::signal(signal, libc::SIG_DFL);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+        libc::raise(signal);
+    }
+
+    process::exit(128 + signal)
+}
+
+/// The longest that's waited before resubmitting a process, however many times it has been
+pub const MAX_RESUBMIT_BACKOFF_MILLIS: u64 = 60000;
+
+/// Returns how long to wait before resubmitting a process for the `attempt`th time (from 1),
+/// i.e. `backoff_millis` doubled for each prior attempt, up to `MAX_RESUBMIT_BACKOFF_MILLIS`
+pub fn resubmit_delay(backoff_millis: u64, attempt: u32) -> time::Duration {
+    let factor = 1u64 << attempt.saturating_sub(1).min(16);
+
+    time::Duration::from_millis(
+        backoff_millis
+            .saturating_mul(factor)
+            .min(MAX_RESUBMIT_BACKOFF_MILLIS),
+    )
+}
 
 /// uses new_stream to open a connection to
 /// landlordd. if it fails in an unexpected manner,
 /// i.e. landlordd isn't ready yet, it retries
-/// after sleeping for some time.
-pub fn wait_until_ready<NewS, IO>(new_stream: &mut NewS, sleep_time: time::Duration) -> ()
+/// after sleeping for some time. gives up once
+/// `timeout` has passed, if provided, returning
+/// whether landlordd became ready.
+pub fn wait_until_ready<NewS, IO>(
+    new_stream: &mut NewS,
+    sleep_time: time::Duration,
+    timeout: Option<time::Duration>,
+) -> bool
 where
     NewS: FnMut() -> io::Result<IO>,
     IO: IOStream + Read + Write,
 {
+    let deadline = timeout.map(|t| time::Instant::now() + t);
+
     loop {
         match new_stream() {
             Err(_) => {}
@@ -26,284 +168,493 @@ where
                 // it will respond with three question marks (ASCII 63)
                 // otherwise we'll keep retrying
 
-                let question_mark: u8 = 63;
+                let mut probe = BytesMut::new();
 
-                let result = s.write_all(&[question_mark])
+                let result = encode_frame(Frame::Probe, &mut probe)
+                    .and_then(|_| s.write_all(&probe))
                     .and_then(|_| s.flush())
                     .and_then(|_| s.shutdown(net::Shutdown::Write));
 
                 if result.is_ok() {
-                    if let Some(bs) = read_bytes(&mut s, 3).ok() {
-                        if bs.len() == 3
-                            && bs[0] == question_mark
-                            && bs[1] == question_mark
-                            && bs[2] == question_mark
-                        {
-                            break;
-                        }
+                    let reply = read_frame(&mut s, &mut ClientCodec::new(), &mut BytesMut::new());
+
+                    if let Ok(Some(Frame::Unrecognized)) = reply {
+                        return true;
                     }
                 }
             }
         }
 
+        let sleep_time = match deadline {
+            Some(deadline) => {
+                let now = time::Instant::now();
+
+                if now >= deadline {
+                    return false;
+                }
+
+                sleep_time.min(deadline - now)
+            }
+
+            None => sleep_time,
+        };
+
         thread::sleep(sleep_time);
     }
 }
 
-/// Binds everything together and ensures that events received from a given `reader` will
-/// be handled accordingly.
-pub fn handle_events<NewS, IO>(
-    pid: i32,
-    stream: &mut IO,
-    reader: Receiver<Input>,
-    mut new_stream: NewS,
-) -> io::Result<i32>
+/// Submits a process via `submit`, resubmitting it up to `attempts` times should its connection
+/// to landlordd be lost, i.e. when `error_exit_code` is `EXIT_UNAVAILABLE`, or `EXIT_TIMED_OUT`
+/// before the process launched. `submit` fails with whether it had, i.e. whether its pid was
+/// received, as a landlordd that stops responding after launching a process may well still be
+/// running it, and resubmitting it would run it twice. Before each attempt, `notify` is called with its number and delay (see `resubmit_delay`),
+/// and once that has passed `ready` is given until the next delay for landlordd to become
+/// ready. An attempt for which it doesn't fails as landlordd being unavailable.
+///
+/// Other errors aren't resubmitted, as a landlordd that didn't understand the process won't
+/// the next time either, nor will a class path entry that couldn't be read have appeared.
+pub fn resubmit<T, Submit, Ready, Notify>(
+    attempts: u32,
+    backoff_millis: u64,
+    mut submit: Submit,
+    mut ready: Ready,
+    mut notify: Notify,
+) -> io::Result<T>
 where
-    NewS: FnMut() -> io::Result<IO>,
-    IO: IOStream + Read + Write,
+    Submit: FnMut() -> Result<T, (io::Error, bool)>,
+    Ready: FnMut(time::Duration) -> bool,
+    Notify: FnMut(u32, time::Duration),
 {
-    let mut stdout = io::stdout();
-    let mut stderr = io::stderr();
+    let mut result = submit();
+    let mut attempt = 0;
 
-    let handler_reader = || {
-        reader
-            .recv()
-            .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
-    };
-    let handler_writer = |bs: Vec<u8>| {
-        if bs.is_empty() {
-            stream.shutdown(net::Shutdown::Write)
-        } else {
-            stream.write_all(&bs)
+    loop {
+        let lost = match result {
+            Ok(_) => false,
+
+            Err((ref e, launched)) => {
+                let code = error_exit_code(e);
+
+                code == EXIT_UNAVAILABLE || (code == EXIT_TIMED_OUT && !launched)
+            }
+        };
+
+        if !lost || attempt >= attempts {
+            return result.map_err(|(e, _)| e);
         }
-    };
-    let session_writer = |bs: Vec<u8>| {
-        new_stream().and_then(|ref mut s| {
-            s.write_all(&bs)
-                .and_then(|_| s.flush())
-                .and_then(|_| s.shutdown(net::Shutdown::Write))
-        })
-    };
-    let std_out = |bs: Vec<u8>| stdout.write_all(&bs);
-    let std_err = |bs: Vec<u8>| stderr.write_all(&bs);
-
-    input_handler(
-        pid,
-        handler_reader,
-        handler_writer,
-        session_writer,
-        std_out,
-        std_err,
-    )
-}
 
-/// Signals to the OS which signals we are interested in, and then
-/// spawns a thread to wait for them and forward them to the
-/// provided `sender`.
-pub fn spawn_and_handle_signals(sender: Sender<Input>) {
-    let all_signals = [
-        Signal::ABRT,
-        Signal::ALRM,
-        Signal::BUS,
-        Signal::CHLD,
-        Signal::CONT,
-        Signal::FPE,
-        Signal::HUP,
-        Signal::ILL,
-        Signal::INT,
-        Signal::IO,
-        Signal::KILL,
-        Signal::PIPE,
-        Signal::PROF,
-        Signal::QUIT,
-        Signal::SEGV,
-        Signal::STOP,
-        Signal::SYS,
-        Signal::TERM,
-        Signal::TRAP,
-        Signal::TSTP,
-        Signal::TTIN,
-        Signal::TTOU,
-        Signal::URG,
-        Signal::USR1,
-        Signal::USR2,
-        Signal::VTALRM,
-        Signal::WINCH,
-        Signal::XCPU,
-        Signal::XFSZ,
-    ];
+        attempt += 1;
 
-    // chan_signal doesn't have a public function for converting a signal to its integer code
-    // so we have to do that ourselves..
-
-    let as_sig = |s: &Signal| match *s {
-        Signal::HUP => libc::SIGHUP,
-        Signal::INT => libc::SIGINT,
-        Signal::QUIT => libc::SIGQUIT,
-        Signal::ILL => libc::SIGILL,
-        Signal::ABRT => libc::SIGABRT,
-        Signal::FPE => libc::SIGFPE,
-        Signal::KILL => libc::SIGKILL,
-        Signal::SEGV => libc::SIGSEGV,
-        Signal::PIPE => libc::SIGPIPE,
-        Signal::ALRM => libc::SIGALRM,
-        Signal::TERM => libc::SIGTERM,
-        Signal::USR1 => libc::SIGUSR1,
-        Signal::USR2 => libc::SIGUSR2,
-        Signal::CHLD => libc::SIGCHLD,
-        Signal::CONT => libc::SIGCONT,
-        Signal::STOP => libc::SIGSTOP,
-        Signal::TSTP => libc::SIGTSTP,
-        Signal::TTIN => libc::SIGTTIN,
-        Signal::TTOU => libc::SIGTTOU,
-        Signal::BUS => libc::SIGBUS,
-        Signal::PROF => libc::SIGPROF,
-        Signal::SYS => libc::SIGSYS,
-        Signal::TRAP => libc::SIGTRAP,
-        Signal::URG => libc::SIGURG,
-        Signal::VTALRM => libc::SIGVTALRM,
-        Signal::XCPU => libc::SIGXCPU,
-        Signal::XFSZ => libc::SIGXFSZ,
-        Signal::IO => libc::SIGIO,
-        Signal::WINCH => libc::SIGWINCH,
-        _ => 1,
-    };
+        let delay = resubmit_delay(backoff_millis, attempt);
 
-    let signal = notify(&all_signals);
+        notify(attempt, delay);
 
-    thread::spawn(move || loop {
-        if let Some(s) = signal.recv() {
-            if let Err(e) = sender.send(Input::Signal(as_sig(&s))) {
-                eprintln!("landlord: signal handler crashed, {:?}", e);
-                process::exit(1);
-            }
-        }
-    });
-}
-
-/// Spawns a thread and consumes stdin, forwarding a copy of
-/// the consumed data to provided `sender`
-pub fn spawn_and_handle_stdin(sender: Sender<Input>) {
-    thread::spawn(move || {
-        let stdin = io::stdin();
-        let mut stdin_lock = stdin.lock();
-        let mut buffer = vec![0; 1024];
-
-        loop {
-            let result = stdin_lock.read(&mut buffer).and_then(|num| {
-                buffer.truncate(num);
-
-                let (closed, message) = if num == 0 {
-                    (true, Input::StdInClosed)
-                } else {
-                    (false, Input::StdIn(buffer.clone()))
-                };
-
-                sender
-                    .send(message)
-                    .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, format!("{:?}", e)))
-                    .map(|_| closed)
-            });
-
-            match result {
-                Ok(closed) if closed => {
-                    return;
-                }
-                Ok(_) => (),
-                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
-                Err(e) => {
-                    eprintln!("landlord: stdin crashed, {:?}", e);
-                    process::exit(1);
-                }
-            }
-        }
-    });
+        thread::sleep(delay);
+
+        let window = resubmit_delay(backoff_millis, attempt + 1);
+
+        result = if ready(window) {
+            submit()
+        } else {
+            Err((
+                daemon_error(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("landlordd wasn't ready within {}ms", as_millis(window)),
+                )),
+                false,
+            ))
+        };
+    }
+}
+
+fn as_millis(duration: time::Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
 }
 
-/// Spawns a thread and reads data from the provided `stream`. The actual logic
-/// of how much to read is done via the read_handler function.
-pub fn spawn_and_handle_stream_read<IO>(mut stream: IO, sender: Sender<Input>)
+/// Connects to landlordd via `new_stream` and negotiates the protocol to use. A landlordd
+/// that doesn't understand the `Hello` closes the connection, so another is opened upon
+/// which the legacy protocol is to be spoken.
+pub fn connect<NewS, IO>(new_stream: &mut NewS) -> io::Result<(IO, Protocol)>
 where
-    IO: IOStream + Read + Send + Write + 'static,
+    NewS: FnMut() -> io::Result<IO>,
+    IO: IOStream + Read + Write,
 {
-    thread::spawn(move || {
-        let s = &mut stream;
-        let r = |n: usize| read_bytes(s, n);
-        let m = |msg: Input| {
-            sender
-                .send(msg)
-                .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
-        };
+    let client = Protocol::client();
+    let mut stream = new_stream().map_err(daemon_error)?;
+    let mut hello = BytesMut::new();
 
-        if let Err(read_error) = read_handler(r, m) {
-            eprintln!("landlord: read_hadler crashed, {:?}", read_error);
-            process::exit(1);
-        }
-    });
+    encode_frame(Frame::Hello(client.clone()), &mut hello)?;
+
+    let response = {
+        let mut daemon = DaemonIo(&mut stream);
+
+        daemon
+            .write_all(&hello)
+            .and_then(|_| daemon.flush())
+            .and_then(|_| read_frame(&mut daemon, &mut ClientCodec::new(), &mut BytesMut::new()))
+            .map_err(daemon_error)?
+    };
+
+    match response {
+        Some(Frame::Hello(daemon)) => Ok((stream, client.negotiate(&daemon))),
+
+        Some(Frame::Unrecognized) => new_stream()
+            .map(|stream| (stream, Protocol::legacy()))
+            .map_err(daemon_error),
+
+        other => Err(daemon_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected response to hello: {:?}", other),
+        ))),
+    }
 }
 
-/// Writes the provided `class_path` to the provided `stream` and starts the process. Returns
-/// the process id (from landlordd's perpsective). Upon successful completion, the process
-/// is running and any data subsequently written to `stream` is stdin.
-pub fn install_fs_and_start<IO, S>(
-    class_path: &[S],
-    props: &[(S, S)],
-    class: &S,
-    args: &[S],
+/// Writes the class and module paths of the provided `launch` to the provided `stream` and
+/// starts the process. Returns the process id (from landlordd's perpsective). Upon successful
+/// completion, the process is running and any data subsequently written to `stream` is stdin.
+/// If a `console` size (rows, columns) is provided, the process is given a console of that
+/// size, which requires framed stdin. If a `compression` (and level) is provided, which is to
+/// be one that `protocol` supports, the tar is compressed with it. If `protocol` supports
+/// content addressing, entries that landlordd already has aren't sent. If a `cache` is
+/// provided, the tar segments and digests of entries are taken from it. Any read timeout of
+/// `stream` applies to what precedes the tar, and is then cleared.
+pub fn install_fs_and_start<IO>(
+    launch: &Launch,
+    protocol: &Protocol,
+    console: Option<(u16, u16)>,
+    compression: Option<(Compression, u32)>,
+    cache: Option<&Cache>,
     stream: &mut IO,
 ) -> io::Result<i32>
 where
     IO: IOStream + Read + Write,
-    S: AsRef<str>,
 {
+    let mut preamble = BytesMut::new();
+
+    if let Some((rows, cols)) = console {
+        protocol.require(Capability::FramedStdin, "-t")?;
+
+        encode_frame(Frame::Console { rows, cols }, &mut preamble)?;
+    }
+
+    if !launch.env.is_empty() {
+        let names = launch
+            .env
+            .iter()
+            .map(|&(ref name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+
+        protocol.require(
+            Capability::EnvForwarding,
+            &format!("forwarding {}", names.join(", ")),
+        )?;
+    }
+
+    if !launch.agents.is_empty() {
+        protocol.require(Capability::JavaAgents, "-javaagent")?;
+    }
+
+    let module_options = launch.module_options();
+
+    if !module_options.is_empty() {
+        protocol.require(Capability::Modules, &module_options.join(", "))?;
+    }
+
+    if let Some((compression, _)) = compression {
+        encode_frame(Frame::Compressed(compression), &mut preamble)?;
+    }
+
+    let entries = canonical_entries(&launch.entries())?;
+
+    let digests = if protocol.supports(Capability::ContentAddressing) {
+        let digests = digest_entries(&entries, cache)?;
+
+        encode_frame(Frame::Manifest(digests.clone()), &mut preamble)?;
+
+        Some(digests)
+    } else {
+        None
+    };
+
+    let descriptor = encode_descriptor(launch, protocol.descriptor_format())?;
+    let mut stream = DaemonIo(stream);
+
+    stream
+        .write_all(&preamble)
+        .and_then(|_| stream.write_all(&descriptor))
+        .and_then(|_| stream.flush())
+        .and_then(|_| match digests {
+            Some(ref digests) => {
+                let present =
+                    read_frame(&mut stream, &mut ClientCodec::new(), &mut BytesMut::new())
+                        .map_err(daemon_error)?;
+
+                match present {
+                    Some(Frame::Present(present)) => {
+                        Ok(missing_entries(&entries, digests, &present))
+                    }
+
+                    other => Err(daemon_error(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unexpected response to manifest: {:?}", other),
+                    ))),
+                }
+            }
+
+            None => Ok(entries),
+        })
+        .and_then(|entries| match compression {
+            Some((compression, level)) => {
+                write_compressed_fs(&entries, cache, compression, level, &mut stream)
+            }
+
+            None => write_fs(&entries, cache, &mut stream),
+        })
+        .and_then(|stream| {
+            // landlordd sends nothing while it unpacks the class path, which takes as long as
+            // that is large, so the pid is awaited without any read timeout
+
+            stream.0.set_read_timeout(None).map_err(daemon_error)?;
+
+            read_pid_handler(stream)
+        })
+}
+
+/// Writes the tar of `entries` (path, name in tar file), i.e. those of a `Launch` that are to be
+/// sent as returned by `canonical_entries`, to `writer`, padded to landlordd's block size,
+/// returning `writer` once done. If a `cache` is provided, the tar segment of each entry is
+/// copied from it, and added to it if absent, so that unchanged entries needn't be walked
+/// again. Entries that don't exist are skipped, as `java` skips them on its class path.
+pub fn write_fs<W: Write>(
+    entries: &[(String, String)],
+    cache: Option<&Cache>,
+    writer: W,
+) -> io::Result<W> {
     // given a list of class path entries, these are written to the tar via their position in
     // the vector. Meaning the first entry will be named "0", second "1", and so on. This
     // allows the user to specify any combination of directories and files without us having
     // to find some common parent path string.
 
-    let cp_with_names = class_path_with_names(class_path);
-    let descriptor = app_cmdline(cp_with_names.as_slice(), props, class, args);
+    let mut tar_padding_writer = BlockSizeWriter::new(writer, 10240);
 
-    stream.write_all(descriptor.as_bytes()).and_then(|_| {
-        let tar_padding_writer = BlockSizeWriter::new(stream, 10240);
+    for &(ref path, ref name) in entries {
+        let path = path::Path::new(path);
 
-        let mut tar_builder = Builder::new(tar_padding_writer);
+        if !path.exists() {
+            continue;
+        }
 
-        cp_with_names
-            .iter()
-            .fold(Ok(()), |accum, &(ref path, ref name)| {
-                accum.and_then(|_| {
-                    fs::canonicalize(path).and_then(|path| {
-                        let path_struct = path::Path::new(&path);
-
-                        if path_struct.is_file() {
-                            fs::File::open(path_struct)
-                                .and_then(|ref mut f| tar_builder.append_file(name, f))
-                        } else if path_struct.is_dir() {
-                            tar_builder.append_dir_all(name, path.clone())
-                        } else {
-                            Ok(())
-                        }
-                    })
+        // should the cache be unusable, the segment is written directly
+
+        let cached = cache.and_then(|cache| {
+            cache
+                .get_or_build(path, &format!("{}.tar", name), |file| {
+                    write_segment(path, name, file)
                 })
-            })
-            .and_then(|_| {
-                tar_builder
-                    .finish()
-                    .and_then(|_| tar_builder.into_inner())
-                    .and_then(|ref mut stream| stream.finish())
-                    .and_then(|ref mut stream| match stream {
-                        &mut None => Err(io::Error::new(
-                            io::ErrorKind::InvalidInput,
-                            "Unable to acquire stream (was finish() called?)",
-                        )),
-
-                        &mut Some(ref mut stream) => read_pid_handler(stream).ok_or(
-                            io::Error::new(io::ErrorKind::InvalidInput, "Unable to parse pid"),
-                        ),
-                    })
-            })
-    })
+                .ok()
+        });
+
+        match cached {
+            Some(cached) => {
+                io::copy(&mut fs::File::open(cached)?, &mut tar_padding_writer)?;
+            }
+
+            None => write_segment(path, name, &mut tar_padding_writer)?,
+        }
+    }
+
+    // the segments are followed by the two empty records that mark the end of the archive
+
+    tar_padding_writer
+        .write_all(&[0; 1024])
+        .and_then(|_| tar_padding_writer.finish())
+        .and_then(|writer| {
+            writer.ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unable to acquire stream (was finish() called?)",
+            ))
+        })
+}
+
+/// Writes the tar segment of the entry at `path`, i.e. its records without the end of
+/// archive marker, stored as `name`. An entry that's neither a file nor a directory is
+/// skipped.
+fn write_segment<W: Write>(path: &path::Path, name: &str, writer: W) -> io::Result<()> {
+    let mut tar_builder = Builder::new(SegmentWriter::new(writer));
+
+    if path.is_file() {
+        fs::File::open(path).and_then(|ref mut f| tar_builder.append_file(name, f))?;
+    } else if path.is_dir() {
+        tar_builder.append_dir_all(name, path)?;
+    }
+
+    tar_builder.into_inner().and_then(|writer| writer.finish())
+}
+
+/// Writes the tar of `entries` to `writer` as `write_fs` does, though compressed with
+/// `compression` at `level`. The compressed tar is written in length-prefixed chunks, the
+/// last of which is empty, so that landlordd needn't decompress it to know where it ends.
+pub fn write_compressed_fs<W: Write>(
+    entries: &[(String, String)],
+    cache: Option<&Cache>,
+    compression: Compression,
+    level: u32,
+    writer: W,
+) -> io::Result<W> {
+    let chunks = ChunkWriter::new(writer, 65536);
+
+    let chunks = match compression {
+        Compression::Gzip => {
+            let encoder = GzEncoder::new(chunks, flate2::Compression::new(level));
+
+            write_fs(entries, cache, encoder)?.finish()?
+        }
+
+        #[cfg(feature = "zstd-compression")]
+        Compression::Zstd => {
+            let encoder = zstd::stream::write::Encoder::new(chunks, level as i32)?;
+
+            write_fs(entries, cache, encoder)?.finish()?
+        }
+
+        #[cfg(not(feature = "zstd-compression"))]
+        Compression::Zstd => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "landlord was built without zstd support",
+            ))
+        }
+    };
+
+    chunks.finish()
+}
+
+/// Writes a description of what would be sent to landlordd in order to launch `launch`
+/// to `out`, i.e. the decoded descriptor, each tar entry and its size, the digest of each
+/// class path entry, and the total number of bytes to be uploaded should landlordd have none
+/// of them. Nothing is sent, and the tar is only listed as it's written rather than kept.
+/// If a `cache` is provided, segments and digests are taken from it as they would be when
+/// launching.
+pub fn dry_run<W: Write>(
+    launch: &Launch,
+    format: DescriptorFormat,
+    cache: Option<&Cache>,
+    out: &mut W,
+) -> io::Result<()> {
+    let descriptor = encode_descriptor(launch, format)?;
+    let entries = canonical_entries(&launch.entries())?;
+    let tar = write_fs(&entries, cache, TarListing::new())?;
+
+    writeln!(out, "descriptor format: {:?}", format)?;
+    write!(out, "{}", describe_launch(launch))?;
+    writeln!(out, "tar entries:")?;
+
+    for &(ref path, size) in &tar.entries {
+        writeln!(out, "  {} {}", path, size)?;
+    }
+
+    writeln!(out, "entry digests:")?;
+
+    for (name, digest) in digest_entries(&entries, cache)? {
+        writeln!(out, "  {} {}", name, digest)?;
+    }
+
+    writeln!(
+        out,
+        "total upload size: {} bytes (descriptor: {}, tar: {})",
+        descriptor.len() as u64 + tar.written,
+        descriptor.len(),
+        tar.written
+    )
+}
+
+/// Lists the entries of a tar as it's written, i.e. the path and size of each, counting the
+/// bytes written rather than keeping them
+struct TarListing {
+    entries: Vec<(String, u64)>,
+    header: Vec<u8>,
+    long_name: Option<Vec<u8>>,
+    remaining: u64,
+    written: u64,
+}
+
+impl TarListing {
+    fn new() -> TarListing {
+        TarListing {
+            entries: vec![],
+            header: Vec::with_capacity(512),
+            long_name: None,
+            remaining: 0,
+            written: 0,
+        }
+    }
+
+    /// Records the entry described by the header that has been read. A GNU long name entry
+    /// holds the name of the one that follows it.
+    fn read_header(&mut self) -> io::Result<()> {
+        if self.header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+
+        let header = Header::from_byte_slice(&self.header);
+        let size = header.entry_size()?;
+
+        if header.entry_type().is_gnu_longname() {
+            self.long_name = Some(vec![]);
+        } else {
+            let path = match self.long_name.take() {
+                Some(name) => name.into_iter().take_while(|b| *b != 0).collect(),
+                None => header.path_bytes().into_owned(),
+            };
+
+            self.entries
+                .push((String::from_utf8_lossy(&path).into_owned(), size));
+        }
+
+        // entry data is padded to the 512 byte record size
+
+        self.remaining = (size + 511) / 512 * 512;
+
+        Ok(())
+    }
+}
+
+impl Write for TarListing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len();
+        let mut buf = buf;
+
+        while !buf.is_empty() {
+            if self.remaining > 0 {
+                let num = buf.len().min(self.remaining as usize);
+
+                if let Some(ref mut name) = self.long_name {
+                    name.extend_from_slice(&buf[..num]);
+                }
+
+                self.remaining -= num as u64;
+                self.written += num as u64;
+                buf = &buf[num..];
+            } else {
+                let num = buf.len().min(512 - self.header.len());
+
+                self.header.extend_from_slice(&buf[..num]);
+                self.written += num as u64;
+                buf = &buf[num..];
+
+                if self.header.len() == 512 {
+                    self.read_header()?;
+                    self.header.clear();
+                }
+            }
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 /// BlockSizeWritter ensures that data written to a provided `stream`
@@ -363,17 +714,135 @@ impl<W: Write> BlockSizeWriter<W> {
     }
 }
 
-/// Exposes underlying shutdown and try_clone functions
+/// SegmentWriter passes what's written on to a provided `writer`, apart
+/// from the last 1024 bytes, i.e. the two empty records with which a
+/// `Builder` ends the archive. These are checked by `finish`.
+struct SegmentWriter<W: Write> {
+    writer: W,
+    held: Vec<u8>,
+}
+
+impl<W: Write> Write for SegmentWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.held.extend_from_slice(buf);
+
+        if self.held.len() > 1024 {
+            let len = self.held.len() - 1024;
+
+            self.writer.write_all(&self.held[..len])?;
+            self.held.drain(..len);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> SegmentWriter<W> {
+    pub fn new(writer: W) -> SegmentWriter<W> {
+        SegmentWriter {
+            writer,
+            held: Vec::with_capacity(2048),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.held.len() == 1024 && self.held.iter().all(|b| *b == 0) {
+            self.writer.flush()
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "tar segment did not end with the end of archive marker",
+            ))
+        }
+    }
+}
+
+/// ChunkWriter buffers what's written to a provided `writer`, writing
+/// it in chunks of up to `chunk_size` that are each prefixed with their
+/// length. `finish` writes the empty chunk that marks the end.
+struct ChunkWriter<W: Write> {
+    writer: W,
+    chunk: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk_size - self.chunk.len());
+
+        self.chunk.extend_from_slice(&buf[..len]);
+
+        if self.chunk.len() == self.chunk_size {
+            self.write_chunk()?;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk().and_then(|_| self.writer.flush())
+    }
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(writer: W, chunk_size: usize) -> ChunkWriter<W> {
+        ChunkWriter {
+            writer,
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk()?;
+        self.writer.write_i32::<BigEndian>(0)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.chunk.is_empty() {
+            self.writer
+                .write_i32::<BigEndian>(self.chunk.len() as i32)?;
+            self.writer.write_all(&self.chunk)?;
+            self.chunk.clear();
+        }
+
+        Ok(())
+    }
+}
+
+/// Exposes underlying set_read_timeout, shutdown and try_clone functions
 /// for the types of host protocols we support, i.e. UDS and TCP.
+/// `set_keepalive` has the peer probed once the connection has been
+/// idle for a while, so that one that has vanished is noticed within
+/// `timeout` rather than never.
 pub trait IOStream
 where
     Self: marker::Sized,
 {
+    fn set_keepalive(&self, timeout: time::Duration) -> io::Result<()>;
+    fn set_read_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()>;
     fn shutdown(&self, how: net::Shutdown) -> io::Result<()>;
     fn try_clone(&self) -> io::Result<Self>;
 }
 
 impl IOStream for UnixStream {
+    fn set_keepalive(&self, _: time::Duration) -> io::Result<()> {
+        // the peer is local, so its connection is reset should it vanish
+
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+
     fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
         self.shutdown(how)
     }
@@ -384,6 +853,23 @@ impl IOStream for UnixStream {
 }
 
 impl IOStream for TcpStream {
+    fn set_keepalive(&self, timeout: time::Duration) -> io::Result<()> {
+        // the connection is probed after half the timeout has passed idly, and then up to
+        // three more times over the other half
+
+        let idle = (timeout.as_secs() / 2).max(1) as libc::c_int;
+        let interval = (timeout.as_secs() / 6).max(1) as libc::c_int;
+
+        set_socket_option(self, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)
+            .and_then(|_| set_socket_option(self, libc::IPPROTO_TCP, TCP_KEEPIDLE, idle))
+            .and_then(|_| set_socket_option(self, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, interval))
+            .and_then(|_| set_socket_option(self, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, 3))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+
     fn shutdown(&self, how: net::Shutdown) -> io::Result<()> {
         self.shutdown(how)
     }
@@ -392,3 +878,445 @@ impl IOStream for TcpStream {
         self.try_clone()
     }
 }
+
+// macOS names the idle time before probing TCP_KEEPALIVE
+
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPIDLE;
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPALIVE;
+
+/// Sets an integer option of `socket`
+fn set_socket_option<S: AsRawFd>(
+    socket: &S,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tar_listing() {
+    use tar::Archive;
+
+    let root = std::env::temp_dir().join(format!("landlord-test-{}-listing", process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    // names of more than 100 bytes are written as GNU long name entries
+
+    let long = root.join("classes").join("a".repeat(120));
+
+    fs::create_dir_all(&long).unwrap();
+    fs::write(long.join("A.class"), "a").unwrap();
+    fs::write(root.join("lib.jar"), vec![0; 1000]).unwrap();
+
+    let entries = vec![
+        (
+            root.join("classes").to_string_lossy().into_owned(),
+            "0".to_string(),
+        ),
+        (
+            root.join("lib.jar").to_string_lossy().into_owned(),
+            "1".to_string(),
+        ),
+    ];
+
+    let tar = write_fs(&entries, None, vec![]).unwrap();
+    let listing = write_fs(&entries, None, TarListing::new()).unwrap();
+
+    let listed = Archive::new(&tar[..])
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+
+            (
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                entry.header().size().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(listing.entries, listed);
+    assert_eq!(listing.entries.len(), 4);
+    assert_eq!(listing.written, tar.len() as u64);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_write_fs_cached() {
+    let root = std::env::temp_dir().join(format!("landlord-test-{}-write-fs", process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    fs::create_dir_all(root.join("classes/com")).unwrap();
+    fs::write(root.join("classes/com/A.class"), "a").unwrap();
+    fs::write(root.join("lib.jar"), vec![1; 3000]).unwrap();
+
+    let entries = vec![
+        (
+            root.join("classes").to_string_lossy().into_owned(),
+            "0".to_string(),
+        ),
+        (
+            root.join("lib.jar").to_string_lossy().into_owned(),
+            "1".to_string(),
+        ),
+    ];
+
+    let cache = Cache::new(root.join("cache"));
+
+    let uncached = write_fs(&entries, None, vec![]).unwrap();
+    let building = write_fs(&entries, Some(&cache), vec![]).unwrap();
+    let cached = write_fs(&entries, Some(&cache), vec![]).unwrap();
+
+    assert_eq!(fs::read_dir(root.join("cache")).unwrap().count(), 2);
+    assert_eq!(building, uncached);
+    assert_eq!(cached, uncached);
+    assert_eq!(uncached.len() % 10240, 0);
+
+    // as with their digests, entries that don't exist are skipped
+
+    let mut with_missing = entries.clone();
+
+    with_missing.push((
+        root.join("missing.jar").to_string_lossy().into_owned(),
+        "2".to_string(),
+    ));
+
+    assert_eq!(write_fs(&with_missing, None, vec![]).unwrap(), uncached);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_write_compressed_fs() {
+    use byteorder::ReadBytesExt;
+    use flate2::read::GzDecoder;
+    use tar::Archive;
+
+    let root = std::env::temp_dir().join(format!("landlord-test-{}-compressed", process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    // incompressible content, so that the compressed tar spans several chunks
+
+    let random = (0..200000u32)
+        .map(|n| (n.wrapping_mul(2654435761) >> 13) as u8)
+        .collect::<Vec<_>>();
+
+    fs::create_dir_all(root.join("classes/com")).unwrap();
+    fs::write(root.join("classes/com/A.class"), "a").unwrap();
+    fs::write(root.join("lib.jar"), random).unwrap();
+
+    let entries = vec![
+        (
+            root.join("classes").to_string_lossy().into_owned(),
+            "0".to_string(),
+        ),
+        (
+            root.join("lib.jar").to_string_lossy().into_owned(),
+            "1".to_string(),
+        ),
+    ];
+
+    let tar = write_fs(&entries, None, vec![]).unwrap();
+    let written = write_compressed_fs(&entries, None, Compression::Gzip, 6, vec![]).unwrap();
+
+    let mut chunks = &written[..];
+    let mut compressed = vec![];
+    let mut count = 0;
+
+    loop {
+        let len = chunks.read_i32::<BigEndian>().unwrap() as usize;
+
+        if len == 0 {
+            break;
+        }
+
+        assert!(len <= 65536);
+        compressed.extend_from_slice(&chunks[..len]);
+        chunks = &chunks[len..];
+        count += 1;
+    }
+
+    assert!(chunks.is_empty());
+    assert!(count > 1);
+
+    let mut decompressed = vec![];
+
+    GzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+
+    assert_eq!(decompressed, tar);
+
+    let names = Archive::new(&decompressed[..])
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(names, vec!["0/", "0/com", "0/com/A.class", "1"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_dry_run_newline_argument() {
+    let launch = Launch {
+        agents: vec![],
+        args: vec!["two\nlines".to_string()],
+        assertions: vec![],
+        class_path: vec![],
+        env: vec![],
+        hints: vec![],
+        main: Main::Class("com.example.Hello".to_string()),
+        module_path: vec![],
+        options: vec![],
+        props: vec![],
+    };
+
+    let mut out = vec![];
+
+    dry_run(&launch, DescriptorFormat::LengthPrefixed, None, &mut out).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("descriptor format: LengthPrefixed\n"));
+    assert!(out.contains("  two\nlines\n"));
+
+    // the legacy format can't convey the argument, so describing it fails
+
+    assert_eq!(
+        dry_run(&launch, DescriptorFormat::Legacy, None, &mut vec![])
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_error_exit_code() {
+    let refused = || io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+
+    assert_eq!(error_exit_code(&daemon_error(refused())), EXIT_UNAVAILABLE);
+    assert_eq!(
+        error_exit_code(&daemon_error(io::Error::from(io::ErrorKind::TimedOut))),
+        EXIT_TIMED_OUT
+    );
+    assert_eq!(
+        error_exit_code(&daemon_error(io::Error::from(io::ErrorKind::InvalidData))),
+        EXIT_PROTOCOL
+    );
+
+    // the same kinds of errors that arise locally, e.g. a missing class path entry or a
+    // closed stdout, aren't landlordd's
+
+    assert_eq!(error_exit_code(&refused()), 1);
+    assert_eq!(
+        error_exit_code(&io::Error::from(io::ErrorKind::NotFound)),
+        1
+    );
+    assert_eq!(
+        error_exit_code(&io::Error::from(io::ErrorKind::BrokenPipe)),
+        1
+    );
+
+    let (stream, _) = UnixStream::pair().unwrap();
+
+    assert_eq!(
+        error_exit_code(&DaemonIo(stream).write_all(b"hi").unwrap_err()),
+        EXIT_UNAVAILABLE
+    );
+}
+
+#[test]
+fn test_resubmit() {
+    let lost = || daemon_error(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+
+    // a process is resubmitted once landlordd is ready again, until it's no longer lost
+
+    let mut results = vec![Ok(0), Err((lost(), true)), Err((lost(), false))];
+    let mut notified = Vec::new();
+
+    let result = resubmit(
+        5,
+        0,
+        || results.pop().unwrap(),
+        |_| true,
+        |attempt, _| notified.push(attempt),
+    );
+
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(notified, vec![1, 2]);
+
+    // local errors, and those of a landlordd that didn't understand it, aren't resubmitted
+
+    for error in vec![
+        io::Error::new(io::ErrorKind::ConnectionRefused, "refused"),
+        daemon_error(io::Error::from(io::ErrorKind::InvalidData)),
+    ] {
+        let mut error = Some(error);
+        let mut submitted = 0;
+
+        let result = resubmit::<(), _, _, _>(
+            5,
+            0,
+            || {
+                submitted += 1;
+
+                Err((error.take().unwrap(), false))
+            },
+            |_| true,
+            |_, _| panic!("resubmitted"),
+        );
+
+        assert_eq!(submitted, 1);
+        assert!(result.is_err());
+    }
+
+    // once the attempts are exhausted, the last error is returned
+
+    let mut submitted = 0;
+
+    let result = resubmit::<(), _, _, _>(
+        3,
+        0,
+        || {
+            submitted += 1;
+
+            Err((
+                daemon_error(io::Error::from(io::ErrorKind::TimedOut)),
+                false,
+            ))
+        },
+        |_| true,
+        |_, _| (),
+    );
+
+    assert_eq!(submitted, 4);
+    assert_eq!(error_exit_code(&result.unwrap_err()), EXIT_TIMED_OUT);
+
+    // a landlordd that stops responding once the process has launched may still be running
+    // it, so it isn't resubmitted
+
+    let mut submitted = 0;
+
+    let result = resubmit::<(), _, _, _>(
+        3,
+        0,
+        || {
+            submitted += 1;
+
+            Err((daemon_error(io::Error::from(io::ErrorKind::TimedOut)), true))
+        },
+        |_| true,
+        |_, _| panic!("resubmitted"),
+    );
+
+    assert_eq!(submitted, 1);
+    assert_eq!(error_exit_code(&result.unwrap_err()), EXIT_TIMED_OUT);
+
+    // landlordd closing the connection before the pid, e.g. upon restarting while it unpacks
+    // the class path, is it going away
+
+    let (mut closed, peer) = UnixStream::pair().unwrap();
+
+    drop(peer);
+
+    let (launching, mut peer) = UnixStream::pair().unwrap();
+
+    peer.write_all(&[0, 0, 0, 7]).unwrap();
+
+    let mut streams = vec![launching, closed.try_clone().unwrap()];
+
+    assert_eq!(
+        error_exit_code(&read_pid_handler(&mut closed).unwrap_err()),
+        EXIT_UNAVAILABLE
+    );
+
+    let result = resubmit(
+        3,
+        0,
+        || read_pid_handler(&mut streams.pop().unwrap()).map_err(|e| (e, false)),
+        |_| true,
+        |_, _| (),
+    );
+
+    assert_eq!(result.unwrap(), 7);
+    assert!(streams.is_empty());
+
+    // a landlordd that isn't ready in time uses up an attempt, and is given until the next
+    // would be due
+
+    let mut submitted = 0;
+    let mut windows = Vec::new();
+
+    let result = resubmit::<(), _, _, _>(
+        3,
+        10,
+        || {
+            submitted += 1;
+
+            Err((lost(), false))
+        },
+        |window| {
+            windows.push(window);
+
+            false
+        },
+        |_, _| (),
+    );
+
+    assert_eq!(submitted, 1);
+    assert_eq!(
+        windows,
+        vec![
+            time::Duration::from_millis(20),
+            time::Duration::from_millis(40),
+            time::Duration::from_millis(80),
+        ]
+    );
+    assert_eq!(error_exit_code(&result.unwrap_err()), EXIT_UNAVAILABLE);
+}
+
+#[test]
+fn test_wait_until_ready_timeout() {
+    let mut opened = 0;
+
+    let ready = wait_until_ready(
+        &mut || {
+            opened += 1;
+
+            Err::<UnixStream, _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+        },
+        time::Duration::from_millis(5),
+        Some(time::Duration::from_millis(20)),
+    );
+
+    assert!(!ready);
+    assert!(opened > 1);
+}
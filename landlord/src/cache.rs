@@ -0,0 +1,292 @@
+use libc;
+use sha2::{Digest, Sha256};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::{fs, io, path, process, ptr, time};
+
+/// How long a cached file may go unused before `landlord cache prune` removes it
+pub const CACHE_MAX_AGE_DAYS: u64 = 7;
+
+/// A cache of files that are derived from class path entries, i.e. their tar segments and
+/// digests, so that entries which haven't changed needn't be walked and read again. Files
+/// are keyed by the entry's path along with the size, modification and change times, inode
+/// and mode of it and everything within it, so a changed entry is simply cached anew. Those that are no
+/// longer used are removed by `prune`.
+pub struct Cache {
+    dir: path::PathBuf,
+}
+
+/// Returns the directory that landlord caches files within, i.e.
+/// `$XDG_CACHE_HOME/landlord` (or `~/.cache/landlord`), if it can be determined
+pub fn cache_dir<E>(env: E) -> Option<path::PathBuf>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    env("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(path::PathBuf::from)
+        .or_else(|| env("HOME").map(|h| path::Path::new(&h).join(".cache")))
+        .map(|d| d.join("landlord"))
+}
+
+impl Cache {
+    pub fn new(dir: path::PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    /// Returns the path of the cached file of the provided `kind` (its extension) for the
+    /// entry at `path`. If there isn't one, it's written by `build` first. Files are written
+    /// under a temporary name and then renamed, so that concurrent launches never read one
+    /// that's incomplete.
+    pub fn get_or_build<F>(
+        &self,
+        path: &path::Path,
+        kind: &str,
+        build: F,
+    ) -> io::Result<path::PathBuf>
+    where
+        F: FnOnce(&mut fs::File) -> io::Result<()>,
+    {
+        let key = entry_key(path)?;
+        let cached = self.dir.join(format!("{}.{}", key, kind));
+
+        if cached.is_file() {
+            touch(&cached)?;
+
+            return Ok(cached);
+        }
+
+        let temp = self
+            .dir
+            .join(format!("{}.{}.{}.tmp", key, kind, process::id()));
+
+        fs::create_dir_all(&self.dir)?;
+
+        let result = fs::File::create(&temp)
+            .and_then(|mut file| build(&mut file).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&temp, &cached));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        result.map(|_| cached)
+    }
+
+    /// Removes the cached files that haven't been used within `max_age`, or every one if it
+    /// isn't provided. Returns the number of files removed and their total size.
+    pub fn prune(&self, max_age: Option<time::Duration>) -> io::Result<(usize, u64)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e),
+        };
+
+        let mut removed = (0, 0);
+
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            let expired = match max_age {
+                Some(max_age) => metadata
+                    .modified()?
+                    .elapsed()
+                    .map(|age| age > max_age)
+                    .unwrap_or(false),
+
+                None => true,
+            };
+
+            if metadata.is_file() && expired {
+                fs::remove_file(entry.path())?;
+
+                removed = (removed.0 + 1, removed.1 + metadata.len());
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Returns the key of the entry at `path`, i.e. a SHA-256 digest (as lowercase hex) of its
+/// path, and the relative path, size, modification and change times, inode and mode of it
+/// and everything within it. The change time is included as, unlike the modification time,
+/// it can't be set back, e.g. by a build tool that preserves the times of what it copies.
+/// Only metadata is read, so this is far cheaper than reading the entry itself.
+pub fn entry_key(path: &path::Path) -> io::Result<String> {
+    let mut hasher = Sha256::default();
+
+    hasher.input(path.as_os_str().as_bytes());
+    hasher.input(b"\0");
+
+    key_metadata(path, path::Path::new(""), &mut hasher)?;
+
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn key_metadata(path: &path::Path, relative: &path::Path, hasher: &mut Sha256) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+
+    let fields = format!(
+        "{}\0{} {} {} {} {} {} {}\0",
+        relative.display(),
+        metadata.size(),
+        metadata.mtime(),
+        metadata.mtime_nsec(),
+        metadata.ctime(),
+        metadata.ctime_nsec(),
+        metadata.ino(),
+        metadata.mode()
+    );
+
+    hasher.input(fields.as_bytes());
+
+    if metadata.is_dir() {
+        let mut children = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        children.sort();
+
+        for name in children {
+            key_metadata(&path.join(&name), &relative.join(&name), hasher)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the modification time of the file at `path` to now, marking it as used
+fn touch(path: &path::Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    if unsafe { libc::utimes(path.as_ptr(), ptr::null()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[test]
+fn test_cache_dir() {
+    let env = |name: &str| match name {
+        "HOME" => Some("/home/user".to_string()),
+        "XDG_CACHE_HOME" => Some("/xdg".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(cache_dir(env), Some(path::PathBuf::from("/xdg/landlord")));
+
+    let env = |name: &str| match name {
+        "HOME" => Some("/home/user".to_string()),
+        "XDG_CACHE_HOME" => Some("".to_string()),
+        _ => None,
+    };
+
+    assert_eq!(
+        cache_dir(env),
+        Some(path::PathBuf::from("/home/user/.cache/landlord"))
+    );
+
+    assert_eq!(cache_dir(|_| None), None);
+}
+
+#[test]
+fn test_cache() {
+    use std::io::Write;
+
+    let root = std::env::temp_dir().join(format!("landlord-test-{}-cache", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    fs::create_dir_all(root.join("classes/com")).unwrap();
+    fs::write(root.join("classes/com/A.class"), "a").unwrap();
+
+    let cache = Cache::new(root.join("cache"));
+    let classes = root.join("classes");
+    let mut builds = 0;
+
+    let first = cache
+        .get_or_build(&classes, "txt", |file| {
+            builds += 1;
+            file.write_all(b"built")
+        })
+        .unwrap();
+
+    let second = cache
+        .get_or_build(&classes, "txt", |_| panic!("should be cached"))
+        .unwrap();
+
+    assert_eq!(builds, 1);
+    assert_eq!(first, second);
+    assert_eq!(fs::read_to_string(&first).unwrap(), "built");
+
+    // a change within the directory yields a new key
+
+    let key = entry_key(&classes).unwrap();
+    fs::write(root.join("classes/com/B.class"), "b").unwrap();
+    assert_ne!(entry_key(&classes).unwrap(), key);
+
+    let third = cache
+        .get_or_build(&classes, "txt", |file| file.write_all(b"rebuilt"))
+        .unwrap();
+
+    assert_ne!(first, third);
+
+    // as does a change of the same size whose modification time is then set back
+
+    let class = root.join("classes/com/B.class");
+    let metadata = fs::metadata(&class).unwrap();
+    let key = entry_key(&classes).unwrap();
+
+    let times = [
+        libc::timespec {
+            tv_sec: metadata.atime(),
+            tv_nsec: metadata.atime_nsec(),
+        },
+        libc::timespec {
+            tv_sec: metadata.mtime(),
+            tv_nsec: metadata.mtime_nsec(),
+        },
+    ];
+
+    std::thread::sleep(time::Duration::from_millis(10));
+    fs::write(&class, "c").unwrap();
+
+    let class = CString::new(class.as_os_str().as_bytes()).unwrap();
+
+    assert_eq!(
+        unsafe { libc::utimensat(libc::AT_FDCWD, class.as_ptr(), times.as_ptr(), 0) },
+        0
+    );
+    assert_ne!(entry_key(&classes).unwrap(), key);
+
+    // a failed build leaves nothing behind
+
+    assert!(cache
+        .get_or_build(&classes, "other", |_| Err(io::Error::new(
+            io::ErrorKind::Other,
+            "failed"
+        )))
+        .is_err());
+
+    assert_eq!(fs::read_dir(root.join("cache")).unwrap().count(), 2);
+
+    assert_eq!(
+        cache.prune(Some(time::Duration::from_secs(3600))).unwrap(),
+        (0, 0)
+    );
+
+    assert_eq!(cache.prune(None).unwrap(), (2, 12));
+    assert_eq!(fs::read_dir(root.join("cache")).unwrap().count(), 0);
+
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(cache.prune(None).unwrap(), (0, 0));
+}
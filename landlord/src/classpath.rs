@@ -0,0 +1,301 @@
+use std::io::prelude::*;
+use std::{fs, io, path};
+use zip::ZipArchive;
+
+const MANIFEST_NAME: &'static str = "META-INF/MANIFEST.MF";
+
+/// The attributes of a jar's manifest that are required in order to launch it
+#[derive(PartialEq, Debug)]
+pub struct Manifest {
+    pub class_path: Vec<String>,
+    pub main_class: Option<String>,
+}
+
+/// Expands class path entries that are wildcards (`*` or ending with `/*`) to every
+/// `.jar`/`.JAR` file within that directory, in the manner of `java`. As with `java`,
+/// sub-directories aren't searched, the jars are ordered as per the directory listing, and
+/// a directory that cannot be read expands to nothing.
+pub fn expand_class_path_wildcards<S: AsRef<str>>(class_path: &[S]) -> Vec<String> {
+    let mut expanded = vec![];
+
+    for entry in class_path.iter().map(|e| e.as_ref()) {
+        let is_wildcard =
+            (entry == "*" || entry.ends_with("/*")) && !path::Path::new(entry).exists();
+
+        if !is_wildcard {
+            expanded.push(entry.to_string());
+        } else {
+            let prefix = &entry[..entry.len() - 1];
+            let dir = if prefix.is_empty() { "." } else { prefix };
+
+            if let Ok(entries) = fs::read_dir(dir) {
+                for dir_entry in entries.filter_map(|e| e.ok()) {
+                    let name = dir_entry.file_name().to_string_lossy().into_owned();
+
+                    if name.ends_with(".jar") || name.ends_with(".JAR") {
+                        expanded.push(format!("{}{}", prefix, name));
+                    }
+                }
+            }
+        }
+    }
+
+    expanded
+}
+
+/// Given the path to a jar file, returns the class path and main class that `java -jar`
+/// would use, i.e. the jar itself followed by the entries of its `Class-Path` attribute.
+/// As with `java`, `Class-Path` entries that do not exist are ignored.
+pub fn resolve_jar(file: &str) -> io::Result<(Vec<String>, String)> {
+    let manifest = read_jar_manifest(path::Path::new(file))?;
+
+    let main_class = manifest.main_class.clone().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no main manifest attribute, in {}", file),
+        )
+    })?;
+
+    let jar_dir = path::Path::new(file)
+        .parent()
+        .unwrap_or_else(|| path::Path::new(""));
+
+    let mut class_path = vec![file.to_string()];
+
+    class_path.extend(
+        manifest_class_path(jar_dir, &manifest.class_path)
+            .into_iter()
+            .filter(|entry| path::Path::new(entry).exists()),
+    );
+
+    Ok((class_path, main_class))
+}
+
+/// Opens the jar at `path` and parses its manifest. A jar without a manifest yields an
+/// empty `Manifest`.
+pub fn read_jar_manifest(path: &path::Path) -> io::Result<Manifest> {
+    let file = fs::File::open(path).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Error: Unable to access jarfile {}", path.display()),
+        )
+    })?;
+
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Error: Invalid or corrupt jarfile {}", path.display()),
+        )
+    };
+
+    let mut archive = ZipArchive::new(file).map_err(|_| invalid())?;
+
+    let mut contents = String::new();
+
+    if let Ok(mut entry) = archive.by_name(MANIFEST_NAME) {
+        entry.read_to_string(&mut contents).map_err(|_| invalid())?;
+    }
+
+    Ok(parse_manifest(&contents))
+}
+
+/// Parses the main section of a jar manifest, handling continuation lines (those beginning
+/// with a single space) and any of the CR LF, LF or CR line endings.
+pub fn parse_manifest(contents: &str) -> Manifest {
+    let mut manifest = Manifest {
+        class_path: vec![],
+        main_class: None,
+    };
+
+    let normalized = contents.replace("\r\n", "\n").replace("\r", "\n");
+
+    let mut headers: Vec<String> = vec![];
+
+    for line in normalized.split('\n') {
+        if line.is_empty() {
+            // a blank line ends the main section
+            break;
+        } else if line.starts_with(' ') {
+            if let Some(last) = headers.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else {
+            headers.push(line.to_string());
+        }
+    }
+
+    for header in headers {
+        let parts: Vec<&str> = header.splitn(2, ':').collect();
+
+        if parts.len() == 2 {
+            let name = parts[0].trim().to_lowercase();
+            let value = parts[1].trim();
+
+            if name == "main-class" && !value.is_empty() {
+                manifest.main_class = Some(value.to_string());
+            } else if name == "class-path" {
+                manifest.class_path = value
+                    .split(' ')
+                    .filter(|e| !e.is_empty())
+                    .map(|e| e.to_string())
+                    .collect();
+            }
+        }
+    }
+
+    manifest
+}
+
+/// Resolves the (URL encoded) entries of a `Class-Path` attribute against the
+/// directory containing the jar.
+pub fn manifest_class_path<S: AsRef<str>>(jar_dir: &path::Path, entries: &[S]) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.as_ref();
+            let entry = if entry.starts_with("file:") {
+                &entry[5..]
+            } else {
+                entry
+            };
+
+            let decoded = decode_url_path(entry);
+            let decoded_path = path::Path::new(&decoded);
+
+            if decoded_path.is_absolute() {
+                decoded.clone()
+            } else {
+                jar_dir.join(decoded_path).to_string_lossy().into_owned()
+            }
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escape sequences of a URL path, leaving malformed sequences intact.
+fn decode_url_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            path.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn test_expand_class_path_wildcards() {
+    let dir = std::env::temp_dir().join(format!("landlord-test-{}-cp", std::process::id()));
+    let dir_name = dir.to_string_lossy().into_owned();
+
+    fs::create_dir_all(dir.join("sub")).unwrap();
+
+    for name in &["one.jar", "two.JAR", "three.txt", "sub/four.jar"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+
+    let mut expanded = expand_class_path_wildcards(&[
+        "/before".to_string(),
+        format!("{}/*", dir_name),
+        format!("{}/missing/*", dir_name),
+        "/after".to_string(),
+    ]);
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    expanded[1..3].sort();
+
+    assert_eq!(
+        expanded,
+        vec![
+            "/before".to_string(),
+            format!("{}/one.jar", dir_name),
+            format!("{}/two.JAR", dir_name),
+            "/after".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_manifest() {
+    assert_eq!(
+        parse_manifest(
+            "Manifest-Version: 1.0\r\nMain-Class: com.example.Main\r\nClass-Path: lib/one.jar lib/two.jar\r\n\r\nName: other\r\nMain-Class: com.example.Other\r\n"
+        ),
+        Manifest {
+            class_path: vec!["lib/one.jar".to_string(), "lib/two.jar".to_string()],
+            main_class: Some("com.example.Main".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_parse_manifest_continuation() {
+    assert_eq!(
+        parse_manifest("main-class: com.exam\n ple.Main\nClass-Path: lib/a.jar li\n b/b.jar\n"),
+        Manifest {
+            class_path: vec!["lib/a.jar".to_string(), "lib/b.jar".to_string()],
+            main_class: Some("com.example.Main".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_parse_manifest_empty() {
+    assert_eq!(
+        parse_manifest(""),
+        Manifest {
+            class_path: vec![],
+            main_class: None,
+        }
+    );
+}
+
+#[test]
+fn test_manifest_class_path() {
+    assert_eq!(
+        manifest_class_path(
+            path::Path::new("/opt/app"),
+            &[
+                "lib/one.jar",
+                "my%20lib.jar",
+                "/abs/two.jar",
+                "file:/abs/three.jar",
+                "classes/"
+            ]
+        ),
+        vec![
+            "/opt/app/lib/one.jar".to_string(),
+            "/opt/app/my lib.jar".to_string(),
+            "/abs/two.jar".to_string(),
+            "/abs/three.jar".to_string(),
+            "/opt/app/classes/".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_url_path() {
+    assert_eq!(decode_url_path("a%20b"), "a b");
+    assert_eq!(decode_url_path("a%2"), "a%2");
+    assert_eq!(decode_url_path("a%zzb"), "a%zzb");
+    assert_eq!(decode_url_path("%C3%A9"), "é");
+}
@@ -0,0 +1,331 @@
+use bindings::{DaemonIo, IOStream, FORWARDED_SIGNALS};
+use bytes::{Bytes, BytesMut};
+use futures::sync::mpsc;
+use futures::{future, stream, Future, Poll, Stream};
+use libc;
+use mio;
+use mio::unix::EventedFd;
+use proto::*;
+use std::io::prelude::*;
+use std::os::unix::net::UnixStream;
+use std::{io, net, time};
+use terminal::signal_input;
+use tokio;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::reactor::{Handle, PollEvented2};
+use tokio::runtime::current_thread::Runtime;
+use tokio::timer::Timeout;
+use tokio_codec::{BytesCodec, FramedRead, FramedWrite};
+use tokio_signal::unix::Signal;
+
+/// Converts a connected stream to its tokio equivalent. The stream is registered with
+/// the reactor of the runtime that first polls it.
+pub trait AsyncStream: IOStream {
+    type Async: AsyncRead + AsyncWrite;
+
+    fn into_async(self) -> io::Result<Self::Async>;
+}
+
+impl AsyncStream for UnixStream {
+    type Async = tokio::net::UnixStream;
+
+    fn into_async(self) -> io::Result<Self::Async> {
+        tokio::net::UnixStream::from_std(self, &Handle::default())
+    }
+}
+
+impl AsyncStream for net::TcpStream {
+    type Async = tokio::net::TcpStream;
+
+    fn into_async(self) -> io::Result<Self::Async> {
+        tokio::net::TcpStream::from_std(self, &Handle::default())
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for DaemonIo<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.0.shutdown().map_err(daemon_error)
+    }
+}
+
+/// Runs a launched process until it exits, returning its exit status. This is done upon a
+/// current-thread runtime, so the one thread waits upon stdin, signals and `stream` alike.
+///
+/// As with `input_handler`, stdin is forwarded to `stream` and signals that aren't `excluded`
+/// are forwarded as `protocol` allows, and stdout and stderr are written as landlordd
+/// provides them. landlordd is deemed to have stopped responding once it has been silent for
+/// `heartbeat_timeout`.
+pub fn run<IO, NewS>(
+    pid: i32,
+    stream: IO,
+    protocol: &Protocol,
+    excluded: &[i32],
+    heartbeat_timeout: Option<time::Duration>,
+    mut new_stream: NewS,
+) -> io::Result<ExitStatus>
+where
+    IO: AsyncStream + Read + Write,
+    NewS: FnMut() -> io::Result<IO>,
+{
+    let mut runtime = Runtime::new()?;
+
+    // shutting down a clone shuts down the connection, which is otherwise owned by the runtime
+
+    let control = stream.try_clone().map_err(daemon_error)?;
+    let (reader, writer) = stream.into_async().map_err(daemon_error)?.split();
+    let writer = DaemonIo(writer);
+
+    // what handle_input writes to the main connection, i.e. signals when stdin is framed, is
+    // interleaved with the stdin frames
+
+    let (to_daemon, written) = mpsc::unbounded();
+
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+
+    let forward_stdin = if protocol.supports(Capability::FramedStdin) {
+        let frames = FramedRead::new(Stdin::new()?, BytesCodec::new())
+            .map(|chunk| Frame::StdIn(chunk.to_vec()))
+            .chain(stream::once(Ok(Frame::StdInClosed)))
+            .and_then(|frame| {
+                let mut data = BytesMut::new();
+
+                encode_frame(frame, &mut data).map(|_| data.freeze())
+            });
+
+        let written = written
+            .map(Bytes::from)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "input channel closed"));
+
+        future::Either::A(
+            frames
+                .select(written)
+                .forward(FramedWrite::new(writer, BytesCodec::new()))
+                .map(|_| ()),
+        )
+    } else {
+        future::Either::B(
+            tokio::io::copy(Stdin::new()?, writer)
+                .and_then(move |_| control.shutdown(net::Shutdown::Write).map_err(daemon_error)),
+        )
+    }
+    .and_then(|_| future::empty::<Option<ExitStatus>, io::Error>());
+
+    let frames = FramedRead::new(reader, ClientCodec::launched()).map_err(daemon_error);
+
+    let frames = match heartbeat_timeout {
+        Some(timeout) => future::Either::A(Timeout::new(frames, timeout).map_err(move |e| {
+            if e.is_elapsed() {
+                heartbeat_timed_out(timeout)
+            } else {
+                e.into_inner()
+                    .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, "timer failed"))
+            }
+        })),
+
+        None => future::Either::B(frames),
+    };
+
+    let outputs = frames
+        .filter(|frame| *frame != Frame::Heartbeat)
+        .and_then(launched_input);
+
+    let exit_status = signals(excluded)
+        .map(|signals| inputs(signals, outputs))
+        .flatten_stream()
+        .and_then(|input| {
+            let mut writer = |bs: Vec<u8>| {
+                to_daemon
+                    .unbounded_send(bs)
+                    .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "input channel closed"))
+            };
+            let mut session_writer = |bs: Vec<u8>| {
+                new_stream().and_then(|ref mut s| {
+                    s.write_all(&bs)
+                        .and_then(|_| s.flush())
+                        .and_then(|_| s.shutdown(net::Shutdown::Write))
+                        .map_err(daemon_error)
+                })
+            };
+
+            handle_input(
+                pid,
+                protocol,
+                input,
+                &mut writer,
+                &mut session_writer,
+                &mut |bs: Vec<u8>| stdout.write_all(&bs).and_then(|_| stdout.flush()),
+                &mut |bs: Vec<u8>| stderr.write_all(&bs),
+            )
+        })
+        .filter_map(|status| status)
+        .into_future()
+        .map(|(status, _)| status)
+        .map_err(|(e, _)| e);
+
+    runtime
+        .block_on(exit_status.select(forward_stdin).map_err(|(e, _)| e))
+        .and_then(|(status, _)| {
+            status.ok_or(daemon_error(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "landlordd closed the connection",
+            )))
+        })
+}
+
+/// Returns a stream of the signals that are received, other than those `excluded`
+fn signals(
+    excluded: &[i32],
+) -> Box<dyn Future<Item = Box<dyn Stream<Item = i32, Error = io::Error>>, Error = io::Error>> {
+    let signals = FORWARDED_SIGNALS
+        .iter()
+        .filter(|s| !excluded.contains(s))
+        .map(|s| Signal::new(*s))
+        .collect::<Vec<_>>();
+
+    Box::new(future::join_all(signals).map(|signals| {
+        signals.into_iter().fold(
+            Box::new(stream::empty()) as Box<dyn Stream<Item = i32, Error = io::Error>>,
+            |all, signal| Box::new(all.select(signal)),
+        )
+    }))
+}
+
+/// Merges the `signals` that are received with the `outputs` of landlordd, ending along with
+/// the latter as the stream of signals never does
+fn inputs<S, O>(signals: S, outputs: O) -> impl Stream<Item = Input, Error = io::Error>
+where
+    S: Stream<Item = i32, Error = io::Error>,
+    O: Stream<Item = Input, Error = io::Error>,
+{
+    // the end of the connection is marked by `None`
+
+    let outputs = outputs.map(Some).chain(stream::once(Ok(None)));
+
+    signals
+        .map(|s| Some(signal_input(s)))
+        .select(outputs)
+        .take_while(|input| Ok(input.is_some()))
+        .filter_map(|input| input)
+}
+
+/// stdin, as an `AsyncRead`. Readiness is waited upon via the reactor unless stdin doesn't
+/// support it, e.g. a regular file or `/dev/null`, in which case reads never block anyway.
+/// The file descriptor is left in blocking mode as it's typically shared with stdout.
+enum Stdin {
+    Evented(PollEvented2<StdinFd>),
+    File(StdinFd),
+}
+
+impl Stdin {
+    fn new() -> io::Result<Stdin> {
+        let poll = mio::Poll::new()?;
+
+        let pollable = poll
+            .register(
+                &StdinFd,
+                mio::Token(0),
+                mio::Ready::readable(),
+                mio::PollOpt::edge(),
+            )
+            .is_ok();
+
+        Ok(if pollable {
+            Stdin::Evented(PollEvented2::new(StdinFd))
+        } else {
+            Stdin::File(StdinFd)
+        })
+    }
+}
+
+impl Read for Stdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stdin::Evented(ref mut stdin) => stdin.read(buf),
+            Stdin::File(ref mut stdin) => stdin.read(buf),
+        }
+    }
+}
+
+impl AsyncRead for Stdin {}
+
+/// Reads from file descriptor 0, failing with `WouldBlock` rather than blocking
+struct StdinFd;
+
+impl Read for StdinFd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fds = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        match unsafe { libc::poll(&mut fds, 1, 0) } {
+            -1 => Err(io::Error::last_os_error()),
+
+            0 => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "stdin isn't ready",
+            )),
+
+            _ => {
+                let num = unsafe {
+                    libc::read(
+                        libc::STDIN_FILENO,
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                    )
+                };
+
+                if num < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(num as usize)
+                }
+            }
+        }
+    }
+}
+
+impl mio::Evented for StdinFd {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: mio::Token,
+        interest: mio::Ready,
+        opts: mio::PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&libc::STDIN_FILENO).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: mio::Token,
+        interest: mio::Ready,
+        opts: mio::PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&libc::STDIN_FILENO).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&libc::STDIN_FILENO).deregister(poll)
+    }
+}
+
+#[test]
+fn test_inputs() {
+    // landlordd closes the connection without sending an exit frame
+
+    let signals = future::empty::<i32, io::Error>().into_stream();
+    let outputs = stream::iter_ok(vec![Input::StdOut(b"hello".to_vec())]);
+
+    let inputs = inputs(signals, outputs).collect().wait().unwrap();
+
+    assert_eq!(inputs.len(), 1);
+
+    match inputs[0] {
+        Input::StdOut(ref payload) => assert_eq!(payload, b"hello"),
+        _ => panic!("expected stdout"),
+    }
+}
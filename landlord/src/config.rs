@@ -0,0 +1,414 @@
+use args::{parse_host, JavaArgs, ParseError};
+use libc;
+use std::collections::BTreeMap;
+use std::{fs, io, path};
+use toml;
+
+const SYSTEM_CONFIG_PATH: &'static str = "/etc/landlord/config.toml";
+
+/// landlord's configuration file, which holds a number of named profiles. `profile` names
+/// the profile to use when `LANDLORD_PROFILE` isn't set. Otherwise, the profile named
+/// "default" is used if present.
+///
+/// ```toml
+/// profile = "local"
+///
+/// [profiles.local]
+/// host = "unix:///var/run/landlord/landlordd.sock"
+/// wait = true
+/// wait-interval-millis = 1000
+/// excluded-signals = ["WINCH"]
+/// heartbeat-timeout-millis = 30000
+/// resubmit-attempts = 5
+/// resubmit-backoff-millis = 1000
+/// compression-level = 9
+/// cache = true
+/// env-allow = ["JAVA_TOOL_OPTIONS", "APP_*"]
+/// env-deny = ["APP_SECRET"]
+///
+/// [profiles.local.props]
+/// "app.environment" = "dev"
+/// ```
+#[derive(Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named set of settings that are applied prior to those of the environment
+/// and command line. `excluded-signals` are not forwarded to the process, and
+/// instead have their default behavior in `landlord`. The client's environment
+/// variables are only forwarded if they match `env-allow` and not `env-deny`;
+/// patterns ending with `*` match a prefix. landlordd is deemed to have stopped
+/// responding if it's silent for `heartbeat-timeout-millis`, where 0 disables this.
+/// The tar is compressed if landlordd supports it, unless `compress` is false, at
+/// `compression-level` if provided. The tar segments of entries are cached unless
+/// `cache` is false. Should the connection to landlordd be lost, the process is
+/// resubmitted up to `resubmit-attempts` times, waiting `resubmit-backoff-millis`
+/// (doubled upon each attempt) beforehand.
+#[derive(Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    pub cache: Option<bool>,
+    pub compress: Option<bool>,
+    pub compression_level: Option<u32>,
+    #[serde(default)]
+    pub env_allow: Vec<String>,
+    #[serde(default)]
+    pub env_deny: Vec<String>,
+    #[serde(default)]
+    pub excluded_signals: Vec<String>,
+    pub heartbeat_timeout_millis: Option<u64>,
+    pub host: Option<String>,
+    #[serde(default)]
+    pub props: BTreeMap<String, String>,
+    pub resubmit_attempts: Option<u32>,
+    pub resubmit_backoff_millis: Option<u64>,
+    pub wait: Option<bool>,
+    pub wait_interval_millis: Option<u64>,
+}
+
+impl Config {
+    /// Parses a configuration file's contents
+    pub fn parse(contents: &str) -> io::Result<Config> {
+        toml::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Overlays `other` onto this configuration. Its profiles replace any that have the
+    /// same name.
+    pub fn merge(mut self, other: Config) -> Config {
+        if other.profile.is_some() {
+            self.profile = other.profile;
+        }
+
+        self.profiles.extend(other.profiles);
+
+        self
+    }
+
+    /// Selects the profile named by `LANDLORD_PROFILE` (or this configuration), applying
+    /// the `LANDLORD_HOST` override if set.
+    pub fn select_profile<E>(&self, env: E) -> io::Result<Profile>
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        let name = env("LANDLORD_PROFILE").or_else(|| self.profile.clone());
+
+        let mut profile = match name {
+            Some(ref name) => self.profiles.get(name).cloned().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("profile \"{}\" is not defined", name),
+                )
+            })?,
+
+            None => self.profiles.get("default").cloned().unwrap_or_default(),
+        };
+
+        if let Some(host) = env("LANDLORD_HOST") {
+            profile.host = Some(host);
+        }
+
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    /// Applies this profile's settings to `jargs`, recording any invalid settings
+    /// as errors
+    pub fn apply(&self, jargs: &mut JavaArgs) {
+        if let Some(ref host) = self.host {
+            match parse_host(host) {
+                Some(host) => jargs.host = host,
+                None => jargs.errors.push(ParseError::InvalidProfile {
+                    message: "profile host must begin with \"tcp://\" or \"unix://\"".to_string(),
+                }),
+            }
+        }
+
+        jargs.props.extend(
+            self.props
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string())),
+        );
+
+        if let Some(cache) = self.cache {
+            jargs.cache = cache;
+        }
+
+        if let Some(compress) = self.compress {
+            jargs.compress = compress;
+        }
+
+        if let Some(compression_level) = self.compression_level {
+            jargs.compression_level = Some(compression_level);
+        }
+
+        jargs.env_allow.extend(self.env_allow.iter().cloned());
+        jargs.env_deny.extend(self.env_deny.iter().cloned());
+
+        for name in &self.excluded_signals {
+            match signal_number(name) {
+                Some(signal) => jargs.excluded_signals.push(signal),
+                None => jargs.errors.push(ParseError::InvalidProfile {
+                    message: format!("profile contains unknown signal: {}", name),
+                }),
+            }
+        }
+
+        if let Some(heartbeat_timeout_millis) = self.heartbeat_timeout_millis {
+            jargs.heartbeat_timeout_millis = heartbeat_timeout_millis;
+        }
+
+        if let Some(resubmit_attempts) = self.resubmit_attempts {
+            jargs.resubmit_attempts = resubmit_attempts;
+        }
+
+        if let Some(resubmit_backoff_millis) = self.resubmit_backoff_millis {
+            jargs.resubmit_backoff_millis = resubmit_backoff_millis;
+        }
+
+        if let Some(wait) = self.wait {
+            jargs.wait = wait;
+        }
+
+        if let Some(wait_interval_millis) = self.wait_interval_millis {
+            jargs.wait_interval_millis = wait_interval_millis;
+        }
+    }
+}
+
+/// Reads and merges the system (`/etc/landlord/config.toml`) and user
+/// (`$XDG_CONFIG_HOME/landlord/config.toml`) configuration files, the latter taking
+/// precedence. Files that don't exist are skipped.
+pub fn load_config<E>(env: E) -> io::Result<Config>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    let user_config_dir = env("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(path::PathBuf::from)
+        .or_else(|| env("HOME").map(|h| path::Path::new(&h).join(".config")));
+
+    let mut paths = vec![path::PathBuf::from(SYSTEM_CONFIG_PATH)];
+
+    if let Some(dir) = user_config_dir {
+        paths.push(dir.join("landlord").join("config.toml"));
+    }
+
+    paths.iter().fold(Ok(Config::default()), |config, path| {
+        config.and_then(|config| match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents)
+                .map(|other| config.merge(other))
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("invalid configuration in {}: {}", path.display(), e),
+                    )
+                }),
+
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(config),
+
+            Err(e) => Err(e),
+        })
+    })
+}
+
+/// Converts a signal name (with or without its `SIG` prefix) or number to its number
+pub fn signal_number(name: &str) -> Option<i32> {
+    let name = name.trim_start_matches("SIG");
+
+    let signal = match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "ILL" => libc::SIGILL,
+        "ABRT" => libc::SIGABRT,
+        "FPE" => libc::SIGFPE,
+        "SEGV" => libc::SIGSEGV,
+        "PIPE" => libc::SIGPIPE,
+        "ALRM" => libc::SIGALRM,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "CHLD" => libc::SIGCHLD,
+        "CONT" => libc::SIGCONT,
+        "TSTP" => libc::SIGTSTP,
+        "TTIN" => libc::SIGTTIN,
+        "TTOU" => libc::SIGTTOU,
+        "BUS" => libc::SIGBUS,
+        "PROF" => libc::SIGPROF,
+        "SYS" => libc::SIGSYS,
+        "TRAP" => libc::SIGTRAP,
+        "URG" => libc::SIGURG,
+        "VTALRM" => libc::SIGVTALRM,
+        "XCPU" => libc::SIGXCPU,
+        "XFSZ" => libc::SIGXFSZ,
+        "IO" => libc::SIGIO,
+        "WINCH" => libc::SIGWINCH,
+        other => return other.parse().ok(),
+    };
+
+    Some(signal)
+}
+
+#[test]
+fn test_select_profile() {
+    let config = Config::parse(
+        r#"
+        profile = "local"
+
+        [profiles.default]
+        host = "tcp://127.0.0.1:2376"
+
+        [profiles.local]
+        host = "unix:///tmp/landlordd.sock"
+        wait = true
+        excluded-signals = ["WINCH", "SIGHUP"]
+        heartbeat-timeout-millis = 10000
+        resubmit-attempts = 3
+        compression-level = 9
+        cache = false
+        env-allow = ["APP_*"]
+
+        [profiles.local.props]
+        "app.environment" = "dev"
+        "#,
+    )
+    .unwrap();
+
+    let local = config.select_profile(|_| None).unwrap();
+
+    assert_eq!(
+        local,
+        Profile {
+            cache: Some(false),
+            compress: None,
+            compression_level: Some(9),
+            env_allow: vec!["APP_*".to_string()],
+            env_deny: vec![],
+            excluded_signals: vec!["WINCH".to_string(), "SIGHUP".to_string()],
+            heartbeat_timeout_millis: Some(10000),
+            host: Some("unix:///tmp/landlordd.sock".to_string()),
+            props: vec![("app.environment".to_string(), "dev".to_string())]
+                .into_iter()
+                .collect(),
+            resubmit_attempts: Some(3),
+            resubmit_backoff_millis: None,
+            wait: Some(true),
+            wait_interval_millis: None,
+        }
+    );
+
+    assert_eq!(
+        config
+            .select_profile(|name| match name {
+                "LANDLORD_PROFILE" => Some("default".to_string()),
+                _ => None,
+            })
+            .unwrap()
+            .host,
+        Some("tcp://127.0.0.1:2376".to_string())
+    );
+
+    assert_eq!(
+        config
+            .select_profile(|name| match name {
+                "LANDLORD_HOST" => Some("tcp://10.0.0.1:2376".to_string()),
+                _ => None,
+            })
+            .unwrap()
+            .host,
+        Some("tcp://10.0.0.1:2376".to_string())
+    );
+
+    assert!(config
+        .select_profile(|name| match name {
+            "LANDLORD_PROFILE" => Some("missing".to_string()),
+            _ => None,
+        })
+        .is_err());
+
+    assert_eq!(
+        Config::default().select_profile(|_| None).unwrap(),
+        Profile::default()
+    );
+}
+
+#[test]
+fn test_merge() {
+    let system =
+        Config::parse("profile = \"a\"\n[profiles.a]\nwait = true\n[profiles.b]\nwait = true\n")
+            .unwrap();
+    let user = Config::parse("[profiles.b]\nwait = false\n").unwrap();
+    let merged = system.merge(user);
+
+    assert_eq!(merged.profile, Some("a".to_string()));
+    assert_eq!(merged.profiles["a"].wait, Some(true));
+    assert_eq!(merged.profiles["b"].wait, Some(false));
+}
+
+#[test]
+fn test_apply_profile() {
+    use args::{parse_java_args_with, Host};
+
+    let profile = Profile {
+        cache: Some(false),
+        compress: Some(false),
+        compression_level: Some(1),
+        env_allow: vec!["APP_*".to_string()],
+        env_deny: vec!["APP_SECRET".to_string()],
+        excluded_signals: vec!["WINCH".to_string(), "BOGUS".to_string()],
+        heartbeat_timeout_millis: Some(0),
+        host: Some("tcp://127.0.0.1:2376".to_string()),
+        props: vec![("one".to_string(), "profile".to_string())]
+            .into_iter()
+            .collect(),
+        resubmit_attempts: Some(2),
+        resubmit_backoff_millis: Some(250),
+        wait: Some(true),
+        wait_interval_millis: Some(100),
+    };
+
+    let mut base = JavaArgs::default();
+
+    profile.apply(&mut base);
+
+    let parsed = parse_java_args_with(base, &["-Done=cli", "com.hello.Example"], |_| None);
+
+    assert_eq!(parsed.host, Host::Tcp("127.0.0.1:2376".to_string()));
+    assert_eq!(
+        parsed.props,
+        vec![
+            ("one".to_string(), "profile".to_string()),
+            ("one".to_string(), "cli".to_string()),
+        ]
+    );
+    assert_eq!(parsed.env_allow, vec!["APP_*".to_string()]);
+    assert_eq!(parsed.env_deny, vec!["APP_SECRET".to_string()]);
+    assert_eq!(parsed.excluded_signals, vec![libc::SIGWINCH]);
+    assert_eq!(
+        parsed.errors,
+        vec![ParseError::InvalidProfile {
+            message: "profile contains unknown signal: BOGUS".to_string(),
+        }]
+    );
+    assert!(!parsed.cache);
+    assert!(!parsed.compress);
+    assert_eq!(parsed.compression_level, Some(1));
+    assert_eq!(parsed.heartbeat_timeout_millis, 0);
+    assert_eq!(parsed.resubmit_attempts, 2);
+    assert_eq!(parsed.resubmit_backoff_millis, 250);
+    assert!(parsed.wait);
+    assert_eq!(parsed.wait_interval_millis, 100);
+}
+
+#[test]
+fn test_signal_number() {
+    assert_eq!(signal_number("TERM"), Some(libc::SIGTERM));
+    assert_eq!(signal_number("SIGINT"), Some(libc::SIGINT));
+    assert_eq!(signal_number("10"), Some(10));
+    assert_eq!(signal_number("NOPE"), None);
+}
@@ -0,0 +1,272 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use cache::Cache;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::io::prelude::*;
+use std::{fs, io, path};
+
+/// Returns the SHA-256 digest, as lowercase hex, of the class path entry at `path`. A file's
+/// digest is that of its contents, i.e. what `sha256sum` prints, whereas a directory's
+/// covers the relative path of everything within it, along with the size and contents of
+/// each file, in sorted order. Either way, it changes whenever what the tar would hold does.
+pub fn digest_path(path: &path::Path) -> io::Result<String> {
+    let mut hasher = Sha256::default();
+
+    if path.is_dir() {
+        digest_dir(path, path::Path::new(""), &mut hasher)?;
+    } else {
+        digest_file(path, &mut hasher)?;
+    }
+
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Given the entries of a launch (path, name in tar file), returns those that exist with their
+/// paths made canonical, as `java` skips the others on its class path. Both the tar and the
+/// digests are derived from these, so that each is cached under the same path.
+pub fn canonical_entries(entries: &[(String, String)]) -> io::Result<Vec<(String, String)>> {
+    let mut canonical = vec![];
+
+    for &(ref path, ref name) in entries {
+        match fs::canonicalize(path) {
+            Ok(path) => canonical.push((path.to_string_lossy().into_owned(), name.clone())),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(canonical)
+}
+
+/// Given the entries of a launch (path, name in tar file), as returned by `canonical_entries`,
+/// returns the digest of each that exists as (name, digest). Those that don't exist aren't
+/// written to the tar either. If a `cache` is provided, digests are taken from it, and added
+/// to it if absent.
+pub fn digest_entries(
+    entries: &[(String, String)],
+    cache: Option<&Cache>,
+) -> io::Result<Vec<(String, String)>> {
+    let mut digests = vec![];
+
+    for &(ref path, ref name) in entries {
+        let path = path::Path::new(path);
+
+        if path.exists() {
+            let digest = match cache {
+                Some(cache) => cache
+                    .get_or_build(path, "sha256", |file| {
+                        file.write_all(digest_path(path)?.as_bytes())
+                    })
+                    .and_then(fs::read_to_string)?,
+
+                None => digest_path(path)?,
+            };
+
+            digests.push((name.clone(), digest));
+        }
+    }
+
+    Ok(digests)
+}
+
+/// Returns the entries (path, name in tar file) that still need to be sent, given their
+/// `digests` (name, digest) and those that landlordd already has (`present`).
+pub fn missing_entries(
+    entries: &[(String, String)],
+    digests: &[(String, String)],
+    present: &[String],
+) -> Vec<(String, String)> {
+    let present: BTreeSet<&str> = present.iter().map(|d| d.as_str()).collect();
+
+    let sent: BTreeSet<&str> = digests
+        .iter()
+        .filter(|&&(_, ref digest)| !present.contains(digest.as_str()))
+        .map(|&(ref name, _)| name.as_str())
+        .collect();
+
+    entries
+        .iter()
+        .filter(|&&(_, ref name)| sent.contains(name.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn digest_dir(root: &path::Path, relative: &path::Path, hasher: &mut Sha256) -> io::Result<()> {
+    let mut children = fs::read_dir(root.join(relative))?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    children.sort();
+
+    for name in children {
+        let relative = relative.join(name);
+        let path = root.join(&relative);
+        let relative_name = relative.to_string_lossy().into_owned();
+
+        // paths are NUL terminated, and files are preceded by their size, so that
+        // no two trees can produce the same input
+
+        if path.is_dir() {
+            hasher.input(b"d");
+            hasher.input(relative_name.as_bytes());
+            hasher.input(b"\0");
+
+            digest_dir(root, &relative, hasher)?;
+        } else if path.is_file() {
+            let mut size = vec![];
+            size.write_u64::<BigEndian>(fs::metadata(&path)?.len())?;
+
+            hasher.input(b"f");
+            hasher.input(relative_name.as_bytes());
+            hasher.input(b"\0");
+            hasher.input(&size);
+
+            digest_file(&path, hasher)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn digest_file(path: &path::Path, hasher: &mut Sha256) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let mut chunk = [0; 65536];
+
+    loop {
+        let num = match file.read(&mut chunk) {
+            Ok(num) => num,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        if num == 0 {
+            return Ok(());
+        }
+
+        hasher.input(&chunk[..num]);
+    }
+}
+
+#[test]
+fn test_digest_path() {
+    let root = std::env::temp_dir().join(format!("landlord-test-{}-digest", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    fs::create_dir_all(root.join("tree/com/example")).unwrap();
+    fs::write(root.join("lib.jar"), "hello").unwrap();
+    fs::write(root.join("tree/com/example/A.class"), "a").unwrap();
+
+    assert_eq!(
+        digest_path(&root.join("lib.jar")).unwrap(),
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+
+    // landlordd computes the same digest in order to check what it keeps
+
+    let tree = digest_path(&root.join("tree")).unwrap();
+    assert_eq!(
+        tree,
+        "1ed1b667564f4eb9af808359db5aa3a7a42d81ba0b2b12106c5a6467ee818727"
+    );
+    assert_eq!(digest_path(&root.join("tree")).unwrap(), tree);
+
+    fs::write(root.join("tree/com/example/A.class"), "b").unwrap();
+    let changed = digest_path(&root.join("tree")).unwrap();
+    assert_ne!(changed, tree);
+
+    fs::rename(
+        root.join("tree/com/example/A.class"),
+        root.join("tree/com/example/B.class"),
+    ).unwrap();
+    assert_ne!(digest_path(&root.join("tree")).unwrap(), changed);
+
+    let entries = vec![
+        (
+            root.join("lib.jar").to_string_lossy().into_owned(),
+            "0".to_string(),
+        ),
+        (
+            root.join("absent.jar").to_string_lossy().into_owned(),
+            "1".to_string(),
+        ),
+    ];
+
+    let expected = vec![(
+        "0".to_string(),
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string(),
+    )];
+
+    // paths are made canonical once, and those that don't exist are skipped
+
+    std::os::unix::fs::symlink(root.join("lib.jar"), root.join("link.jar")).unwrap();
+
+    let linked = vec![
+        (
+            root.join("link.jar").to_string_lossy().into_owned(),
+            "0".to_string(),
+        ),
+        (
+            root.join("absent.jar").to_string_lossy().into_owned(),
+            "1".to_string(),
+        ),
+    ];
+
+    assert_eq!(
+        canonical_entries(&linked).unwrap(),
+        vec![(
+            fs::canonicalize(root.join("lib.jar"))
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            "0".to_string(),
+        )]
+    );
+
+    let cache = Cache::new(root.join("cache"));
+
+    assert_eq!(digest_entries(&entries, None).unwrap(), expected);
+    assert_eq!(digest_entries(&entries, Some(&cache)).unwrap(), expected);
+    assert_eq!(digest_entries(&entries, Some(&cache)).unwrap(), expected);
+    assert_eq!(fs::read_dir(root.join("cache")).unwrap().count(), 1);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_missing_entries() {
+    let entries = vec![
+        ("/lib/a.jar".to_string(), "0".to_string()),
+        ("/lib/b.jar".to_string(), "1".to_string()),
+        ("/classes".to_string(), "2".to_string()),
+        ("/absent.jar".to_string(), "3".to_string()),
+    ];
+
+    let digests = vec![
+        ("0".to_string(), "aa".to_string()),
+        ("1".to_string(), "bb".to_string()),
+        ("2".to_string(), "cc".to_string()),
+    ];
+
+    assert_eq!(
+        missing_entries(&entries, &digests, &["bb".to_string(), "dd".to_string()]),
+        vec![
+            ("/lib/a.jar".to_string(), "0".to_string()),
+            ("/classes".to_string(), "2".to_string()),
+        ]
+    );
+
+    assert_eq!(missing_entries(&entries, &digests, &[]).len(), 3);
+
+    assert_eq!(
+        missing_entries(
+            &entries,
+            &digests,
+            &["aa".to_string(), "bb".to_string(), "cc".to_string()]
+        ),
+        vec![]
+    );
+}
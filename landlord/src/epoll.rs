@@ -0,0 +1,482 @@
+use bindings::{DaemonIo, IOStream, FORWARDED_SIGNALS};
+use bytes::BytesMut;
+use libc;
+use mio;
+use mio::unix::EventedFd;
+use proto::*;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::{io, mem, net, ptr, time};
+use terminal::signal_input;
+
+const STDIN: mio::Token = mio::Token(0);
+const STDOUT: mio::Token = mio::Token(1);
+const STREAM: mio::Token = mio::Token(2);
+const SIGNALS: mio::Token = mio::Token(3);
+
+/// The most that is read from stdin or `stream` at once
+const READ_SIZE: usize = 8192;
+
+/// The most that is written to a pollable stdout at once, so as not to block when it has
+/// less room than that available
+const STDOUT_WRITE_SIZE: usize = 4096;
+
+/// Once this much output is waiting to be written to stdout, `stream` is no longer read
+/// until some of it has been
+const MAX_PENDING_STDOUT: usize = 65536;
+
+/// A connected stream that can be registered with an event loop
+pub trait EventedStream: IOStream + Read + Write + AsRawFd {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl EventedStream for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.set_nonblocking(nonblocking)
+    }
+}
+
+impl EventedStream for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.set_nonblocking(nonblocking)
+    }
+}
+
+/// Runs a launched process until it exits, returning its exit status. This is done by a single
+/// thread that polls (via epoll) stdin, stdout, `stream` and a signalfd.
+///
+/// stdin is only read once what was last read has been written to `stream`, and `stream` is
+/// only read while stdout keeps up with it. stdin and stdout that can't be polled, e.g.
+/// regular files, never block and so are read and written as needed. stderr is written as
+/// landlordd provides it. stdin and signals are forwarded as `protocol` allows. landlordd is
+/// deemed to have stopped responding once it has been silent for `heartbeat_timeout`, though
+/// only while `stream` is being read.
+pub fn run<IO, NewS>(
+    pid: i32,
+    mut stream: IO,
+    protocol: &Protocol,
+    excluded: &[i32],
+    heartbeat_timeout: Option<time::Duration>,
+    mut new_stream: NewS,
+) -> io::Result<ExitStatus>
+where
+    IO: EventedStream,
+    NewS: FnMut() -> io::Result<IO>,
+{
+    let poll = mio::Poll::new()?;
+    let signals = SignalFd::new(excluded)?;
+
+    stream.set_nonblocking(true).map_err(daemon_error)?;
+
+    poll.register(
+        &signals,
+        SIGNALS,
+        mio::Ready::readable(),
+        mio::PollOpt::level(),
+    )?;
+
+    let mut stdin = Interest::new(libc::STDIN_FILENO, STDIN, &poll);
+    let mut stdout = Interest::new(libc::STDOUT_FILENO, STDOUT, &poll);
+    let mut socket = Interest::new(stream.as_raw_fd(), STREAM, &poll);
+
+    let mut codec = ClientCodec::launched();
+    let mut events = mio::Events::with_capacity(16);
+    let mut from_daemon = BytesMut::new();
+    let mut to_daemon = vec![];
+    let mut to_stdout = vec![];
+    let mut stdin_open = true;
+    let mut shutdown_requested = false;
+    let mut write_shutdown = false;
+    let mut buf = [0; READ_SIZE];
+    let mut last_heard = time::Instant::now();
+
+    loop {
+        let mut inputs = vec![];
+
+        if !to_daemon.is_empty() {
+            write_nonblocking(&mut DaemonIo(&mut stream), &mut to_daemon)?;
+        }
+
+        if shutdown_requested && to_daemon.is_empty() && !write_shutdown {
+            stream
+                .shutdown(net::Shutdown::Write)
+                .map_err(daemon_error)?;
+            write_shutdown = true;
+        }
+
+        // stdin that can't be polled is read as soon as what was last read has been written,
+        // in which case poll is merely checked rather than waited upon so that it's handled
+
+        if stdin_open && !stdin.pollable && to_daemon.is_empty() {
+            inputs.push(stdin_input(&mut buf, &mut stdin_open)?);
+        }
+
+        let mut socket_ready = mio::Ready::empty();
+
+        if to_stdout.len() < MAX_PENDING_STDOUT {
+            socket_ready |= mio::Ready::readable();
+        } else {
+            last_heard = time::Instant::now();
+        }
+
+        if !to_daemon.is_empty() {
+            socket_ready |= mio::Ready::writable();
+        }
+
+        socket.update(socket_ready)?;
+
+        stdin.update(if stdin_open && to_daemon.is_empty() {
+            mio::Ready::readable()
+        } else {
+            mio::Ready::empty()
+        })?;
+
+        stdout.update(if to_stdout.is_empty() {
+            mio::Ready::empty()
+        } else {
+            mio::Ready::writable()
+        })?;
+
+        let timeout = if !inputs.is_empty() {
+            Some(time::Duration::from_millis(0))
+        } else {
+            heartbeat_timeout.map(|t| {
+                t.checked_sub(last_heard.elapsed())
+                    .unwrap_or(time::Duration::from_millis(0))
+            })
+        };
+
+        match poll.poll(&mut events, timeout) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+
+            result => {
+                result?;
+            }
+        }
+
+        for event in events.iter() {
+            match event.token() {
+                STDIN => match stdin_input(&mut buf, &mut stdin_open) {
+                    Ok(input) => inputs.push(input),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                },
+
+                STDOUT => {
+                    let len = to_stdout.len().min(STDOUT_WRITE_SIZE);
+                    let written = write_fd(libc::STDOUT_FILENO, &to_stdout[..len])?;
+
+                    to_stdout.drain(..written);
+                }
+
+                STREAM if event.readiness().is_readable() => match stream.read(&mut buf) {
+                    Ok(0) => {
+                        return Err(daemon_error(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "landlordd closed the connection",
+                        )))
+                    }
+
+                    Ok(n) => {
+                        from_daemon.extend_from_slice(&buf[..n]);
+                        last_heard = time::Instant::now();
+
+                        while let Some(frame) =
+                            codec.decode(&mut from_daemon).map_err(daemon_error)?
+                        {
+                            if frame != Frame::Heartbeat {
+                                inputs.push(launched_input(frame)?);
+                            }
+                        }
+                    }
+
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+
+                    Err(e) => return Err(daemon_error(e)),
+                },
+
+                SIGNALS => {
+                    while let Some(signal) = signals.read()? {
+                        inputs.push(signal_input(signal));
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        if let Some(timeout) = heartbeat_timeout {
+            if last_heard.elapsed() >= timeout {
+                return Err(heartbeat_timed_out(timeout));
+            }
+        }
+
+        for input in inputs {
+            let mut writer = |bs: Vec<u8>| {
+                if bs.is_empty() {
+                    shutdown_requested = true;
+                } else {
+                    to_daemon.extend(bs);
+                }
+
+                Ok(())
+            };
+            let mut session_writer = |bs: Vec<u8>| {
+                new_stream().and_then(|ref mut s| {
+                    s.write_all(&bs)
+                        .and_then(|_| s.flush())
+                        .and_then(|_| s.shutdown(net::Shutdown::Write))
+                        .map_err(daemon_error)
+                })
+            };
+
+            let pollable_stdout = stdout.pollable;
+
+            let handled = handle_input(
+                pid,
+                protocol,
+                input,
+                &mut writer,
+                &mut session_writer,
+                &mut |bs: Vec<u8>| {
+                    if pollable_stdout {
+                        to_stdout.extend(bs);
+
+                        Ok(())
+                    } else {
+                        let mut out = io::stdout();
+
+                        out.write_all(&bs).and_then(|_| out.flush())
+                    }
+                },
+                &mut |bs: Vec<u8>| io::stderr().write_all(&bs),
+            )?;
+
+            if let Some(status) = handled {
+                // the process has exited, so whatever it wrote is now waited upon
+
+                stdout.update(mio::Ready::empty())?;
+                write_all_fd(libc::STDOUT_FILENO, &to_stdout)?;
+
+                return Ok(status);
+            }
+        }
+    }
+}
+
+/// A file descriptor's registration with a `mio::Poll`. Those that can't be polled, e.g.
+/// regular files, are never registered.
+struct Interest<'a> {
+    fd: RawFd,
+    token: mio::Token,
+    poll: &'a mio::Poll,
+    pollable: bool,
+    registered: mio::Ready,
+}
+
+impl<'a> Interest<'a> {
+    fn new(fd: RawFd, token: mio::Token, poll: &'a mio::Poll) -> Interest<'a> {
+        let pollable = poll
+            .register(
+                &EventedFd(&fd),
+                token,
+                mio::Ready::empty(),
+                mio::PollOpt::level(),
+            )
+            .and_then(|_| poll.deregister(&EventedFd(&fd)))
+            .is_ok();
+
+        Interest {
+            fd,
+            token,
+            poll,
+            pollable,
+            registered: mio::Ready::empty(),
+        }
+    }
+
+    /// Registers interest in `ready`, or deregisters if it is empty
+    fn update(&mut self, ready: mio::Ready) -> io::Result<()> {
+        if !self.pollable || ready == self.registered {
+            return Ok(());
+        }
+
+        let fd = EventedFd(&self.fd);
+
+        if ready.is_empty() {
+            self.poll.deregister(&fd)?;
+        } else if self.registered.is_empty() {
+            self.poll
+                .register(&fd, self.token, ready, mio::PollOpt::level())?;
+        } else {
+            self.poll
+                .reregister(&fd, self.token, ready, mio::PollOpt::level())?;
+        }
+
+        self.registered = ready;
+
+        Ok(())
+    }
+}
+
+/// The forwarded signals, delivered via a signalfd rather than a handler. They're blocked
+/// for as long as this exists.
+struct SignalFd {
+    fd: RawFd,
+    mask: libc::sigset_t,
+}
+
+impl SignalFd {
+    fn new(excluded: &[i32]) -> io::Result<SignalFd> {
+        unsafe {
+            let mut mask: libc::sigset_t = mem::zeroed();
+
+            libc::sigemptyset(&mut mask);
+
+            for signal in FORWARDED_SIGNALS.iter().filter(|s| !excluded.contains(s)) {
+                libc::sigaddset(&mut mask, *signal);
+            }
+
+            if libc::sigprocmask(libc::SIG_BLOCK, &mask, ptr::null_mut()) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let fd = libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC);
+
+            if fd == -1 {
+                let error = io::Error::last_os_error();
+
+                libc::sigprocmask(libc::SIG_UNBLOCK, &mask, ptr::null_mut());
+
+                return Err(error);
+            }
+
+            Ok(SignalFd { fd, mask })
+        }
+    }
+
+    /// Returns the next signal received, if any
+    fn read(&self) -> io::Result<Option<i32>> {
+        let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+        let size = mem::size_of::<libc::signalfd_siginfo>();
+
+        let num = unsafe { libc::read(self.fd, &mut info as *mut _ as *mut libc::c_void, size) };
+
+        if num == size as isize {
+            Ok(Some(info.ssi_signo as i32))
+        } else if num < 0 {
+            let error = io::Error::last_os_error();
+
+            if error.kind() == io::ErrorKind::WouldBlock {
+                Ok(None)
+            } else {
+                Err(error)
+            }
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Short read from signalfd",
+            ))
+        }
+    }
+}
+
+impl mio::Evented for SignalFd {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: mio::Token,
+        interest: mio::Ready,
+        opts: mio::PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.fd).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: mio::Token,
+        interest: mio::Ready,
+        opts: mio::PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.fd).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&self.fd).deregister(poll)
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+            libc::sigprocmask(libc::SIG_UNBLOCK, &self.mask, ptr::null_mut());
+        }
+    }
+}
+
+/// Reads the next chunk of stdin, noting when it has ended
+fn stdin_input(buf: &mut [u8], stdin_open: &mut bool) -> io::Result<Input> {
+    match read_fd(libc::STDIN_FILENO, buf)? {
+        0 => {
+            *stdin_open = false;
+
+            Ok(Input::StdInClosed)
+        }
+
+        n => Ok(Input::StdIn(buf[..n].to_vec())),
+    }
+}
+
+/// Writes as much of `data` to `stream` as it will accept without blocking, removing what
+/// was written
+fn write_nonblocking<W: Write>(stream: &mut W, data: &mut Vec<u8>) -> io::Result<()> {
+    while !data.is_empty() {
+        match stream.write(data) {
+            Ok(n) => {
+                data.drain(..n);
+            }
+
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_fd(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let num = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+    if num < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(num as usize)
+    }
+}
+
+fn write_fd(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let num = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+
+    if num < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(num as usize)
+    }
+}
+
+fn write_all_fd(fd: RawFd, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match write_fd(fd, buf) {
+            Ok(n) => buf = &buf[n..],
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,35 @@
 extern crate byteorder;
-extern crate chan_signal;
+extern crate bytes;
+extern crate flate2;
+#[cfg(feature = "async")]
+extern crate futures;
 extern crate libc;
+extern crate mio;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate sha2;
 extern crate tar;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "async")]
+extern crate tokio_codec;
+#[cfg(feature = "async")]
+extern crate tokio_signal;
+extern crate toml;
+extern crate zip;
+#[cfg(feature = "zstd-compression")]
+extern crate zstd;
 
 pub mod args;
 pub mod bindings;
+pub mod cache;
+pub mod classpath;
+#[cfg(feature = "async")]
+pub mod client;
+pub mod config;
+pub mod digest;
+#[cfg(feature = "epoll")]
+pub mod epoll;
 pub mod proto;
+pub mod terminal;
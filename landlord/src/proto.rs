@@ -1,15 +1,101 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::BytesMut;
+use libc;
 use std::io;
 use std::io::prelude::*;
+use std::{error, fmt, str, time};
+#[cfg(feature = "async")]
+use tokio_codec;
 
 pub enum Input {
-    Exit(i32),
+    Exit(ExitStatus),
     Fail(io::Error),
     Signal(i32),
     StdIn(Vec<u8>),
     StdInClosed,
     StdOut(Vec<u8>),
     StdErr(Vec<u8>),
+    WindowSize { rows: u16, cols: u16 },
+}
+
+/// Why a process exited, as reported by a landlordd that supports `ExitReasons`. Others
+/// only report the code, i.e. `Unspecified`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExitReason {
+    Exited,
+    LoadFailed,
+    Signaled,
+    UncaughtException,
+    Unspecified,
+}
+
+impl ExitReason {
+    fn from_code(code: i32) -> ExitReason {
+        match code {
+            1 => ExitReason::Exited,
+            2 => ExitReason::UncaughtException,
+            3 => ExitReason::Signaled,
+            4 => ExitReason::LoadFailed,
+            _ => ExitReason::Unspecified,
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match *self {
+            ExitReason::Unspecified => 0,
+            ExitReason::Exited => 1,
+            ExitReason::UncaughtException => 2,
+            ExitReason::Signaled => 3,
+            ExitReason::LoadFailed => 4,
+        }
+    }
+}
+
+/// How a process exited: its exit code and why, along with any message that landlordd
+/// provided, e.g. the stack trace of an uncaught exception. The code of a process that was
+/// `Signaled` is 128 + the signal's number, as per the convention of shells.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExitStatus {
+    pub code: i32,
+    pub reason: ExitReason,
+    pub message: Option<String>,
+}
+
+impl ExitStatus {
+    /// The status of a process whose exit was reported without a reason
+    pub fn code(code: i32) -> ExitStatus {
+        ExitStatus {
+            code,
+            reason: ExitReason::Unspecified,
+            message: None,
+        }
+    }
+
+    /// The signal that the process was terminated by, if it was
+    pub fn signal(&self) -> Option<i32> {
+        if self.reason == ExitReason::Signaled && self.code > 128 {
+            Some(self.code - 128)
+        } else {
+            None
+        }
+    }
+
+    /// Describes the exit as `java` would on stderr, if it would at all
+    pub fn describe(&self) -> Option<String> {
+        match (self.reason, &self.message) {
+            (ExitReason::UncaughtException, &Some(ref message)) => {
+                Some(format!("Exception in thread \"main\" {}", message))
+            }
+
+            (ExitReason::LoadFailed, &Some(ref message)) => Some(format!("Error: {}", message)),
+
+            (ExitReason::LoadFailed, &None) => {
+                Some("Error: Could not find or load main class".to_string())
+            }
+
+            _ => None,
+        }
+    }
 }
 
 /// Allocates a buffer of `num` bytes and reads that exact number
@@ -24,12 +110,13 @@ pub fn read_bytes(read: &mut Read, num: usize) -> io::Result<Vec<u8>> {
 /// handles the events accordingly
 pub fn input_handler<R, W, SW, StdOut, StdErr>(
     pid: i32,
+    protocol: &Protocol,
     mut reader: R,
     mut writer: W,
     mut single_session_writer: SW,
     mut std_out: StdOut,
     mut std_err: StdErr,
-) -> io::Result<i32>
+) -> io::Result<ExitStatus>
 where
     R: FnMut() -> io::Result<Input>,
     W: FnMut(Vec<u8>) -> io::Result<()>,
@@ -38,272 +125,2176 @@ where
     StdErr: FnMut(Vec<u8>) -> io::Result<()>,
 {
     loop {
-        match reader() {
-            Ok(Input::Exit(s)) => {
-                return Ok(s);
-            }
+        let input = reader()?;
 
-            Ok(Input::Fail(e)) => {
-                return Err(e);
-            }
+        let handled = handle_input(
+            pid,
+            protocol,
+            input,
+            &mut writer,
+            &mut single_session_writer,
+            &mut std_out,
+            &mut std_err,
+        )?;
 
-            Ok(Input::StdIn(b)) => {
-                if !b.is_empty() {
-                    if let Err(e) = writer(b) {
-                        return Err(e);
-                    }
-                }
-            }
+        if let Some(status) = handled {
+            return Ok(status);
+        }
+    }
+}
 
-            Ok(Input::StdInClosed) => {
-                if let Err(e) = writer(vec![]) {
-                    return Err(e);
-                }
-            }
+/// handles a single event on behalf of `input_handler`, for use by event loops that are
+/// driven by readiness rather than a blocking reader. Returns the exit status once the
+/// process has exited.
+///
+/// What `writer` is given is to be written to the main connection. Unless `protocol` frames
+/// stdin, that's stdin as is, with an empty write signalling its end (i.e. a write-half
+/// shutdown), and signals are instead sent to `single_session_writer`.
+pub fn handle_input<W, SW, StdOut, StdErr>(
+    pid: i32,
+    protocol: &Protocol,
+    input: Input,
+    writer: &mut W,
+    single_session_writer: &mut SW,
+    std_out: &mut StdOut,
+    std_err: &mut StdErr,
+) -> io::Result<Option<ExitStatus>>
+where
+    W: FnMut(Vec<u8>) -> io::Result<()>,
+    StdOut: FnMut(Vec<u8>) -> io::Result<()>,
+    SW: FnMut(Vec<u8>) -> io::Result<()>,
+    StdErr: FnMut(Vec<u8>) -> io::Result<()>,
+{
+    let framed = protocol.supports(Capability::FramedStdin);
+
+    match input {
+        Input::Exit(s) => {
+            return Ok(Some(s));
+        }
 
-            Ok(Input::Signal(s)) => {
-                let result = encode_i32(pid)
-                    .and_then(|pid_bytes| encode_i32(s).map(|sig_bytes| (pid_bytes, sig_bytes)))
-                    .and_then(|(ref mut pid_bytes, ref mut sig_bytes)| {
-                        let mut data = vec![];
+        Input::Fail(e) => {
+            return Err(e);
+        }
 
-                        data.push(b'k');
-                        data.append(pid_bytes);
-                        data.append(sig_bytes);
+        Input::StdOut(b) => {
+            std_out(b)?;
+        }
 
-                        single_session_writer(data)
-                    });
+        Input::StdErr(b) => {
+            std_err(b)?;
+        }
 
-                if let Err(e) = result {
-                    return Err(e);
+        other => {
+            if framed {
+                if let Some(frame) = outgoing_frame(other) {
+                    write_frame(writer, frame)?;
                 }
-            }
+            } else {
+                match other {
+                    Input::StdIn(b) => {
+                        if !b.is_empty() {
+                            writer(b)?;
+                        }
+                    }
 
-            Ok(Input::StdOut(b)) => {
-                if let Err(e) = std_out(b) {
-                    return Err(e);
-                }
-            }
+                    Input::StdInClosed => {
+                        writer(vec![])?;
+                    }
 
-            Ok(Input::StdErr(b)) => {
-                if let Err(e) = std_err(b) {
-                    return Err(e);
-                }
-            }
+                    Input::Signal(s) => {
+                        write_frame(single_session_writer, Frame::Kill { pid, signal: s })?;
+                    }
+
+                    Input::WindowSize { .. } => {
+                        let signal = libc::SIGWINCH;
+
+                        write_frame(single_session_writer, Frame::Kill { pid, signal })?;
+                    }
 
-            Err(e) => {
-                return Err(e);
+                    _ => {}
+                }
             }
         }
     }
+
+    Ok(None)
+}
+
+/// converts an input that is destined for landlordd to the frame that carries it upon the main
+/// connection of a session that negotiated `FramedStdin`
+pub fn outgoing_frame(input: Input) -> Option<Frame> {
+    match input {
+        Input::Signal(signal) => Some(Frame::Signal(signal)),
+
+        Input::StdIn(payload) => Some(Frame::StdIn(payload)),
+
+        Input::StdInClosed => Some(Frame::StdInClosed),
+
+        Input::WindowSize { rows, cols } => Some(Frame::WindowSize { rows, cols }),
+
+        _ => None,
+    }
+}
+
+/// encodes `frame` and gives it to `writer`
+fn write_frame<W>(writer: &mut W, frame: Frame) -> io::Result<()>
+where
+    W: FnMut(Vec<u8>) -> io::Result<()>,
+{
+    let mut data = BytesMut::new();
+
+    encode_frame(frame, &mut data)?;
+
+    writer(data.to_vec())
 }
 
-/// manages reading the socket (landlord protocol)
-pub fn read_handler<R, W>(mut reader: R, mut writer: W) -> io::Result<()>
+/// manages reading the socket (landlord protocol), i.e. the frames that follow the process id
+pub fn read_handler<R, W>(reader: &mut R, mut writer: W) -> io::Result<()>
 where
-    R: FnMut(usize) -> io::Result<Vec<u8>>,
+    R: Read,
     W: FnMut(Input) -> io::Result<()>,
 {
+    let mut codec = ClientCodec::launched();
+    let mut buf = BytesMut::new();
+
     loop {
-        match reader(1).map(|bs| bs[0]) {
-            Ok(101) => {
-                // UTF8 'e'
-                let result = read_payload(&mut reader)
-                    .map(|p| Input::StdErr(p))
-                    .and_then(|msg| writer(msg));
-
-                if result.is_err() {
-                    return result;
-                }
-            }
-            Ok(111) => {
-                // UTF8 'o'
-                let result = read_payload(&mut reader)
-                    .map(|p| Input::StdOut(p))
-                    .and_then(|msg| writer(msg));
-
-                if result.is_err() {
-                    return result;
-                }
-            }
-            Ok(120) => {
-                // UTF8 'x'
+        match read_frame(reader, &mut codec, &mut buf)? {
+            Some(Frame::Heartbeat) => {}
 
-                return reader(4)
-                    .and_then(|bs| decode_i32(&bs))
-                    .and_then(|code| writer(Input::Exit(code)));
-            }
-            Ok(other) => {
+            Some(frame) => match launched_input(frame)? {
+                Input::Exit(status) => return writer(Input::Exit(status)),
+
+                input => writer(input)?,
+            },
+
+            None => {
                 return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Unknown code: {}", other),
+                    io::ErrorKind::UnexpectedEof,
+                    "landlordd closed the connection",
                 ))
             }
-            Err(err) => {
-                return Err(err);
-            }
         }
     }
 }
 
-/// reads the process id from the provided `stream`
-pub fn read_pid_handler(stream: &mut Read) -> Option<i32> {
-    read_bytes(stream, 4)
-        .ok()
-        .and_then(|bs| io::Cursor::new(bs).read_i32::<BigEndian>().ok())
+/// converts a frame received from landlordd once the process has launched to its `Input`.
+/// `Heartbeat`s carry no input, so are to be handled by the caller.
+pub fn launched_input(frame: Frame) -> io::Result<Input> {
+    match frame {
+        Frame::StdErr(payload) => Ok(Input::StdErr(payload)),
+
+        Frame::StdOut(payload) => Ok(Input::StdOut(payload)),
+
+        Frame::Exit(code) => Ok(Input::Exit(ExitStatus::code(code))),
+
+        Frame::ExitStatus(status) => Ok(Input::Exit(status)),
+
+        other => Err(daemon_error(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unexpected frame: {:?}", other),
+        ))),
+    }
 }
 
-/// Creates the first line of data that is sent to landlordd when loading an app.
-pub fn app_cmdline<S: AsRef<str>>(
-    class_path_with_names: &[(String, String)],
-    props: &[(S, S)],
-    class: &S,
-    args: &[S],
-) -> String {
-    let props = props
-        .iter()
-        .map(|&(ref n, ref v)| format!("-D{}={}", n.as_ref(), v.as_ref()))
-        .collect::<Vec<String>>()
-        .join("\u{0000}");
-
-    format!(
-        "l{}-cp\u{0000}{}\u{0000}{}{}\n",
-        if props == "" {
-            props
+/// reads the process id from the provided `stream`. Its errors are those of communicating with
+/// landlordd (see `daemon_error`), and landlordd closing the connection before sending it is
+/// reported as `UnexpectedEof`, i.e. as landlordd having gone away.
+pub fn read_pid_handler(stream: &mut dyn Read) -> io::Result<i32> {
+    // exactly four bytes are read, as whatever follows is read by `read_handler`
+
+    let bytes = read_bytes(stream, 4).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => daemon_error(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "landlordd closed the connection before launching the program",
+        )),
+
+        _ => daemon_error(e),
+    })?;
+
+    match ClientCodec::new().decode(&mut BytesMut::from(bytes)) {
+        Ok(Some(Frame::Pid(pid))) => Ok(pid),
+
+        _ => Err(daemon_error(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Unable to parse pid",
+        ))),
+    }
+}
+
+/// The version of the protocol that this client speaks. Version 0 is the legacy protocol,
+/// i.e. that of a landlordd which doesn't understand `Hello`.
+pub const PROTOCOL_VERSION: i32 = 1;
+
+/// The capabilities that this client implements, and so advertises in its `Hello`.
+/// `ZstdCompression` is also advertised when built with the "zstd-compression" feature.
+pub const CLIENT_CAPABILITIES: [Capability; 11] = [
+    Capability::ContentAddressing,
+    Capability::EnvForwarding,
+    Capability::ExitReasons,
+    Capability::FramedStdin,
+    Capability::GzipCompression,
+    Capability::Heartbeats,
+    Capability::LengthPrefixedDescriptor,
+    Capability::Modules,
+    Capability::Assertions,
+    Capability::JavaAgents,
+    Capability::ResourceHints,
+];
+
+/// Optional protocol features, which are only used when both sides support them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Capability {
+    Assertions,
+    GzipCompression,
+    ContentAddressing,
+    EnvForwarding,
+    ExitReasons,
+    FramedStdin,
+    Heartbeats,
+    JavaAgents,
+    LengthPrefixedDescriptor,
+    Modules,
+    ResourceHints,
+    ZstdCompression,
+}
+
+const ALL_CAPABILITIES: [Capability; 12] = [
+    Capability::Assertions,
+    Capability::GzipCompression,
+    Capability::ContentAddressing,
+    Capability::EnvForwarding,
+    Capability::ExitReasons,
+    Capability::FramedStdin,
+    Capability::Heartbeats,
+    Capability::JavaAgents,
+    Capability::LengthPrefixedDescriptor,
+    Capability::Modules,
+    Capability::ResourceHints,
+    Capability::ZstdCompression,
+];
+
+impl Capability {
+    /// The bit that represents this capability within a `Hello`
+    fn bit(&self) -> i32 {
+        match *self {
+            Capability::GzipCompression => 1,
+            Capability::EnvForwarding => 1 << 1,
+            Capability::ExitReasons => 1 << 2,
+            Capability::FramedStdin => 1 << 3,
+            Capability::LengthPrefixedDescriptor => 1 << 4,
+            Capability::Heartbeats => 1 << 5,
+            Capability::ZstdCompression => 1 << 6,
+            Capability::ContentAddressing => 1 << 7,
+            Capability::Modules => 1 << 8,
+            Capability::Assertions => 1 << 9,
+            Capability::JavaAgents => 1 << 10,
+            Capability::ResourceHints => 1 << 11,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Capability::Assertions => "assertion switches",
+            Capability::GzipCompression => "gzip compression",
+            Capability::ContentAddressing => "content-addressed entries",
+            Capability::EnvForwarding => "environment forwarding",
+            Capability::ExitReasons => "exit reasons",
+            Capability::FramedStdin => "framed stdin",
+            Capability::Heartbeats => "heartbeats",
+            Capability::JavaAgents => "java agents",
+            Capability::LengthPrefixedDescriptor => "length-prefixed launch descriptors",
+            Capability::Modules => "the module system",
+            Capability::ResourceHints => "resource hints",
+            Capability::ZstdCompression => "zstd compression",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A protocol version and the capabilities supported at it, as exchanged via `Hello`. Once
+/// negotiated, `capabilities` holds those that both sides support.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Protocol {
+    pub version: i32,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Protocol {
+    /// The protocol of this client
+    pub fn client() -> Protocol {
+        let mut capabilities = CLIENT_CAPABILITIES.to_vec();
+
+        if cfg!(feature = "zstd-compression") {
+            capabilities.push(Capability::ZstdCompression);
+        }
+
+        Protocol {
+            version: PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+
+    /// The protocol of a landlordd that doesn't understand `Hello`
+    pub fn legacy() -> Protocol {
+        Protocol {
+            version: 0,
+            capabilities: vec![],
+        }
+    }
+
+    /// Returns the protocol to use given landlordd's `Hello`, i.e. the lower of the two
+    /// versions and the capabilities common to both.
+    pub fn negotiate(&self, daemon: &Protocol) -> Protocol {
+        Protocol {
+            version: self.version.min(daemon.version),
+            capabilities: self
+                .capabilities
+                .iter()
+                .filter(|c| daemon.supports(**c))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Fails with an error explaining that landlordd lacks `capability` if it isn't supported.
+    /// `reason` describes what requires it.
+    pub fn require(&self, capability: Capability, reason: &str) -> io::Result<()> {
+        if self.supports(capability) {
+            Ok(())
         } else {
-            format!("{}\u{0000}", props)
-        },
-        class_path_with_names
-            .iter()
-            .map(|&(_, ref name)| name.as_ref())
-            .collect::<Vec<&str>>()
-            .join(":"),
-        class.as_ref(),
-        if args.is_empty() {
-            format!("")
+            Err(daemon_error(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} requires {}, which landlordd (protocol version {}) does not support",
+                    reason, capability, self.version
+                ),
+            )))
+        }
+    }
+
+    /// The compression to upload the tar with, if any. zstd is preferred, as it's both faster
+    /// and more effective than gzip.
+    pub fn compression(&self) -> Option<Compression> {
+        if self.supports(Capability::ZstdCompression) {
+            Some(Compression::Zstd)
+        } else if self.supports(Capability::GzipCompression) {
+            Some(Compression::Gzip)
         } else {
-            format!(
-                "\u{0000}{}",
-                args.iter()
-                    .map(|a| a.as_ref())
-                    .collect::<Vec<&str>>()
-                    .join("\u{0000}")
-            )
+            None
         }
-    )
+    }
+
+    /// The launch descriptor format to use
+    pub fn descriptor_format(&self) -> DescriptorFormat {
+        if self.supports(Capability::LengthPrefixedDescriptor) {
+            DescriptorFormat::LengthPrefixed
+        } else {
+            DescriptorFormat::Legacy
+        }
+    }
 }
 
-/// Given class path entries, returns a new vector containing entries
-/// as a tuple, each element: (path, name to store in tar file)
-pub fn class_path_with_names<S: AsRef<str>>(class_path: &[S]) -> Vec<(String, String)> {
-    class_path
-        .iter()
-        .enumerate()
-        .map(|(ref i, e)| (e.as_ref().to_string(), i.to_string()))
-        .collect()
+/// A message of landlord's wire protocol. Clients send a `Launch` (followed by the tar and
+/// stdin, which aren't framed), `Kill` or `Probe`. In response to a `Launch`, landlordd
+/// sends the `Pid`, any number of `StdOut` and `StdErr` frames, and then an `Exit`. It
+/// responds to messages it doesn't understand, such as `Probe`, with `Unrecognized`.
+///
+/// A client may begin a connection with a `Hello`, to which landlordd responds with its own.
+/// A landlordd that predates `Hello` responds with `Unrecognized` and closes the connection.
+///
+/// When `FramedStdin` has been negotiated, whatever follows the tar is framed as well: stdin
+/// is sent as `StdIn` frames and its end as `StdInClosed` rather than a write-half shutdown,
+/// and `Signal` and `WindowSize` are sent upon the same connection rather than a new one.
+/// Such a session may also precede its `Launch` with a `Console`, which conveys the size of the
+/// terminal that the process runs upon. `System.console()` can't be provided as the JVM has the
+/// one console, so changes in size reach the process as a `SIGWINCH` upon its `trap`.
+///
+/// When `ExitReasons` has been negotiated, landlordd ends with an `ExitStatus` rather than an
+/// `Exit`, which also conveys why the process exited.
+///
+/// While the process runs, landlordd sends something whenever it has otherwise been quiet for
+/// its heartbeat interval (a second by default), so that the client can tell a landlordd that
+/// has stopped responding from a process that's merely quiet. When `Heartbeats` has been
+/// negotiated that's a `Heartbeat`, and otherwise an empty `StdOut`.
+///
+/// When `GzipCompression` or `ZstdCompression` has been negotiated, a `Launch` may be preceded
+/// by a `Compressed`, in which case the tar is compressed accordingly and sent as a sequence of
+/// length-prefixed chunks, the last of which is empty.
+///
+/// When `EnvForwarding` has been negotiated, a `Launch` may be preceded by an `Env`, which
+/// holds the environment variables (name, value) to set for the process.
+///
+/// When `ContentAddressing` has been negotiated, a `Launch` may be preceded by a `Manifest`,
+/// which holds the SHA-256 digest of each entry (name, digest). Having read the `Launch`,
+/// landlordd responds with the digests it already has as `Present`, and the tar that follows
+/// holds only the remaining entries. Those that are present are installed from its store.
+#[derive(PartialEq, Debug)]
+pub enum Frame {
+    Compressed(Compression),
+    Console { rows: u16, cols: u16 },
+    Env(Vec<(String, String)>),
+    Exit(i32),
+    ExitStatus(ExitStatus),
+    Heartbeat,
+    Hello(Protocol),
+    Kill { pid: i32, signal: i32 },
+    Launch(DescriptorFormat, Vec<String>),
+    Manifest(Vec<(String, String)>),
+    Pid(i32),
+    Present(Vec<String>),
+    Probe,
+    Signal(i32),
+    StdErr(Vec<u8>),
+    StdIn(Vec<u8>),
+    StdInClosed,
+    StdOut(Vec<u8>),
+    Unrecognized,
+    WindowSize { rows: u16, cols: u16 },
 }
 
-/// given a reader, reads a landlord payload, i.e. a 4-byte encoded (big endian) size followed
-/// by that number of bytes.
-fn read_payload<R>(reader: &mut R) -> io::Result<Vec<u8>>
-where
-    R: FnMut(usize) -> io::Result<Vec<u8>>,
-{
-    reader(4)
-        .and_then(|bs| decode_i32(&bs))
-        .and_then(|size| reader(size as usize))
+/// Decodes frames from the bytes read so far, removing those of each frame that it returns.
+/// With the `async` feature, the codecs also implement tokio's `Decoder` and `Encoder`.
+pub trait FrameDecoder {
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>>;
 }
 
-fn decode_i32(vec: &Vec<u8>) -> io::Result<i32> {
-    io::Cursor::new(vec).read_i32::<BigEndian>()
+/// Encodes the frames that clients send, and decodes those that landlordd responds with.
+/// `new` expects landlordd's first responses, i.e. `Hello`, `Present`, `Pid` or
+/// `Unrecognized`, whereas `launched` is for a connection whose `Pid` has already been read.
+/// Process ids are less than 2^24, so cannot begin with the `h`, `p` or `?` of the others.
+pub struct ClientCodec {
+    pid_decoded: bool,
 }
 
-fn encode_i32(value: i32) -> io::Result<Vec<u8>> {
-    let mut buf = vec![];
+impl ClientCodec {
+    pub fn new() -> ClientCodec {
+        ClientCodec { pid_decoded: false }
+    }
 
-    buf.write_i32::<BigEndian>(value).map(|_| buf)
+    pub fn launched() -> ClientCodec {
+        ClientCodec { pid_decoded: true }
+    }
 }
 
-#[test]
-fn test_app_cmdline_no_args() {
-    assert_eq!(
-        app_cmdline(
-            &[
-                ("/test1/one".to_string(), "0".to_string()),
-                ("/test1/two".to_string(), "1".to_string()),
-            ],
-            &[],
-            &"com.example.HelloWorld1",
-            &[]
-        ),
-        "l-cp\u{0000}0:1\u{0000}com.example.HelloWorld1\n".to_string()
-    )
-}
+impl FrameDecoder for ClientCodec {
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+        let decoded = match src.first().cloned() {
+            None => None,
 
-#[test]
-fn test_app_cmdline_with_args() {
-    assert_eq!(
-        app_cmdline(
-            &[
-                ("/test2/one".to_string(), "0".to_string()),
-                ("/test2/two".to_string(), "1".to_string()),
-            ],
-            &[],
-            &"com.example.HelloWorld2",
-            &["argone", "arg two"]
-        ).as_str(),
-        "l-cp\u{0000}0:1\u{0000}com.example.HelloWorld2\u{0000}argone\u{0000}arg two\n"
-    )
+            Some(b'?') if !self.pid_decoded => {
+                if src.len() >= 3 {
+                    Some((Frame::Unrecognized, 3))
+                } else {
+                    None
+                }
+            }
+
+            Some(b'h') if !self.pid_decoded => peek_hello(src),
+
+            Some(b'p') if !self.pid_decoded => {
+                peek_fields(src)?.map(|(digests, end)| (Frame::Present(digests), end))
+            }
+
+            Some(_) if !self.pid_decoded => peek_i32(src, 0).map(|pid| (Frame::Pid(pid), 4)),
+
+            Some(b'b') => Some((Frame::Heartbeat, 1)),
+
+            Some(b'e') => peek_field(src, 1)?.map(|(p, end)| (Frame::StdErr(p.to_vec()), end)),
+
+            Some(b'o') => peek_field(src, 1)?.map(|(p, end)| (Frame::StdOut(p.to_vec()), end)),
+
+            Some(b'x') => peek_i32(src, 1).map(|code| (Frame::Exit(code), 5)),
+
+            Some(b'X') => match (peek_i32(src, 1), peek_i32(src, 5)) {
+                (Some(code), Some(reason)) => peek_field(src, 9)?.map(|(message, end)| {
+                    let message = String::from_utf8_lossy(message).into_owned();
+                    let status = ExitStatus {
+                        code,
+                        reason: ExitReason::from_code(reason),
+                        message: Some(message).filter(|m| !m.is_empty()),
+                    };
+
+                    (Frame::ExitStatus(status), end)
+                }),
+
+                _ => None,
+            },
+
+            Some(other) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown code: {}", other),
+                ))
+            }
+        };
+
+        Ok(decoded.map(|(frame, len)| {
+            src.split_to(len);
+
+            if let Frame::Pid(_) = frame {
+                self.pid_decoded = true;
+            }
+
+            frame
+        }))
+    }
 }
 
-#[test]
-fn test_app_cmdline_with_args_props() {
-    assert_eq!(
-        app_cmdline(
-            &[
-                ("/test2/one".to_string(), "0".to_string()),
-                ("/test2/two".to_string(), "1".to_string()),
-            ],
-            &[("one", "#1!"), ("two", "#2!")],
-            &"com.example.HelloWorld2",
-            &["argone", "arg two"]
-        ).as_str(),
+#[cfg(feature = "async")]
+impl tokio_codec::Decoder for ClientCodec {
+    type Item = Frame;
+    type Error = io::Error;
 
-        "l-Done=#1!\u{0000}-Dtwo=#2!\u{0000}-cp\u{0000}0:1\u{0000}com.example.HelloWorld2\u{0000}argone\u{0000}arg two\n"
-    )
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+        FrameDecoder::decode(self, src)
+    }
 }
 
-#[test]
-fn test_class_path_with_names() {
-    assert_eq!(
-        class_path_with_names(&["/test/one", "/test/two"]),
-        vec![
-            ("/test/one".to_string(), "0".to_string()),
-            ("/test/two".to_string(), "1".to_string()),
-        ]
-    );
+#[cfg(feature = "async")]
+impl tokio_codec::Encoder for ClientCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+        encode_frame(frame, dst)
+    }
 }
 
-#[test]
-fn test_decode_i32_invalid() {
-    assert!(decode_i32(&vec![]).is_err());
-    assert!(decode_i32(&vec![0]).is_err());
-    assert!(decode_i32(&vec![0, 0]).is_err());
-    assert!(decode_i32(&vec![0, 0, 0]).is_err());
+/// Decodes the frames that clients send, and encodes those that landlordd responds with. Once
+/// a `Launch` has been decoded, no further frames are, leaving the tar and stdin that follow
+/// it in the buffer. `framed_stdin` decodes the frames that follow the tar of a session that
+/// negotiated `FramedStdin`.
+pub struct DaemonCodec {
+    launched: bool,
+    framed_stdin: bool,
 }
 
-#[test]
-fn test_decode_i32_valid() {
-    assert_eq!(decode_i32(&vec![0, 0, 0, 0]).ok(), Some(0));
-    assert_eq!(decode_i32(&vec![0, 0, 0, 1]).ok(), Some(1));
-    assert_eq!(decode_i32(&vec![1, 0, 0, 0]).ok(), Some(16777216));
-    assert_eq!(decode_i32(&vec![1, 0, 0, 1]).ok(), Some(16777217));
+impl DaemonCodec {
+    pub fn new() -> DaemonCodec {
+        DaemonCodec {
+            launched: false,
+            framed_stdin: false,
+        }
+    }
+
+    pub fn framed_stdin() -> DaemonCodec {
+        DaemonCodec {
+            launched: true,
+            framed_stdin: true,
+        }
+    }
 }
 
-#[test]
-fn test_encode_i32_valid() {
-    assert_eq!(encode_i32(0).ok(), Some(vec![0, 0, 0, 0]));
-    assert_eq!(encode_i32(1).ok(), Some(vec![0, 0, 0, 1]));
-    assert_eq!(encode_i32(16777216).ok(), Some(vec![1, 0, 0, 0]));
-    assert_eq!(encode_i32(16777217).ok(), Some(vec![1, 0, 0, 1]));
+impl FrameDecoder for DaemonCodec {
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+        if self.launched && !self.framed_stdin {
+            return Ok(None);
+        }
+
+        let decoded = match src.first().cloned() {
+            None => None,
+
+            Some(b'c') if self.framed_stdin => Some((Frame::StdInClosed, 1)),
+
+            Some(b'i') if self.framed_stdin => {
+                peek_field(src, 1)?.map(|(p, end)| (Frame::StdIn(p.to_vec()), end))
+            }
+
+            Some(b's') if self.framed_stdin => {
+                peek_i32(src, 1).map(|signal| (Frame::Signal(signal), 5))
+            }
+
+            Some(b'w') if self.framed_stdin => match (peek_i32(src, 1), peek_i32(src, 5)) {
+                (Some(rows), Some(cols)) => {
+                    let (rows, cols) = (rows as u16, cols as u16);
+
+                    Some((Frame::WindowSize { rows, cols }, 9))
+                }
+                _ => None,
+            },
+
+            Some(b'?') => Some((Frame::Probe, 1)),
+
+            Some(b'z') => match peek_i32(src, 1) {
+                Some(code) => match Compression::from_code(code) {
+                    Some(compression) => Some((Frame::Compressed(compression), 5)),
+
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Unknown compression: {}", code),
+                        ))
+                    }
+                },
+
+                None => None,
+            },
+
+            Some(b'E') => {
+                peek_pairs(src, "environment variable")?.map(|(env, end)| (Frame::Env(env), end))
+            }
+
+            Some(b'h') => peek_hello(src),
+
+            Some(b't') => match (peek_i32(src, 1), peek_i32(src, 5)) {
+                (Some(rows), Some(cols)) => {
+                    let (rows, cols) = (rows as u16, cols as u16);
+
+                    Some((Frame::Console { rows, cols }, 9))
+                }
+                _ => None,
+            },
+
+            Some(b'k') => match (peek_i32(src, 1), peek_i32(src, 5)) {
+                (Some(pid), Some(signal)) => Some((Frame::Kill { pid, signal }, 9)),
+                _ => None,
+            },
+
+            Some(b'l') => match src.iter().position(|b| *b == b'\n') {
+                Some(end) => {
+                    let fields = decode_str(&src[1..end])?
+                        .split('\u{0000}')
+                        .filter(|_| end > 1)
+                        .map(|f| f.to_string())
+                        .collect();
+
+                    Some((Frame::Launch(DescriptorFormat::Legacy, fields), end + 1))
+                }
+
+                None => None,
+            },
+
+            Some(b'L') => peek_fields(src)?.map(|(fields, end)| {
+                (Frame::Launch(DescriptorFormat::LengthPrefixed, fields), end)
+            }),
+
+            Some(b'm') => peek_pairs(src, "manifest entry")?
+                .map(|(manifest, end)| (Frame::Manifest(manifest), end)),
+
+            Some(other) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown code: {}", other),
+                ))
+            }
+        };
+
+        Ok(decoded.map(|(frame, len)| {
+            src.split_to(len);
+
+            if let Frame::Launch(_, _) = frame {
+                self.launched = true;
+            }
+
+            frame
+        }))
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio_codec::Decoder for DaemonCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+        FrameDecoder::decode(self, src)
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio_codec::Encoder for DaemonCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+        encode_frame(frame, dst)
+    }
+}
+
+/// Appends the encoding of `frame` to `dst`. A legacy `Launch` is rejected if any of its
+/// fields contain a newline or NUL, as landlordd would misinterpret them.
+pub fn encode_frame(frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+    match frame {
+        Frame::Compressed(compression) => {
+            dst.extend_from_slice(b"z");
+            dst.extend_from_slice(&encode_i32(compression.code())?);
+        }
+
+        Frame::Console { rows, cols } => {
+            dst.extend_from_slice(b"t");
+            dst.extend_from_slice(&encode_i32(i32::from(rows))?);
+            dst.extend_from_slice(&encode_i32(i32::from(cols))?);
+        }
+
+        Frame::Env(env) => {
+            encode_fields(b'E', &encode_pairs(&env), dst)?;
+        }
+
+        Frame::Exit(code) => {
+            dst.extend_from_slice(b"x");
+            dst.extend_from_slice(&encode_i32(code)?);
+        }
+
+        Frame::ExitStatus(status) => {
+            let message = status.message.unwrap_or_default();
+
+            dst.extend_from_slice(b"X");
+            dst.extend_from_slice(&encode_i32(status.code)?);
+            dst.extend_from_slice(&encode_i32(status.reason.code())?);
+            dst.extend_from_slice(&encode_i32(message.len() as i32)?);
+            dst.extend_from_slice(message.as_bytes());
+        }
+
+        Frame::Heartbeat => {
+            dst.extend_from_slice(b"b");
+        }
+
+        Frame::Hello(protocol) => {
+            let capabilities = protocol
+                .capabilities
+                .iter()
+                .fold(0, |bits, c| bits | c.bit());
+
+            dst.extend_from_slice(b"h");
+            dst.extend_from_slice(&encode_i32(protocol.version)?);
+            dst.extend_from_slice(&encode_i32(capabilities)?);
+        }
+
+        Frame::Kill { pid, signal } => {
+            dst.extend_from_slice(b"k");
+            dst.extend_from_slice(&encode_i32(pid)?);
+            dst.extend_from_slice(&encode_i32(signal)?);
+        }
+
+        Frame::Launch(DescriptorFormat::Legacy, fields) => {
+            if let Some(field) = fields
+                .iter()
+                .find(|f| f.contains('\n') || f.contains('\u{0000}'))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{:?} contains a newline or NUL character, which landlordd does not support",
+                        field
+                    ),
+                ));
+            }
+
+            dst.extend_from_slice(format!("l{}\n", fields.join("\u{0000}")).as_bytes());
+        }
+
+        Frame::Launch(DescriptorFormat::LengthPrefixed, fields) => {
+            encode_fields(b'L', &fields, dst)?;
+        }
+
+        Frame::Manifest(manifest) => {
+            encode_fields(b'm', &encode_pairs(&manifest), dst)?;
+        }
+
+        Frame::Pid(pid) => {
+            dst.extend_from_slice(&encode_i32(pid)?);
+        }
+
+        Frame::Present(digests) => {
+            encode_fields(b'p', &digests, dst)?;
+        }
+
+        Frame::Probe => {
+            dst.extend_from_slice(b"?");
+        }
+
+        Frame::Signal(signal) => {
+            dst.extend_from_slice(b"s");
+            dst.extend_from_slice(&encode_i32(signal)?);
+        }
+
+        Frame::StdErr(payload) => {
+            dst.extend_from_slice(b"e");
+            dst.extend_from_slice(&encode_i32(payload.len() as i32)?);
+            dst.extend_from_slice(&payload);
+        }
+
+        Frame::StdIn(payload) => {
+            dst.extend_from_slice(b"i");
+            dst.extend_from_slice(&encode_i32(payload.len() as i32)?);
+            dst.extend_from_slice(&payload);
+        }
+
+        Frame::StdInClosed => {
+            dst.extend_from_slice(b"c");
+        }
+
+        Frame::StdOut(payload) => {
+            dst.extend_from_slice(b"o");
+            dst.extend_from_slice(&encode_i32(payload.len() as i32)?);
+            dst.extend_from_slice(&payload);
+        }
+
+        Frame::Unrecognized => {
+            dst.extend_from_slice(b"???");
+        }
+
+        Frame::WindowSize { rows, cols } => {
+            dst.extend_from_slice(b"w");
+            dst.extend_from_slice(&encode_i32(i32::from(rows))?);
+            dst.extend_from_slice(&encode_i32(i32::from(cols))?);
+        }
+    }
+
+    Ok(())
+}
+
+/// The error for a landlordd that has been silent for `timeout`, and so isn't sending
+/// `Heartbeat`s (or empty `StdOut`s) either
+pub fn heartbeat_timed_out(timeout: time::Duration) -> io::Error {
+    let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+
+    daemon_error(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!(
+            "landlordd stopped responding, as it has been silent for {}ms",
+            millis
+        ),
+    ))
+}
+
+/// An error in communicating with landlordd, as opposed to one that occurred locally
+struct DaemonError(io::Error);
+
+impl fmt::Debug for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.get_ref() {
+            Some(e) => fmt::Debug::fmt(e, f),
+            None => fmt::Debug::fmt(&self.0, f),
+        }
+    }
+}
+
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl error::Error for DaemonError {
+    fn description(&self) -> &str {
+        "landlordd error"
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Marks `error`, keeping its kind, as having occurred in communicating with landlordd, e.g.
+/// upon its socket or in what it sent, rather than locally, e.g. in reading the class path or
+/// writing to stdout. Only the former are taken to say anything about landlordd.
+pub fn daemon_error(error: io::Error) -> io::Error {
+    if is_daemon_error(&error) {
+        error
+    } else {
+        io::Error::new(error.kind(), DaemonError(error))
+    }
+}
+
+/// Returns whether `error` was marked by `daemon_error`
+pub fn is_daemon_error(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .map(|e| e.is::<DaemonError>())
+        .unwrap_or(false)
+}
+
+/// Reads from `reader` into `buf` until `decoder` yields a frame. Returns `None` if the stream
+/// ends between frames, and an error if it ends part way through one.
+pub fn read_frame<R, D>(
+    reader: &mut R,
+    decoder: &mut D,
+    buf: &mut BytesMut,
+) -> io::Result<Option<Frame>>
+where
+    R: Read + ?Sized,
+    D: FrameDecoder,
+{
+    let mut chunk = [0; 8192];
+
+    loop {
+        if let Some(item) = decoder.decode(buf)? {
+            return Ok(Some(item));
+        }
+
+        let num = match reader.read(&mut chunk) {
+            Ok(num) => num,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        if num == 0 {
+            return if buf.is_empty() {
+                Ok(None)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "stream ended part way through a frame",
+                ))
+            };
+        }
+
+        buf.extend_from_slice(&chunk[..num]);
+    }
+}
+
+/// What landlordd is to execute once the class and module paths have been installed
+#[derive(Clone, PartialEq, Debug)]
+pub enum Main {
+    Class(String),
+    Module(String),
+}
+
+/// Describes a program to be launched by landlordd, i.e. the paths that are to be streamed
+/// to it along with the remainder of the `java` command line. `agents` holds the jar path
+/// and options of each `-javaagent`, `hints` the `-Xms`, `-Xmx` and `-Xss` flags, `options`
+/// those of the module system, and `env` the environment variables to forward.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Launch {
+    pub agents: Vec<(String, Option<String>)>,
+    pub args: Vec<String>,
+    pub assertions: Vec<String>,
+    pub class_path: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub hints: Vec<String>,
+    pub main: Main,
+    pub module_path: Vec<String>,
+    pub options: Vec<String>,
+    pub props: Vec<(String, String)>,
+}
+
+impl Launch {
+    /// Returns every path that is to be written to the tar, as a tuple of
+    /// (path, name to store in tar file). Class path entries come first, followed by
+    /// module path entries and then agent jars.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let paths: Vec<&String> = self
+            .class_path
+            .iter()
+            .chain(self.module_path.iter())
+            .chain(self.agents.iter().map(|&(ref jar, _)| jar))
+            .collect();
+
+        class_path_with_names(&paths)
+    }
+
+    /// Returns the module system options that are used, e.g. `--module-path` and `-m`, which
+    /// require landlordd to support `Modules`
+    pub fn module_options(&self) -> Vec<&str> {
+        let mut used = vec![];
+
+        if !self.module_path.is_empty() {
+            used.push("--module-path");
+        }
+
+        if let Main::Module(_) = self.main {
+            used.push("-m");
+        }
+
+        for option in &self.options {
+            let name = option.splitn(2, '=').next().unwrap_or("");
+
+            if name.starts_with("--add-") && !used.contains(&name) {
+                used.push(name);
+            }
+        }
+
+        used
+    }
+
+    /// Returns this launch without the assertion switches and resource hints that `protocol`
+    /// doesn't support, along with a notice for each that is dropped. These only tune how the
+    /// program runs, so unlike other options it can still be launched without them.
+    pub fn supported_by(&self, protocol: &Protocol) -> (Launch, Vec<String>) {
+        let mut launch = self.clone();
+        let mut notices = vec![];
+
+        if !protocol.supports(Capability::Assertions) {
+            for flag in launch.assertions.drain(..) {
+                notices.push(format!(
+                    "landlord: ignoring {} as landlordd does not support {}",
+                    flag,
+                    Capability::Assertions
+                ));
+            }
+        }
+
+        if !protocol.supports(Capability::ResourceHints) {
+            for flag in launch.hints.drain(..) {
+                notices.push(format!(
+                    "landlord: ignoring {} as landlordd does not support {}",
+                    flag,
+                    Capability::ResourceHints
+                ));
+            }
+        }
+
+        (launch, notices)
+    }
+}
+
+/// The algorithms that the tar may be compressed with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the level to compress at given that requested, if any, which is limited to
+    /// those that the algorithm supports
+    pub fn level(&self, requested: Option<u32>) -> u32 {
+        match *self {
+            Compression::Gzip => requested.unwrap_or(6).min(9),
+            Compression::Zstd => requested.unwrap_or(3).max(1).min(22),
+        }
+    }
+
+    fn from_code(code: i32) -> Option<Compression> {
+        match code {
+            1 => Some(Compression::Gzip),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match *self {
+            Compression::Gzip => 1,
+            Compression::Zstd => 2,
+        }
+    }
+}
+
+/// The encodings of the launch descriptor that landlordd may accept. `Legacy` separates its
+/// fields with NUL and ends with a newline, so it cannot represent fields containing either.
+/// `LengthPrefixed` prefixes the number of fields, and each field, with its length.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DescriptorFormat {
+    Legacy,
+    LengthPrefixed,
+}
+
+/// Encodes the launch descriptor that is sent to landlordd when loading an app, preceded by
+/// its environment section if there are variables to forward. Fields that cannot be
+/// represented in `format` are rejected, rather than being misinterpreted by landlordd.
+pub fn encode_descriptor(launch: &Launch, format: DescriptorFormat) -> io::Result<Vec<u8>> {
+    let mut descriptor = BytesMut::new();
+
+    if !launch.env.is_empty() {
+        encode_frame(Frame::Env(launch.env.clone()), &mut descriptor)?;
+    }
+
+    encode_frame(
+        Frame::Launch(format, launch_fields(launch)),
+        &mut descriptor,
+    )
+    .map(|_| descriptor.to_vec())
+}
+
+/// Creates the first line of data that is sent to landlordd when loading an app, i.e. the
+/// launch descriptor in the legacy format. See `encode_descriptor`.
+pub fn app_cmdline(launch: &Launch) -> String {
+    format!("l{}\n", launch_fields(launch).join("\u{0000}"))
+}
+
+/// Returns the fields of the launch descriptor, i.e. the `java` command line that landlordd
+/// is to run, with paths replaced by their names in the tar.
+pub fn launch_fields(launch: &Launch) -> Vec<String> {
+    let entries = launch.entries();
+    let (class_path, rest) = entries.split_at(launch.class_path.len());
+    let (module_path, agents) = rest.split_at(launch.module_path.len());
+    let names = |entries: &[(String, String)]| {
+        entries
+            .iter()
+            .map(|&(_, ref name)| name.as_ref())
+            .collect::<Vec<&str>>()
+            .join(":")
+    };
+
+    let mut fields = launch
+        .props
+        .iter()
+        .map(|&(ref n, ref v)| format!("-D{}={}", n, v))
+        .collect::<Vec<String>>();
+
+    fields.extend(agents.iter().zip(launch.agents.iter()).map(
+        |(&(_, ref name), &(_, ref options))| match *options {
+            Some(ref options) => format!("-javaagent:{}={}", name, options),
+            None => format!("-javaagent:{}", name),
+        },
+    ));
+
+    fields.extend(launch.assertions.iter().cloned());
+    fields.extend(launch.hints.iter().cloned());
+    fields.extend(launch.options.iter().cloned());
+    fields.push("-cp".to_string());
+    fields.push(names(class_path));
+
+    if !module_path.is_empty() {
+        fields.push("--module-path".to_string());
+        fields.push(names(module_path));
+    }
+
+    match launch.main {
+        Main::Class(ref class) => {
+            fields.push(class.clone());
+        }
+
+        Main::Module(ref module) => {
+            fields.push("-m".to_string());
+            fields.push(module.clone());
+        }
+    }
+
+    fields.extend(launch.args.iter().cloned());
+
+    fields
+}
+
+/// Describes, in a human readable form, the descriptor that `app_cmdline` creates for
+/// `launch`, i.e. its fields and which path each tar entry name refers to.
+pub fn describe_launch(launch: &Launch) -> String {
+    let entries = launch.entries();
+    let module_path_start = launch.class_path.len();
+    let agents_start = module_path_start + launch.module_path.len();
+
+    let mut description = "fields:\n".to_string();
+
+    for field in launch_fields(launch) {
+        description.push_str(&format!("  {}\n", field));
+    }
+
+    description.push_str("properties:\n");
+
+    for &(ref name, ref value) in &launch.props {
+        description.push_str(&format!("  {}={}\n", name, value));
+    }
+
+    description.push_str("environment:\n");
+
+    for &(ref name, ref value) in &launch.env {
+        description.push_str(&format!("  {}={}\n", name, value));
+    }
+
+    description.push_str("entries:\n");
+
+    for (i, &(ref path, ref name)) in entries.iter().enumerate() {
+        let kind = if i < module_path_start {
+            "class path"
+        } else if i < agents_start {
+            "module path"
+        } else {
+            "agent"
+        };
+
+        description.push_str(&format!("  {} -> {} ({})\n", name, path, kind));
+    }
+
+    match launch.main {
+        Main::Class(ref class) => description.push_str(&format!("main class: {}\n", class)),
+        Main::Module(ref module) => description.push_str(&format!("main module: {}\n", module)),
+    }
+
+    description.push_str("args:\n");
+
+    for arg in &launch.args {
+        description.push_str(&format!("  {}\n", arg));
+    }
+
+    description
+}
+
+/// Given class path entries, returns a new vector containing entries
+/// as a tuple, each element: (path, name to store in tar file)
+pub fn class_path_with_names<S: AsRef<str>>(class_path: &[S]) -> Vec<(String, String)> {
+    class_path
+        .iter()
+        .enumerate()
+        .map(|(ref i, e)| (e.as_ref().to_string(), i.to_string()))
+        .collect()
+}
+
+/// Returns the big endian `i32` at `offset` within `src`, if it has been received
+fn peek_i32(src: &[u8], offset: usize) -> Option<i32> {
+    src.get(offset..offset + 4)
+        .and_then(|bs| decode_i32(bs).ok())
+}
+
+/// Returns a `Hello` frame and its length, if it has been received in full. Capabilities that
+/// aren't known to this client are ignored.
+fn peek_hello(src: &[u8]) -> Option<(Frame, usize)> {
+    match (peek_i32(src, 1), peek_i32(src, 5)) {
+        (Some(version), Some(bits)) => {
+            let capabilities = ALL_CAPABILITIES
+                .iter()
+                .filter(|c| bits & c.bit() != 0)
+                .cloned()
+                .collect();
+
+            Some((
+                Frame::Hello(Protocol {
+                    version,
+                    capabilities,
+                }),
+                9,
+            ))
+        }
+
+        _ => None,
+    }
+}
+
+/// Returns the length-prefixed field at `offset` within `src` and the offset following it,
+/// if it has been received in full
+fn peek_field(src: &[u8], offset: usize) -> io::Result<Option<(&[u8], usize)>> {
+    match peek_i32(src, offset) {
+        Some(len) if len < 0 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid length: {}", len),
+        )),
+
+        Some(len) => {
+            let end = offset + 4 + len as usize;
+
+            Ok(src.get(offset + 4..end).map(|field| (field, end)))
+        }
+
+        None => Ok(None),
+    }
+}
+
+/// Returns the fields of a length-prefixed launch descriptor and its length, if it has been
+/// received in full
+fn peek_fields(src: &[u8]) -> io::Result<Option<(Vec<String>, usize)>> {
+    let count = match peek_i32(src, 1) {
+        Some(count) => count,
+        None => return Ok(None),
+    };
+
+    let mut fields = vec![];
+    let mut offset = 5;
+
+    for _ in 0..count {
+        match peek_field(src, offset)? {
+            Some((field, end)) => {
+                fields.push(decode_str(field)?.to_string());
+                offset = end;
+            }
+
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some((fields, offset)))
+}
+
+/// Returns the name=value pairs of a length-prefixed frame, e.g. an `Env`, and its length, if it
+/// has been received in full. `what` describes a pair, should one lack a "=".
+fn peek_pairs(src: &[u8], what: &str) -> io::Result<Option<(Vec<(String, String)>, usize)>> {
+    let (fields, end) = match peek_fields(src)? {
+        Some(fields) => fields,
+        None => return Ok(None),
+    };
+
+    let pairs = fields
+        .iter()
+        .map(|f| {
+            let parts: Vec<&str> = f.splitn(2, "=").collect();
+
+            match parts.get(1) {
+                Some(value) => Ok((parts[0].to_string(), value.to_string())),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid {}: {}", what, f),
+                )),
+            }
+        })
+        .collect::<io::Result<Vec<(String, String)>>>()?;
+
+    Ok(Some((pairs, end)))
+}
+
+/// Writes `code` followed by the count of `fields` and then each as its length and bytes, i.e.
+/// the form that `peek_fields` reads
+fn encode_fields(code: u8, fields: &[String], dst: &mut BytesMut) -> io::Result<()> {
+    dst.extend_from_slice(&[code]);
+    dst.extend_from_slice(&encode_i32(fields.len() as i32)?);
+
+    for field in fields {
+        dst.extend_from_slice(&encode_i32(field.len() as i32)?);
+        dst.extend_from_slice(field.as_bytes());
+    }
+
+    Ok(())
+}
+
+/// Returns the fields of name=value `pairs`, i.e. the form that `peek_pairs` reads
+fn encode_pairs(pairs: &[(String, String)]) -> Vec<String> {
+    pairs
+        .iter()
+        .map(|&(ref name, ref value)| format!("{}={}", name, value))
+        .collect()
+}
+
+fn decode_str(bytes: &[u8]) -> io::Result<&str> {
+    str::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn decode_i32(bytes: &[u8]) -> io::Result<i32> {
+    io::Cursor::new(bytes).read_i32::<BigEndian>()
+}
+
+fn encode_i32(value: i32) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+
+    buf.write_i32::<BigEndian>(value).map(|_| buf)
+}
+
+#[cfg(test)]
+fn test_launch(props: &[(&str, &str)], main: Main, args: &[&str]) -> Launch {
+    Launch {
+        agents: vec![],
+        args: args.iter().map(|a| a.to_string()).collect(),
+        assertions: vec![],
+        class_path: vec!["/test/one".to_string(), "/test/two".to_string()],
+        env: vec![],
+        hints: vec![],
+        main,
+        module_path: vec![],
+        options: vec![],
+        props: props
+            .iter()
+            .map(|&(n, v)| (n.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_app_cmdline_no_args() {
+    assert_eq!(
+        app_cmdline(&test_launch(
+            &[],
+            Main::Class("com.example.HelloWorld1".to_string()),
+            &[]
+        )),
+        "l-cp\u{0000}0:1\u{0000}com.example.HelloWorld1\n".to_string()
+    )
+}
+
+#[test]
+fn test_app_cmdline_with_args() {
+    assert_eq!(
+        app_cmdline(&test_launch(
+            &[],
+            Main::Class("com.example.HelloWorld2".to_string()),
+            &["argone", "arg two"]
+        )).as_str(),
+        "l-cp\u{0000}0:1\u{0000}com.example.HelloWorld2\u{0000}argone\u{0000}arg two\n"
+    )
+}
+
+#[test]
+fn test_app_cmdline_with_args_props() {
+    assert_eq!(
+        app_cmdline(&test_launch(
+            &[("one", "#1!"), ("two", "#2!")],
+            Main::Class("com.example.HelloWorld2".to_string()),
+            &["argone", "arg two"]
+        )).as_str(),
+
+        "l-Done=#1!\u{0000}-Dtwo=#2!\u{0000}-cp\u{0000}0:1\u{0000}com.example.HelloWorld2\u{0000}argone\u{0000}arg two\n"
+    )
+}
+
+#[test]
+fn test_app_cmdline_module() {
+    let launch = Launch {
+        module_path: vec!["/test/mods".to_string()],
+        options: vec!["--add-modules=java.sql".to_string()],
+        ..test_launch(
+            &[("one", "1")],
+            Main::Module("com.acme.svc/com.acme.Main".to_string()),
+            &["argone"],
+        )
+    };
+
+    assert_eq!(
+        launch.entries(),
+        vec![
+            ("/test/one".to_string(), "0".to_string()),
+            ("/test/two".to_string(), "1".to_string()),
+            ("/test/mods".to_string(), "2".to_string()),
+        ]
+    );
+
+    assert_eq!(
+        app_cmdline(&launch).as_str(),
+        "l-Done=1\u{0000}--add-modules=java.sql\u{0000}-cp\u{0000}0:1\u{0000}--module-path\u{0000}2\u{0000}-m\u{0000}com.acme.svc/com.acme.Main\u{0000}argone\n"
+    )
+}
+
+#[test]
+fn test_app_cmdline_agents() {
+    let launch = Launch {
+        agents: vec![
+            ("/test/agent.jar".to_string(), None),
+            ("/test/other.jar".to_string(), Some("a=b".to_string())),
+        ],
+        assertions: vec!["-ea".to_string()],
+        hints: vec!["-Xmx1g".to_string()],
+        ..test_launch(&[], Main::Class("com.example.HelloWorld".to_string()), &[])
+    };
+
+    assert_eq!(
+        launch.entries(),
+        vec![
+            ("/test/one".to_string(), "0".to_string()),
+            ("/test/two".to_string(), "1".to_string()),
+            ("/test/agent.jar".to_string(), "2".to_string()),
+            ("/test/other.jar".to_string(), "3".to_string()),
+        ]
+    );
+
+    assert_eq!(
+        app_cmdline(&launch).as_str(),
+        "l-javaagent:2\u{0000}-javaagent:3=a=b\u{0000}-ea\u{0000}-Xmx1g\u{0000}-cp\u{0000}0:1\u{0000}com.example.HelloWorld\n"
+    )
+}
+
+#[test]
+fn test_launch_supported_by() {
+    let launch = Launch {
+        assertions: vec!["-ea".to_string()],
+        hints: vec!["-Xmx1g".to_string()],
+        ..test_launch(&[], Main::Class("com.example.HelloWorld".to_string()), &[])
+    };
+
+    let (supported, notices) = launch.supported_by(&Protocol::legacy());
+
+    assert!(supported.assertions.is_empty());
+    assert!(supported.hints.is_empty());
+    assert_eq!(
+        notices,
+        vec![
+            "landlord: ignoring -ea as landlordd does not support assertion switches",
+            "landlord: ignoring -Xmx1g as landlordd does not support resource hints",
+        ]
+    );
+
+    let protocol = Protocol {
+        version: PROTOCOL_VERSION,
+        capabilities: vec![Capability::Assertions, Capability::ResourceHints],
+    };
+
+    assert_eq!(launch.supported_by(&protocol), (launch.clone(), vec![]));
+}
+
+#[test]
+fn test_class_path_with_names() {
+    assert_eq!(
+        class_path_with_names(&["/test/one", "/test/two"]),
+        vec![
+            ("/test/one".to_string(), "0".to_string()),
+            ("/test/two".to_string(), "1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_encode_descriptor() {
+    let launch = test_launch(
+        &[],
+        Main::Class("com.example.HelloWorld".to_string()),
+        &["two\nlines"],
+    );
+
+    assert_eq!(
+        encode_descriptor(&launch, DescriptorFormat::LengthPrefixed).unwrap(),
+        b"L\x00\x00\x00\x04\
+          \x00\x00\x00\x03-cp\
+          \x00\x00\x00\x030:1\
+          \x00\x00\x00\x16com.example.HelloWorld\
+          \x00\x00\x00\x09two\nlines"
+            .to_vec()
+    );
+
+    assert_eq!(
+        encode_descriptor(&launch, DescriptorFormat::Legacy)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    let launch = test_launch(&[], Main::Class("com.example.HelloWorld".to_string()), &[]);
+
+    assert_eq!(
+        encode_descriptor(&launch, DescriptorFormat::Legacy).unwrap(),
+        app_cmdline(&launch).into_bytes()
+    );
+}
+
+#[test]
+fn test_describe_launch() {
+    let mut launch = test_launch(
+        &[("one", "1")],
+        Main::Class("com.example.HelloWorld".to_string()),
+        &["hello world"],
+    );
+
+    launch.agents = vec![("/test/agent.jar".to_string(), None)];
+    launch.env = vec![("TZ".to_string(), "UTC".to_string())];
+
+    assert_eq!(
+        describe_launch(&launch),
+        "fields:
+  -Done=1
+  -javaagent:2
+  -cp
+  0:1
+  com.example.HelloWorld
+  hello world
+properties:
+  one=1
+environment:
+  TZ=UTC
+entries:
+  0 -> /test/one (class path)
+  1 -> /test/two (class path)
+  2 -> /test/agent.jar (agent)
+main class: com.example.HelloWorld
+args:
+  hello world
+"
+    );
+}
+
+#[test]
+fn test_module_options() {
+    let mut launch = test_launch(&[], Main::Class("Main".to_string()), &[]);
+
+    assert!(launch.module_options().is_empty());
+
+    launch.main = Main::Module("app/com.example.Main".to_string());
+    launch.module_path = vec!["/test/mods".to_string()];
+    launch.options = vec![
+        "--add-opens=java.base/java.lang=app".to_string(),
+        "--add-opens=java.base/java.util=app".to_string(),
+        "--add-modules=java.sql".to_string(),
+    ];
+
+    assert_eq!(
+        launch.module_options(),
+        vec!["--module-path", "-m", "--add-opens", "--add-modules"]
+    );
+
+    assert_eq!(
+        Protocol::legacy()
+            .require(Capability::Modules, &launch.module_options().join(", "))
+            .unwrap_err()
+            .to_string(),
+        "--module-path, -m, --add-opens, --add-modules requires the module system, which \
+         landlordd (protocol version 0) does not support"
+    );
+}
+
+#[cfg(test)]
+fn encode_test_frames(frames: Vec<Frame>) -> BytesMut {
+    let mut buf = BytesMut::new();
+
+    for frame in frames {
+        encode_frame(frame, &mut buf).unwrap();
+    }
+
+    buf
+}
+
+#[cfg(test)]
+fn decode_test_frames<D: FrameDecoder>(decoder: &mut D, bytes: &[u8]) -> Vec<Frame> {
+    // bytes are provided one at a time to exercise partial reads
+
+    let mut buf = BytesMut::new();
+    let mut frames = vec![];
+
+    for b in bytes {
+        buf.extend_from_slice(&[*b]);
+
+        if let Some(frame) = decoder.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+    }
+
+    frames
+}
+
+#[test]
+fn test_client_codec() {
+    let bytes = encode_test_frames(vec![
+        Frame::Pid(42),
+        Frame::StdOut(b"hello".to_vec()),
+        Frame::StdErr(vec![]),
+        Frame::Exit(3),
+    ]);
+
+    assert_eq!(
+        &bytes[..],
+        &b"\x00\x00\x00\x2ao\x00\x00\x00\x05helloe\x00\x00\x00\x00x\x00\x00\x00\x03"[..]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::new(), &bytes),
+        vec![
+            Frame::Pid(42),
+            Frame::StdOut(b"hello".to_vec()),
+            Frame::StdErr(vec![]),
+            Frame::Exit(3),
+        ]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::new(), b"???"),
+        vec![Frame::Unrecognized]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::launched(), b"x\x00\x00\x00\x01"),
+        vec![Frame::Exit(1)]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::launched(), b"bbo\x00\x00\x00\x00"),
+        vec![Frame::Heartbeat, Frame::Heartbeat, Frame::StdOut(vec![])]
+    );
+
+    assert!(ClientCodec::launched()
+        .decode(&mut BytesMut::from(&b"z"[..]))
+        .is_err());
+
+    let status = ExitStatus {
+        code: 70,
+        reason: ExitReason::UncaughtException,
+        message: Some("java.lang.IllegalStateException: boom".to_string()),
+    };
+
+    let bytes = encode_test_frames(vec![
+        Frame::ExitStatus(status.clone()),
+        Frame::ExitStatus(ExitStatus::code(0)),
+    ]);
+
+    assert_eq!(
+        &bytes[..13],
+        b"X\x00\x00\x00\x46\x00\x00\x00\x02\x00\x00\x00\x25"
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::launched(), &bytes),
+        vec![
+            Frame::ExitStatus(status),
+            Frame::ExitStatus(ExitStatus::code(0)),
+        ]
+    );
+}
+
+#[test]
+fn test_daemon_codec() {
+    let fields = vec!["-cp".to_string(), "0".to_string(), "Main".to_string()];
+
+    for format in &[DescriptorFormat::Legacy, DescriptorFormat::LengthPrefixed] {
+        let mut bytes = encode_test_frames(vec![Frame::Launch(*format, fields.clone())]);
+
+        bytes.extend_from_slice(b"tar");
+
+        let mut codec = DaemonCodec::new();
+
+        assert_eq!(
+            decode_test_frames(&mut codec, &bytes[..bytes.len() - 3]),
+            vec![Frame::Launch(*format, fields.clone())]
+        );
+
+        let mut rest = BytesMut::from(&b"tar"[..]);
+
+        assert_eq!(codec.decode(&mut rest).unwrap(), None);
+        assert_eq!(&rest[..], b"tar");
+    }
+
+    let bytes = encode_test_frames(vec![Frame::Kill { pid: 1, signal: 15 }]);
+
+    assert_eq!(&bytes[..], b"k\x00\x00\x00\x01\x00\x00\x00\x0f");
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![Frame::Kill { pid: 1, signal: 15 }]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), b"?"),
+        vec![Frame::Probe]
+    );
+
+    let bytes = encode_test_frames(vec![
+        Frame::Console { rows: 24, cols: 80 },
+        Frame::Launch(DescriptorFormat::LengthPrefixed, fields.clone()),
+    ]);
+
+    assert_eq!(&bytes[..9], b"t\x00\x00\x00\x18\x00\x00\x00\x50");
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![
+            Frame::Console { rows: 24, cols: 80 },
+            Frame::Launch(DescriptorFormat::LengthPrefixed, fields),
+        ]
+    );
+}
+
+#[test]
+fn test_compression() {
+    let bytes = encode_test_frames(vec![
+        Frame::Compressed(Compression::Zstd),
+        Frame::Launch(DescriptorFormat::LengthPrefixed, vec![]),
+    ]);
+
+    assert_eq!(&bytes[..5], b"z\x00\x00\x00\x02");
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![
+            Frame::Compressed(Compression::Zstd),
+            Frame::Launch(DescriptorFormat::LengthPrefixed, vec![]),
+        ]
+    );
+
+    assert!(DaemonCodec::new()
+        .decode(&mut BytesMut::from(&b"z\x00\x00\x00\x09"[..]))
+        .is_err());
+
+    let protocol = Protocol {
+        version: PROTOCOL_VERSION,
+        capabilities: vec![Capability::GzipCompression, Capability::ZstdCompression],
+    };
+
+    assert_eq!(protocol.compression(), Some(Compression::Zstd));
+
+    assert_eq!(Compression::Gzip.level(None), 6);
+    assert_eq!(Compression::Gzip.level(Some(12)), 9);
+    assert_eq!(Compression::Zstd.level(None), 3);
+    assert_eq!(Compression::Zstd.level(Some(0)), 1);
+    assert_eq!(Compression::Zstd.level(Some(19)), 19);
+}
+
+#[test]
+fn test_env() {
+    let launch = Launch {
+        env: vec![
+            ("A".to_string(), "1".to_string()),
+            ("B".to_string(), "x=y".to_string()),
+        ],
+        ..test_launch(&[], Main::Class("Main".to_string()), &[])
+    };
+
+    let bytes = encode_descriptor(&launch, DescriptorFormat::LengthPrefixed).unwrap();
+
+    assert_eq!(
+        &bytes[..21],
+        b"E\x00\x00\x00\x02\
+          \x00\x00\x00\x03A=1\
+          \x00\x00\x00\x05B=x=y"
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![
+            Frame::Env(launch.env.clone()),
+            Frame::Launch(DescriptorFormat::LengthPrefixed, launch_fields(&launch)),
+        ]
+    );
+
+    let mut bogus = BytesMut::from(&b"E\x00\x00\x00\x01\x00\x00\x00\x01A"[..]);
+
+    assert_eq!(
+        DaemonCodec::new().decode(&mut bogus).unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_manifest() {
+    let manifest = vec![
+        ("0".to_string(), "aa".to_string()),
+        ("1".to_string(), "bb".to_string()),
+    ];
+
+    let bytes = encode_test_frames(vec![Frame::Manifest(manifest.clone())]);
+
+    assert_eq!(
+        &bytes[..],
+        &b"m\x00\x00\x00\x02\
+           \x00\x00\x00\x040=aa\
+           \x00\x00\x00\x041=bb"[..]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![Frame::Manifest(manifest)]
+    );
+
+    // landlordd responds with the digests it has, before the pid
+
+    let bytes = encode_test_frames(vec![Frame::Present(vec!["bb".to_string()]), Frame::Pid(42)]);
+
+    assert_eq!(
+        &bytes[..],
+        &b"p\x00\x00\x00\x01\x00\x00\x00\x02bb\x00\x00\x00\x2a"[..]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::new(), &bytes),
+        vec![Frame::Present(vec!["bb".to_string()]), Frame::Pid(42)]
+    );
+
+    assert_eq!(
+        decode_test_frames(
+            &mut ClientCodec::new(),
+            b"p\x00\x00\x00\x00\x00\x00\x00\x07"
+        ),
+        vec![Frame::Present(vec![]), Frame::Pid(7)]
+    );
+
+    let mut bogus = BytesMut::from(&b"m\x00\x00\x00\x01\x00\x00\x00\x010"[..]);
+
+    assert_eq!(
+        DaemonCodec::new().decode(&mut bogus).unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_framed_stdin() {
+    let frames = vec![
+        Frame::StdIn(b"hello".to_vec()),
+        Frame::Signal(2),
+        Frame::WindowSize { rows: 24, cols: 80 },
+        Frame::StdInClosed,
+    ];
+
+    let bytes = encode_test_frames(frames);
+
+    assert_eq!(
+        &bytes[..],
+        &b"i\x00\x00\x00\x05hellos\x00\x00\x00\x02w\x00\x00\x00\x18\x00\x00\x00\x50c"[..]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::framed_stdin(), &bytes),
+        vec![
+            Frame::StdIn(b"hello".to_vec()),
+            Frame::Signal(2),
+            Frame::WindowSize { rows: 24, cols: 80 },
+            Frame::StdInClosed,
+        ]
+    );
+
+    // stdin frames are only decoded once framed stdin has been negotiated
+
+    assert!(DaemonCodec::new()
+        .decode(&mut BytesMut::from(&b"c"[..]))
+        .is_err());
+}
+
+#[test]
+fn test_handle_input() {
+    let handle = |protocol: &Protocol, input: Input| {
+        let mut main = vec![];
+        let mut session = vec![];
+
+        handle_input(
+            7,
+            protocol,
+            input,
+            &mut |bs| Ok(main.push(bs)),
+            &mut |bs| Ok(session.push(bs)),
+            &mut |_| Ok(()),
+            &mut |_| Ok(()),
+        )
+        .unwrap();
+
+        (main, session)
+    };
+
+    let legacy = Protocol::legacy();
+    let framed = Protocol {
+        version: PROTOCOL_VERSION,
+        capabilities: vec![Capability::FramedStdin],
+    };
+
+    assert_eq!(
+        handle(&legacy, Input::StdIn(b"hi".to_vec())),
+        (vec![b"hi".to_vec()], vec![])
+    );
+    assert_eq!(handle(&legacy, Input::StdInClosed), (vec![vec![]], vec![]));
+    assert_eq!(
+        handle(&legacy, Input::Signal(2)),
+        (vec![], vec![b"k\x00\x00\x00\x07\x00\x00\x00\x02".to_vec()])
+    );
+    assert_eq!(
+        handle(&legacy, Input::WindowSize { rows: 24, cols: 80 }).1,
+        vec![encode_test_frames(vec![Frame::Kill {
+            pid: 7,
+            signal: libc::SIGWINCH,
+        }])
+        .to_vec()]
+    );
+
+    assert_eq!(
+        handle(&framed, Input::StdIn(b"hi".to_vec())),
+        (vec![b"i\x00\x00\x00\x02hi".to_vec()], vec![])
+    );
+    assert_eq!(
+        handle(&framed, Input::StdInClosed),
+        (vec![b"c".to_vec()], vec![])
+    );
+    assert_eq!(
+        handle(&framed, Input::Signal(2)),
+        (vec![b"s\x00\x00\x00\x02".to_vec()], vec![])
+    );
+}
+
+#[test]
+fn test_hello() {
+    let daemon = Protocol {
+        version: 2,
+        capabilities: vec![
+            Capability::GzipCompression,
+            Capability::LengthPrefixedDescriptor,
+        ],
+    };
+
+    let bytes = encode_test_frames(vec![Frame::Hello(daemon.clone())]);
+
+    assert_eq!(&bytes[..], b"h\x00\x00\x00\x02\x00\x00\x00\x11");
+
+    assert_eq!(
+        decode_test_frames(&mut ClientCodec::new(), &bytes),
+        vec![Frame::Hello(daemon.clone())]
+    );
+
+    assert_eq!(
+        decode_test_frames(&mut DaemonCodec::new(), &bytes),
+        vec![Frame::Hello(daemon.clone())]
+    );
+
+    // unknown capabilities are ignored
+
+    assert_eq!(
+        decode_test_frames(
+            &mut ClientCodec::new(),
+            b"h\x00\x00\x00\x02\x00\x01\x00\x01\x00\x00\x00\x2a"
+        ),
+        vec![
+            Frame::Hello(Protocol {
+                version: 2,
+                capabilities: vec![Capability::GzipCompression],
+            }),
+            Frame::Pid(42),
+        ]
+    );
+
+    let negotiated = Protocol::client().negotiate(&daemon);
+
+    assert_eq!(negotiated.version, PROTOCOL_VERSION);
+    assert_eq!(
+        negotiated.descriptor_format(),
+        DescriptorFormat::LengthPrefixed
+    );
+    assert_eq!(negotiated.compression(), Some(Compression::Gzip));
+    assert_eq!(Protocol::legacy().compression(), None);
+    assert!(negotiated
+        .require(Capability::LengthPrefixedDescriptor, "this")
+        .is_ok());
+
+    assert_eq!(
+        Protocol::legacy().descriptor_format(),
+        DescriptorFormat::Legacy
+    );
+    assert_eq!(
+        Protocol::legacy()
+            .require(Capability::FramedStdin, "-t")
+            .unwrap_err()
+            .to_string(),
+        "-t requires framed stdin, which landlordd (protocol version 0) does not support"
+    );
+}
+
+#[test]
+fn test_read_pid_handler() {
+    assert_eq!(
+        read_pid_handler(&mut io::Cursor::new(vec![0, 0, 1, 2])).unwrap(),
+        258
+    );
+
+    let closed = read_pid_handler(&mut io::Cursor::new(vec![0, 0])).unwrap_err();
+
+    assert_eq!(closed.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(is_daemon_error(&closed));
+
+    let bogus = read_pid_handler(&mut io::Cursor::new(b"h\x00\x00\x00".to_vec())).unwrap_err();
+
+    assert_eq!(bogus.kind(), io::ErrorKind::InvalidInput);
+    assert!(is_daemon_error(&bogus));
+}
+
+#[test]
+fn test_read_frame() {
+    let mut reader = io::Cursor::new(b"o\x00\x00\x00\x01ax\x00\x00".to_vec());
+    let mut codec = ClientCodec::launched();
+    let mut buf = BytesMut::new();
+
+    assert_eq!(
+        read_frame(&mut reader, &mut codec, &mut buf).unwrap(),
+        Some(Frame::StdOut(b"a".to_vec()))
+    );
+
+    assert_eq!(
+        read_frame(&mut reader, &mut codec, &mut buf)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+
+    assert_eq!(
+        read_frame(&mut io::empty(), &mut codec, &mut BytesMut::new()).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_exit_status_describe() {
+    let status = |reason, message: Option<&str>| ExitStatus {
+        code: 1,
+        reason,
+        message: message.map(|m| m.to_string()),
+    };
+
+    assert_eq!(
+        status(
+            ExitReason::UncaughtException,
+            Some("java.lang.Error: boom\n\tat Main.main(Main.java:3)")
+        )
+        .describe(),
+        Some(
+            "Exception in thread \"main\" java.lang.Error: boom\n\tat Main.main(Main.java:3)"
+                .to_string()
+        )
+    );
+
+    assert_eq!(
+        status(
+            ExitReason::LoadFailed,
+            Some("Could not find or load main class Main")
+        )
+        .describe(),
+        Some("Error: Could not find or load main class Main".to_string())
+    );
+
+    assert_eq!(status(ExitReason::Exited, Some("bye")).describe(), None);
+    assert_eq!(ExitStatus::code(1).describe(), None);
+}
+
+#[test]
+fn test_exit_status_signal() {
+    let signaled = |code| ExitStatus {
+        code,
+        reason: ExitReason::Signaled,
+        message: None,
+    };
+
+    assert_eq!(signaled(130).signal(), Some(libc::SIGINT));
+    assert_eq!(signaled(143).signal(), Some(libc::SIGTERM));
+    assert_eq!(signaled(1).signal(), None);
+    assert_eq!(ExitStatus::code(130).signal(), None);
+}
+
+#[test]
+fn test_read_handler() {
+    let mut reader = io::Cursor::new(encode_test_frames(vec![
+        Frame::StdOut(b"out".to_vec()),
+        Frame::Heartbeat,
+        Frame::StdErr(b"err".to_vec()),
+        Frame::Exit(7),
+    ]));
+
+    let mut inputs = vec![];
+
+    read_handler(&mut reader, |input| {
+        inputs.push(match input {
+            Input::StdOut(bs) => format!("out {}", String::from_utf8_lossy(&bs)),
+            Input::StdErr(bs) => format!("err {}", String::from_utf8_lossy(&bs)),
+            Input::Exit(status) => format!("exit {}", status.code),
+            _ => "other".to_string(),
+        });
+
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(inputs, vec!["out out", "err err", "exit 7"]);
+}
+
+#[test]
+fn test_decode_i32_invalid() {
+    assert!(decode_i32(&vec![]).is_err());
+    assert!(decode_i32(&vec![0]).is_err());
+    assert!(decode_i32(&vec![0, 0]).is_err());
+    assert!(decode_i32(&vec![0, 0, 0]).is_err());
+}
+
+#[test]
+fn test_decode_i32_valid() {
+    assert_eq!(decode_i32(&vec![0, 0, 0, 0]).ok(), Some(0));
+    assert_eq!(decode_i32(&vec![0, 0, 0, 1]).ok(), Some(1));
+    assert_eq!(decode_i32(&vec![1, 0, 0, 0]).ok(), Some(16777216));
+    assert_eq!(decode_i32(&vec![1, 0, 0, 1]).ok(), Some(16777217));
+}
+
+#[test]
+fn test_encode_i32_valid() {
+    assert_eq!(encode_i32(0).ok(), Some(vec![0, 0, 0, 0]));
+    assert_eq!(encode_i32(1).ok(), Some(vec![0, 0, 0, 1]));
+    assert_eq!(encode_i32(16777216).ok(), Some(vec![1, 0, 0, 0]));
+    assert_eq!(encode_i32(16777217).ok(), Some(vec![1, 0, 0, 1]));
+}
+
+#[test]
+fn test_daemon_error() {
+    let local = io::Error::new(io::ErrorKind::NotFound, "no such file");
+
+    assert!(!is_daemon_error(&local));
+
+    let error = daemon_error(local);
+
+    assert!(is_daemon_error(&error));
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert_eq!(error.to_string(), "no such file");
+
+    let error = daemon_error(error);
+
+    assert!(is_daemon_error(&error));
+    assert_eq!(error.to_string(), "no such file");
+
+    assert!(is_daemon_error(&heartbeat_timed_out(
+        time::Duration::from_millis(1)
+    )));
 }
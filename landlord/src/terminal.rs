@@ -0,0 +1,75 @@
+use libc;
+use proto::Input;
+use std::{io, mem};
+
+/// Returns the size (rows, columns) of the terminal that stdout, or failing that stdin, is
+/// attached to
+pub fn window_size() -> Option<(u16, u16)> {
+    [libc::STDOUT_FILENO, libc::STDIN_FILENO]
+        .iter()
+        .filter_map(|fd| unsafe {
+            let mut size: libc::winsize = mem::zeroed();
+
+            if libc::ioctl(*fd, libc::TIOCGWINSZ, &mut size) == 0 {
+                Some((size.ws_row, size.ws_col))
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+/// Converts a signal that has been received to the input that forwards it. A change in window
+/// size carries the terminal's new size when it's known.
+pub fn signal_input(signal: i32) -> Input {
+    if signal == libc::SIGWINCH {
+        if let Some((rows, cols)) = window_size() {
+            return Input::WindowSize { rows, cols };
+        }
+    }
+
+    Input::Signal(signal)
+}
+
+/// Returns true if stdin is attached to a terminal
+pub fn stdin_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+/// Puts the terminal that stdin is attached to into raw mode, i.e. input is neither
+/// line-buffered nor echoed and control characters aren't interpreted, so that it can all be
+/// forwarded. The terminal's original mode is restored when this is dropped, including as a
+/// panic unwinds.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        unsafe {
+            let mut original: libc::termios = mem::zeroed();
+
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+
+            libc::cfmakeraw(&mut raw);
+
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(RawMode { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+package com.github.huntc.landlord
+
+import java.io.IOException
+import java.nio.ByteBuffer
+import java.nio.charset.StandardCharsets
+import java.nio.file.{ Files, Path, StandardCopyOption }
+import java.security.MessageDigest
+
+import scala.collection.JavaConverters._
+
+/**
+ * Functions to keep the class path entries that clients send, keyed by their SHA-256 digest, so
+ * that a client needn't send an entry that a previous launch already has. Clients describe each
+ * entry of a launch by its name within the tar (e.g. "0") and its digest, which landlordd checks
+ * before keeping what was sent.
+ *
+ * Digests are computed as the client computes them: a file's is that of its contents, whereas a
+ * directory's covers the relative path of everything within it, along with the size and contents
+ * of each file, in the sorted order of their UTF-8 names.
+ */
+object ContentStore {
+  private val DigestPattern = "[0-9a-f]{64}"
+
+  private val FileBufferSize = 65536
+
+  /**
+   * Returns the digests of the `manifest` (name, digest) that the store at `storePath` holds.
+   */
+  def present(storePath: Path, manifest: Seq[(String, String)]): Seq[String] =
+    manifest
+      .collect { case (_, digest) if digest.matches(DigestPattern) => digest }
+      .distinct
+      .filter(digest => Files.exists(storePath.resolve(digest)))
+
+  /**
+   * Completes the entries of a launch once its tar has been written to `processDirPath`. Those of
+   * the `manifest` (name, digest) that the store holds are copied into `processDirPath`, and
+   * those that were sent are kept in the store, provided that their digest is as described.
+   */
+  def materialize(storePath: Path, processDirPath: Path, manifest: Seq[(String, String)]): Unit =
+    manifest.foreach {
+      case (name, expected) if expected.matches(DigestPattern) && isEntryName(name) =>
+        val stored = storePath.resolve(expected)
+        val entry = processDirPath.resolve(name)
+        if (Files.exists(stored)) {
+          if (!Files.exists(entry))
+            copyTree(stored, entry)
+        } else if (Files.exists(entry) && digest(entry) == expected) {
+          store(storePath, entry, stored)
+        }
+      case _ =>
+    }
+
+  /**
+   * Returns the SHA-256 digest, as lowercase hex, of the class path entry at `path`.
+   */
+  def digest(path: Path): String = {
+    val md = MessageDigest.getInstance("SHA-256")
+    if (Files.isDirectory(path))
+      digestDir(path, "", md)
+    else
+      digestFile(path, md)
+    md.digest().map("%02x".format(_)).mkString
+  }
+
+  private def digestDir(root: Path, relative: String, md: MessageDigest): Unit = {
+    val dir = if (relative.isEmpty) root else root.resolve(relative)
+    val children = {
+      val stream = Files.list(dir)
+      try {
+        stream.iterator.asScala.map(_.getFileName.toString).toVector
+      } finally {
+        stream.close()
+      }
+    }
+
+    // Paths are NUL terminated, and files are preceded by their size, so that no two trees
+    // can produce the same input
+
+    children.sortWith(utf8Before).foreach { name =>
+      val childRelative = if (relative.isEmpty) name else relative + "/" + name
+      val child = root.resolve(childRelative)
+      if (Files.isDirectory(child)) {
+        md.update('d'.toByte)
+        md.update(childRelative.getBytes(StandardCharsets.UTF_8))
+        md.update(0.toByte)
+        digestDir(root, childRelative, md)
+      } else if (Files.isRegularFile(child)) {
+        md.update('f'.toByte)
+        md.update(childRelative.getBytes(StandardCharsets.UTF_8))
+        md.update(0.toByte)
+        md.update(ByteBuffer.allocate(8).putLong(Files.size(child)).array)
+        digestFile(child, md)
+      }
+    }
+  }
+
+  private def digestFile(path: Path, md: MessageDigest): Unit = {
+    val is = Files.newInputStream(path)
+    try {
+      val buffer = Array.ofDim[Byte](FileBufferSize)
+      @annotation.tailrec def update(): Unit = {
+        val read = is.read(buffer)
+        if (read > -1) {
+          md.update(buffer, 0, read)
+          update()
+        }
+      }
+      update()
+    } finally {
+      is.close()
+    }
+  }
+
+  private def utf8Before(left: String, right: String): Boolean = {
+    val l = left.getBytes(StandardCharsets.UTF_8)
+    val r = right.getBytes(StandardCharsets.UTF_8)
+    val differing = l.zip(r).find { case (lb, rb) => lb != rb }
+    differing match {
+      case Some((lb, rb)) => (lb & 0xff) < (rb & 0xff)
+      case None           => l.length < r.length
+    }
+  }
+
+  private def isEntryName(name: String): Boolean =
+    name.nonEmpty && name != "." && name != ".." && !name.contains("/")
+
+  /**
+   * Keeps a copy of `entry` as `stored`. The copy is made under a temporary name and then moved,
+   * so that a concurrent launch never copies one that is incomplete.
+   */
+  private def store(storePath: Path, entry: Path, stored: Path): Unit = {
+    Files.createDirectories(storePath)
+    val temp = Files.createTempDirectory(storePath, ".tmp-")
+    try {
+      val copied = temp.resolve("entry")
+      copyTree(entry, copied)
+      Files.move(copied, stored, StandardCopyOption.ATOMIC_MOVE)
+    } catch {
+      case _: IOException => // Another launch has stored it first, or the store is unusable
+    } finally {
+      deleteTree(temp)
+    }
+  }
+
+  private def copyTree(source: Path, target: Path): Unit = {
+    val stream = Files.walk(source)
+    try {
+      stream.iterator.asScala.foreach { path =>
+        val copy = target.resolve(source.relativize(path).toString)
+        if (Files.isDirectory(path))
+          Files.createDirectories(copy)
+        else
+          Files.copy(path, copy, StandardCopyOption.COPY_ATTRIBUTES)
+      }
+    } finally {
+      stream.close()
+    }
+  }
+
+  private def deleteTree(path: Path): Unit =
+    if (Files.exists(path)) {
+      val stream = Files.walk(path)
+      try {
+        stream.iterator.asScala.toVector.reverse.foreach(Files.delete)
+      } finally {
+        stream.close()
+      }
+    }
+}
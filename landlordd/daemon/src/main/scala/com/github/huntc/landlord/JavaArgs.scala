@@ -1,16 +1,35 @@
 package com.github.huntc.landlord
 
 import scala.collection.immutable.Seq
+import scala.util.Try
 
 sealed trait ExecutionMode
 
 case class ClassExecutionMode(`class`: String, args: Seq[String]) extends ExecutionMode
 
+case class ModuleExecutionMode(module: String, `class`: Option[String], args: Seq[String]) extends ExecutionMode
+
+/**
+ * Heap and thread stack sizes in bytes, as given by `-Xms`, `-Xmx` and `-Xss`. As a process
+ * shares landlordd's JVM, these are hints.
+ */
+case class ResourceHints(
+    initialHeapSize: Option[Long] = None,
+    maxHeapSize: Option[Long] = None,
+    threadStackSize: Option[Long] = None)
+
 case class JavaArgs(
     cp: Seq[String],
     errors: Seq[String],
     mode: ExecutionMode,
-    props: Seq[(String, String)])
+    props: Seq[(String, String)],
+    modulePath: Seq[String] = Seq.empty,
+    addModules: Seq[String] = Seq.empty,
+    addOpens: Seq[ModuleSystem.Directive] = Seq.empty,
+    addExports: Seq[ModuleSystem.Directive] = Seq.empty,
+    agents: Seq[(String, Option[String])] = Seq.empty,
+    assertions: Seq[String] = Seq.empty,
+    resourceHints: ResourceHints = ResourceHints())
 
 /**
  * Parses arguments in a similar manner to the JRE's `java` command. Due to some
@@ -18,6 +37,28 @@ case class JavaArgs(
  * the `-Dname=value` syntax is not possible with scopt.
  */
 object JavaArgs {
+  private val AssertionFlags = Set("-ea", "-da", "-enableassertions", "-disableassertions")
+
+  /**
+   * Parses a size as `-Xmx` and friends accept it, i.e. a number of bytes optionally followed
+   * by a `k`, `m`, `g` or `t` unit (in either case).
+   */
+  def parseSize(size: String): Option[Long] = {
+    val (digits, multiplier) =
+      size.lastOption.map(_.toLower) match {
+        case Some('k') => size.dropRight(1) -> (1L << 10)
+        case Some('m') => size.dropRight(1) -> (1L << 20)
+        case Some('g') => size.dropRight(1) -> (1L << 30)
+        case Some('t') => size.dropRight(1) -> (1L << 40)
+        case _         => size -> 1L
+      }
+
+    if (digits.nonEmpty && digits.forall(_.isDigit))
+      Try(digits.toLong * multiplier).toOption
+    else
+      None
+  }
+
   def parse(args: Seq[String]): Either[Seq[String], JavaArgs] = {
     @annotation.tailrec
     def step(as: Seq[String], accum: JavaArgs): JavaArgs =
@@ -33,6 +74,65 @@ object JavaArgs {
             }
           )
 
+        case Some(flag) if flag == "-m" || flag == "--module" =>
+          as.tail.headOption.fold(accum.copy(errors = accum.errors :+ s"$flag requires module name")) { module =>
+            module.split("/", 2) match {
+              case Array(name, cls) => accum.copy(mode = ModuleExecutionMode(name, Some(cls), as.tail.tail))
+              case _                => accum.copy(mode = ModuleExecutionMode(module, None, as.tail.tail))
+            }
+          }
+
+        case Some(flag) if flag == "-p" || flag == "--module-path" =>
+          step(
+            if (as.tail.isEmpty) Seq.empty else as.tail.tail,
+            as.tail.headOption.fold(accum.copy(errors = accum.errors :+ s"$flag requires module path specification")) { mp =>
+              accum.copy(modulePath = accum.modulePath ++ mp.split(":").toVector)
+            }
+          )
+
+        case Some(flag) if flag.startsWith("--add-modules=") =>
+          step(
+            as.tail,
+            accum.copy(addModules = accum.addModules ++ flag.drop("--add-modules=".length).split(",").toVector.filter(_.nonEmpty))
+          )
+
+        case Some(flag) if flag.startsWith("--add-opens=") || flag.startsWith("--add-exports=") =>
+          val Array(name, value) = flag.split("=", 2)
+
+          step(
+            as.tail,
+            ModuleSystem.Directive.parse(value) match {
+              case Some(directive) if name == "--add-opens" => accum.copy(addOpens = accum.addOpens :+ directive)
+              case Some(directive)                          => accum.copy(addExports = accum.addExports :+ directive)
+              case None                                     => accum.copy(errors = accum.errors :+ s"Invalid $name value: $value")
+            }
+          )
+
+        case Some(flag) if flag.startsWith("-javaagent:") =>
+          val agent =
+            flag.drop("-javaagent:".length).split("=", 2) match {
+              case Array(jar, options) => jar -> Some(options)
+              case _                   => flag.drop("-javaagent:".length) -> None
+            }
+
+          step(as.tail, accum.copy(agents = accum.agents :+ agent))
+
+        case Some(flag) if AssertionFlags.contains(flag.takeWhile(_ != ':')) =>
+          step(as.tail, accum.copy(assertions = accum.assertions :+ flag))
+
+        case Some(flag) if flag.startsWith("-Xms") || flag.startsWith("-Xmx") || flag.startsWith("-Xss") =>
+          val hints = accum.resourceHints
+
+          step(
+            as.tail,
+            (flag.take(4), parseSize(flag.drop(4))) match {
+              case ("-Xms", Some(size)) => accum.copy(resourceHints = hints.copy(initialHeapSize = Some(size)))
+              case ("-Xmx", Some(size)) => accum.copy(resourceHints = hints.copy(maxHeapSize = Some(size)))
+              case (_, Some(size))      => accum.copy(resourceHints = hints.copy(threadStackSize = Some(size)))
+              case (_, None)            => accum.copy(errors = accum.errors :+ s"Invalid size: $flag")
+            }
+          )
+
         case Some(flag) if flag.startsWith("-D") =>
           val parts =
             flag
@@ -7,45 +7,54 @@ import akka.util.ByteString
 import akka.stream._
 import akka.stream.scaladsl.{ BroadcastHub, Keep, Source, StreamConverters }
 import java.io.{ ByteArrayOutputStream, PrintStream }
-import java.lang.reflect.InvocationTargetException
+import java.lang.instrument.Instrumentation
+import java.lang.reflect.{ InvocationTargetException, Method }
 import java.net.URLClassLoader
 import java.nio.ByteOrder
 import java.nio.file.{ Files, Path, Paths }
 import java.security.Permission
 import java.util.Properties
-import java.util.concurrent.atomic.AtomicBoolean
+import java.util.concurrent.atomic.{ AtomicBoolean, AtomicInteger }
+import java.util.jar.JarFile
 
 import scala.collection.JavaConverters._
 import scala.concurrent.{ Future, Promise }
 import scala.concurrent.duration.FiniteDuration
 import scala.ref.WeakReference
+import scala.util.Try
 import scala.util.control.NonFatal
 
 object JvmExecutor {
   def props(
     processId: Int,
-    properties: ThreadGroupProperties, securityManager: ThreadGroupSecurityManager, useDefaultSecurityManager: Boolean,
+    protocol: Protocol,
+    properties: ThreadGroupProperties, environment: ThreadGroupEnvironment,
+    securityManager: ThreadGroupSecurityManager, useDefaultSecurityManager: Boolean,
     stdin: ThreadGroupInputStream, stdinTimeout: FiniteDuration, stdout: ThreadGroupPrintStream, stderr: ThreadGroupPrintStream,
     in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]],
     exitTimeout: FiniteDuration, outputDrainTimeAtExit: FiniteDuration,
     heatbeatInterval: FiniteDuration,
     processDirPath: Path,
+    contentStorePath: Path,
     exposedPropNames: Seq[String]
   ): Props =
     Props(
       new JvmExecutor(
         processId,
-        properties, securityManager, useDefaultSecurityManager,
+        protocol,
+        properties, environment,
+        securityManager, useDefaultSecurityManager,
         stdin, stdinTimeout, stdout, stderr,
         in, out,
         exitTimeout, outputDrainTimeAtExit,
         heatbeatInterval,
         processDirPath,
+        contentStorePath,
         exposedPropNames
       )
     )
 
-  case class StartProcess(commandLine: String, stdin: Source[ByteString, AnyRef])
+  case class StartProcess(launch: Launch, stdin: Source[ByteString, AnyRef])
   case class SignalProcess(signal: Int)
   private case object ConnectionReadClosed
   private case object ConnectionWriteClosed
@@ -79,11 +88,97 @@ object JvmExecutor {
       }
     }
 
-  private[landlord] case class ExitEarly(exitStatus: Int, errorMessage: Option[String])
+  /**
+   * Sets the assertion status of `classLoader` as per `-ea` and `-da` switches, e.g.
+   * `-ea:com.example...` for a package and its subpackages, or `-da:com.example.Main` for a class.
+   */
+  private[landlord] def setAssertionStatus(classLoader: ClassLoader, switches: Seq[String]): Unit =
+    switches.foreach { switch =>
+      val (flag, target) =
+        switch.split(":", 2) match {
+          case Array(f, t) => f -> t
+          case _           => switch -> ""
+        }
+      val enabled = flag == "-ea" || flag == "-enableassertions"
+
+      if (target.isEmpty)
+        classLoader.setDefaultAssertionStatus(enabled)
+      else if (target == "...")
+        classLoader.setPackageAssertionStatus(null, enabled) // The unnamed package
+      else if (target.endsWith("..."))
+        classLoader.setPackageAssertionStatus(target.dropRight(3), enabled)
+      else
+        classLoader.setClassAssertionStatus(target, enabled)
+    }
+
+  /**
+   * An agent of a process, i.e. the `premain` method of the Premain-Class that its jar declares,
+   * along with the options that it's given.
+   */
+  private[landlord] case class Agent(premain: Method, options: Option[String]) {
+    /**
+     * Invokes `premain`, passing the JVM's instrumentation if it accepts it.
+     */
+    def start(): Unit =
+      if (premain.getParameterCount == 2)
+        premain.invoke(null, options.orNull, LandlordAgent.instrumentation.orNull)
+      else
+        premain.invoke(null, options.orNull)
+  }
+
+  /**
+   * Loads the agent of `jar` via `classLoader`. As with `java`, a `premain` that accepts the JVM's
+   * instrumentation is preferred, which requires landlordd to have been started with its agent.
+   */
+  private[landlord] def loadAgent(jar: Path, options: Option[String], classLoader: ClassLoader): Agent = {
+    val jarFile = new JarFile(jar.toFile)
+    val premainClass =
+      try {
+        Option(jarFile.getManifest)
+          .flatMap(manifest => Option(manifest.getMainAttributes.getValue("Premain-Class")))
+          .getOrElse(throw new IllegalArgumentException(s"Failed to find Premain-Class manifest attribute in ${jar.getFileName}"))
+      } finally {
+        jarFile.close()
+      }
+    val cls = classLoader.loadClass(premainClass.trim)
+    val withInstrumentation =
+      Try(cls.getMethod("premain", classOf[String], classOf[Instrumentation])).toOption
+        .filter(_ => LandlordAgent.instrumentation.isDefined)
+
+    Agent(
+      withInstrumentation
+        .orElse(Try(cls.getMethod("premain", classOf[String])).toOption)
+        .getOrElse(throw new IllegalArgumentException(
+          if (LandlordAgent.instrumentation.isEmpty)
+            s"The agent ${cls.getName} requires landlordd to be started with its agent"
+          else
+            s"Failed to find premain method in ${cls.getName}")),
+      options)
+  }
+
+  /**
+   * What has been received in order to launch a process, prior to its stdin.
+   */
+  case class Launch(commandLine: String = "", env: Map[String, String] = Map.empty, manifest: Seq[(String, String)] = Seq.empty)
+
+  /**
+   * Why a process exited, as conveyed to a client that supports the ExitReasons capability.
+   */
+  object ExitReason {
+    val Exited = 1
+    val UncaughtException = 2
+    val Signaled = 3
+    val LoadFailed = 4
+  }
+
+  case class ExitStatus(code: Int, reason: Int, message: Option[String] = None)
+
+  private[landlord] case class ExitEarly(exitStatus: ExitStatus)
 
   private[landlord] val SIGABRT = 6
   private[landlord] val SIGINT = 2
   private[landlord] val SIGTERM = 15
+  private[landlord] val SIGWINCH = 28
 
   private[landlord] def sizeToBytes(size: Int): ByteString =
     ByteString.newBuilder.putInt(size)(ByteOrder.BIG_ENDIAN).result()
@@ -91,11 +186,29 @@ object JvmExecutor {
   private[landlord] def processIdToBytes(processId: Int): ByteString =
     ByteString.newBuilder.putInt(processId)(ByteOrder.BIG_ENDIAN).result()
 
+  private[landlord] def presentToBytes(digests: Seq[String]): ByteString =
+    digests.foldLeft(ByteString.newBuilder.putByte('p').putInt(digests.size)(ByteOrder.BIG_ENDIAN)) { (builder, digest) =>
+      val digestBytes = ByteString(digest)
+      builder.putInt(digestBytes.size)(ByteOrder.BIG_ENDIAN).append(digestBytes)
+    }.result()
+
   private[landlord] def exitStatusToBytes(statusCode: Int): ByteString =
     ByteString.newBuilder.putByte('x').putInt(statusCode)(ByteOrder.BIG_ENDIAN).result()
 
+  private[landlord] def exitReasonToBytes(exitStatus: ExitStatus): ByteString = {
+    val messageBytes = ByteString(exitStatus.message.getOrElse(""))
+    ByteString.newBuilder
+      .putByte('X')
+      .putInt(exitStatus.code)(ByteOrder.BIG_ENDIAN)
+      .putInt(exitStatus.reason)(ByteOrder.BIG_ENDIAN)
+      .putInt(messageBytes.size)(ByteOrder.BIG_ENDIAN)
+      .append(messageBytes)
+      .result()
+  }
+
   private[landlord] val StdoutPrefix = ByteString('o'.toByte)
   private[landlord] val StderrPrefix = ByteString('e'.toByte)
+  private[landlord] val HeartbeatBytes = ByteString('b'.toByte)
 
   private[landlord] val ShutdownHooksPerm = new RuntimePermission("shutdownHooks")
 
@@ -212,6 +325,9 @@ object JvmExecutor {
  *
  * The outgoing stream is presented as follows:
  *
+ * 0. When the ContentAddressing capability has been negotiated and the client has sent a manifest,
+ *    'p' followed by a count of the digests that landlordd already has and then each as its size
+ *    followed by its UTF-8 bytes. This precedes the tar being received.
  * 1. The first four bytes convey the process id.
  * 2. A single UTF-8 character is then sent representing one of 'o', 'e' or 'x' (stdout, stderr, exit code).
  *
@@ -221,15 +337,25 @@ object JvmExecutor {
  * Exit codes are conveyed as a four byte integer and are followed by the stream being
  * terminated. All stdout and stderr is guaranteed to be sent prior to the exit code being
  * transmitted.
+ *
+ * Whenever the process has been quiet for the heartbeat interval, an empty 'o' is sent, or a
+ * lone 'b' when the Heartbeats capability has been negotiated.
+ *
+ * When the ExitReasons capability has been negotiated, 'X' is sent in place of 'x'. Its exit
+ * code is followed by a four byte integer conveying the reason for exiting (see ExitReason), and
+ * then a four byte integer providing the length of any UTF-8 message to follow.
  */
 class JvmExecutor(
     processId: Int,
-    properties: ThreadGroupProperties, securityManager: ThreadGroupSecurityManager, useDefaultSecurityManager: Boolean,
+    protocol: Protocol,
+    properties: ThreadGroupProperties, environment: ThreadGroupEnvironment,
+    securityManager: ThreadGroupSecurityManager, useDefaultSecurityManager: Boolean,
     stdin: ThreadGroupInputStream, stdinTimeout: FiniteDuration, stdout: ThreadGroupPrintStream, stderr: ThreadGroupPrintStream,
     in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]],
     exitTimeout: FiniteDuration, outputDrainTimeAtExit: FiniteDuration,
     heatbeatInterval: FiniteDuration,
     processDirPath: Path,
+    contentStorePath: Path,
     exposedPropNames: Seq[String]
 ) extends Actor with ActorLogging with Timers {
 
@@ -238,23 +364,62 @@ class JvmExecutor(
   implicit val mat: ActorMaterializer = ActorMaterializer()
   import context.dispatcher
 
+  private val blockingEc = context.system.dispatchers.lookup("akka.actor.default-blocking-io-dispatcher")
+
+  // What is sent to the client once the process has started or exited early, optionally preceded
+  // by the digests of the manifest that are present, as they're sent before the tar is received
+
+  private val output = Promise[Source[ByteString, NotUsed]]()
+  private val presentBytes = Promise[ByteString]()
+
+  if (!protocol.supports(Protocol.ContentAddressing))
+    presentBytes.success(ByteString.empty)
+
+  out.success(
+    Source
+      .fromFuture(presentBytes.future)
+      .concat(Source.fromFutureSource(output.future))
+      .mapMaterializedValue(_ => NotUsed)
+  )
+
   log.debug("Process actor starting for {}", processId)
   in
-    .via(new ProcessParameterParser)
-    .runFoldAsync("") {
-      case (_, ProcessParameterParser.CommandLine(value)) =>
-        Future.successful(value)
-      case (cl, ProcessParameterParser.Archive(value)) =>
+    .via(new ProcessParameterParser(protocol))
+    .runFoldAsync(Launch()) {
+      case (launch, ProcessParameterParser.Console(_, _)) =>
+        // The JVM has the one console, so the process can't be given its own
+        Future.successful(launch)
+      case (launch, ProcessParameterParser.Environment(value)) =>
+        Future.successful(launch.copy(env = value))
+      case (launch, ProcessParameterParser.Manifest(entries)) =>
+        Future(ContentStore.present(contentStorePath, entries))(blockingEc)
+          .map { present =>
+            presentBytes.trySuccess(presentToBytes(present))
+            launch.copy(manifest = entries)
+          }
+      case (launch, ProcessParameterParser.CommandLine(value)) =>
+        // A client that negotiated content addressing needn't have sent a manifest
+        presentBytes.trySuccess(ByteString.empty)
+        Future.successful(launch.copy(commandLine = value))
+      case (launch, ProcessParameterParser.Archive(value, gzipped)) =>
         TarStreamWriter
-          .writeTarStream(
-            value,
-            processDirPath,
-            context.system.dispatchers.lookup("akka.actor.default-blocking-io-dispatcher")
-          )
-          .map(_ => cl)
-      case (cl, ProcessParameterParser.Stdin(value)) =>
-        self ! StartProcess(cl, value)
-        Future.successful(cl)
+          .writeTarStream(value, processDirPath, blockingEc, gzipped)
+          .flatMap { _ =>
+            if (launch.manifest.nonEmpty)
+              Future(ContentStore.materialize(contentStorePath, processDirPath, launch.manifest))(blockingEc)
+            else
+              Future.successful(())
+          }
+          .map(_ => launch)
+      case (launch, ProcessParameterParser.Stdin(value)) =>
+        self ! StartProcess(launch, value)
+        Future.successful(launch)
+      case (launch, ProcessParameterParser.Signal(value)) =>
+        self ! SignalProcess(value)
+        Future.successful(launch)
+      case (launch, ProcessParameterParser.WindowSize(_, _)) =>
+        self ! SignalProcess(SIGWINCH)
+        Future.successful(launch)
     }
     .recover {
       case e: AbruptStageTerminationException =>
@@ -263,7 +428,7 @@ class JvmExecutor(
         throw e
       case NonFatal(e) =>
         log.error(e, "Error while processing stream")
-        self ! ExitEarly(1, Some(e.getMessage))
+        self ! ExitEarly(ExitStatus(1, ExitReason.LoadFailed, Option(e.getMessage)))
         throw e
     }
     .andThen {
@@ -273,9 +438,33 @@ class JvmExecutor(
   def receive: Receive =
     starting(unstopped = true)
 
+  /**
+   * Conveys an exit status as an 'X' when the client supports exit reasons, or otherwise as an
+   * 'x' that is preceded by any message upon stderr.
+   */
+  private def exitToBytes(exitStatus: ExitStatus): ByteString =
+    if (protocol.supports(Protocol.ExitReasons))
+      exitReasonToBytes(exitStatus)
+    else
+      exitStatus.message.fold(ByteString.empty) { e =>
+        val errorBytes = ByteString(e)
+        StderrPrefix ++ sizeToBytes(errorBytes.length) ++ errorBytes
+      } ++
+        exitStatusToBytes(exitStatus.code)
+
+  /**
+   * Conveys that the connection is alive while the process is otherwise quiet, as a 'b' when the
+   * client supports heartbeats, or otherwise as empty stdout.
+   */
+  private def keepAliveBytes: ByteString =
+    if (protocol.supports(Protocol.Heartbeats))
+      HeartbeatBytes
+    else
+      StdoutPrefix ++ sizeToBytes(0)
+
   def starting(unstopped: Boolean): Receive = {
-    case StartProcess(commandLine, stdinSource) if unstopped =>
-      val commandLineArgs = commandLine.split("\u0000").toVector
+    case StartProcess(launch, stdinSource) if unstopped =>
+      val commandLineArgs = launch.commandLine.split("\u0000").toVector
 
       JavaArgs.parse(commandLineArgs) match {
         case Right(javaConfig) =>
@@ -292,20 +481,69 @@ class JvmExecutor(
           val (stdoutPos, stdoutSource) = createPrintStreamAndSource
           val (stderrPos, stderrSource) = createPrintStreamAndSource
 
-          val exitStatusPromise = Promise[Int]()
+          val exitStatusPromise = Promise[ExitStatus]()
+
+          // The last terminating signal that the process was sent, if any. Exiting with 128 plus
+          // that signal, as the JVM does without a handler for it, is conveyed as being signaled.
+          val signalled = new AtomicInteger(0)
+          def exitReason(status: Int): Int =
+            if (signalled.get != 0 && status == 128 + signalled.get) ExitReason.Signaled else ExitReason.Exited
+
+          // Should the process be stopped without having exited, its exit status is conveyed all
+          // the same, ending its output so that the status follows it.
+          def abandon(exitStatus: ExitStatus): Unit =
+            if (exitStatusPromise.trySuccess(exitStatus)) {
+              stdoutPos.close()
+              stderrPos.close()
+            }
 
-          // Resolve our process classes
-          val classpath = javaConfig.cp.flatMap(cp => resolvePaths(processDirPath, Paths.get(cp)).map(_.toUri.toURL))
-          val classLoader = new URLClassLoader(classpath.toArray, null)
+          // Resolve our process modules, should it have any, and then its classes. The modules are
+          // defined within a layer of their own, whose loader is the parent of the class path's.
+          val roots =
+            javaConfig.mode match {
+              case ModuleExecutionMode(m, _, _) => m +: javaConfig.addModules
+              case _                            => javaConfig.addModules
+            }
+          val layer = Try {
+            if ((roots.nonEmpty || javaConfig.addOpens.nonEmpty || javaConfig.addExports.nonEmpty) && !ModuleSystem.Supported)
+              throw new UnsupportedOperationException("The module system requires landlordd to run upon Java 9 or later")
+            if (roots.nonEmpty)
+              Some(ModuleSystem.defineLayer(javaConfig.modulePath.flatMap(mp => resolvePaths(processDirPath, Paths.get(mp))), roots))
+            else
+              None
+          }
+          // As with `java`, agent jars are appended to the class path
+          val agentJars = javaConfig.agents.map { case (jar, _) => processDirPath.resolve(jar) }
+          val classpath =
+            javaConfig.cp.flatMap(cp => resolvePaths(processDirPath, Paths.get(cp)).map(_.toUri.toURL)) ++
+              agentJars.map(_.toUri.toURL)
+          val classLoader = new URLClassLoader(classpath.toArray, layer.toOption.flatten.flatMap(_.loader).orNull)
           val classLoaderWeakRef = new WeakReference(classLoader)
 
           try {
-            val (clsName, args) =
+            val processLayer = layer.get
+
+            (classLoader +: processLayer.flatMap(_.loader).toList)
+              .foreach(setAssertionStatus(_, javaConfig.assertions))
+
+            val agents =
+              agentJars.zip(javaConfig.agents).map {
+                case (jar, (_, options)) => loadAgent(jar, options, classLoader)
+              }
+
+            javaConfig.resourceHints.maxHeapSize.filter(_ > Runtime.getRuntime.maxMemory).foreach { size =>
+              log.warning("Process {} asks for a maximum heap of {} bytes, which exceeds landlordd's of {}", processId, size, Runtime.getRuntime.maxMemory)
+            }
+
+            val (cls, args) =
               javaConfig.mode match {
-                case ClassExecutionMode(c, a) => c -> a
+                case ClassExecutionMode(c, a)     => classLoader.loadClass(c) -> a
+                case ModuleExecutionMode(m, c, a) => processLayer.get.loadMainClass(m, c) -> a
               }
 
-            val cls = classLoader.loadClass(clsName)
+            ModuleSystem.addOpens(javaConfig.addOpens, processLayer, classLoader)
+            ModuleSystem.addExports(javaConfig.addExports, processLayer, classLoader)
+
             val meth = cls.getMethod("main", classOf[Array[String]])
 
             // Launch our "process"
@@ -318,12 +556,12 @@ class JvmExecutor(
                       case ite: InvocationTargetException =>
                         ite.getCause match {
                           case ExitException(s) =>
-                            Some(s) // It is normal for this exception to occur given that we want the process to explicitly exit
+                            Some(ExitStatus(s, exitReason(s))) // It is normal for this exception to occur given that we want the process to explicitly exit
                           case null =>
                             stderr.fallback.println(s"An unexpected exception with a null cause has occurred within landlord given process $processId. Stacktrace follows.")
                             ite.printStackTrace(stderr.fallback)
                             stderr.println("Something went wrong - see Landlord's log")
-                            Some(70) // EXIT_SOFTWARE, Internal Software Error as defined in BSD sysexits.h
+                            Some(ExitStatus(70, ExitReason.UncaughtException)) // EXIT_SOFTWARE, Internal Software Error as defined in BSD sysexits.h
                           case otherCause =>
                             val msg = s"An uncaught error for process $processId. Stacktrace follows. The process will continue to run unless System.exit is called."
                             stderr.fallback.println(msg)
@@ -333,12 +571,12 @@ class JvmExecutor(
                             None
                         }
                       case ExitException(s) =>
-                        Some(s) // It is normal for this exception to occur given that we want the process to explicitly exit
+                        Some(ExitStatus(s, exitReason(s))) // It is normal for this exception to occur given that we want the process to explicitly exit
                       case otherException =>
                         stderr.fallback.println(s"An internal error has occurred within landlord given process $processId. Stacktrace follows.")
                         otherException.printStackTrace(stderr.fallback)
                         stderr.println("Something went wrong - see Landlord's log")
-                        Some(70) // EXIT_SOFTWARE, Internal Software Error as defined in BSD sysexits.h
+                        Some(ExitStatus(70, ExitReason.UncaughtException)) // EXIT_SOFTWARE, Internal Software Error as defined in BSD sysexits.h
                     }
                     result
                   }
@@ -399,7 +637,7 @@ class JvmExecutor(
 
                           log.debug("All threads in group {} have terminated, cleaning up", group.getName)
 
-                          exitStatusPromise.success(status)
+                          exitStatusPromise.trySuccess(status)
 
                           try {
                             Thread.sleep(outputDrainTimeAtExit.toMillis)
@@ -413,6 +651,7 @@ class JvmExecutor(
                           stdout.destroy()
                           stderr.destroy()
                           properties.destroy()
+                          environment.destroy()
                           securityManager.destroy()
 
                         }, s"${context.system.name}-cleanup").start()
@@ -444,6 +683,13 @@ class JvmExecutor(
                   }
                   properties.init(props)
 
+                  val env = new java.util.HashMap[String, String](environment.fallback)
+                  launch.env.foreach {
+                    case (name, value) =>
+                      env.put(name, value)
+                  }
+                  environment.init(env)
+
                   securityManager.init(new SecurityManager() {
                     override def checkExit(status: Int): Unit =
                       throw ExitException(status) // This will be caught as an uncaught exception
@@ -455,23 +701,35 @@ class JvmExecutor(
                     override def checkPermission(perm: Permission, context: Object): Unit =
                       if (useDefaultSecurityManager) super.checkPermission(perm, context)
                   })
+
+                  // As with `java`, a process whose agents fail to start exits
+                  try {
+                    agents.foreach(_.start())
+                  } catch {
+                    case e: InvocationTargetException =>
+                      System.err.println("Processing of -javaagent failed")
+                      e.getCause.printStackTrace()
+                      throw ExitException(1)
+                  }
+
                   meth.invoke(null, args.toArray.asInstanceOf[Object])
                 }: Runnable,
-                "main-process-" + processId
+                "main-process-" + processId,
+                javaConfig.resourceHints.threadStackSize.getOrElse(0L) // 0 is the JVM's default
               )
 
-            out.success(
+            output.success(
               Source.single(processIdToBytes(processId))
                 .concat(
                   stdoutSource
                     .map(bytes => StdoutPrefix ++ sizeToBytes(bytes.size) ++ bytes)
                     .merge(stderrSource.map(bytes => StderrPrefix ++ sizeToBytes(bytes.size) ++ bytes))
-                    .keepAlive(heatbeatInterval, () => StdoutPrefix ++ sizeToBytes(0))
+                    .keepAlive(heatbeatInterval, () => keepAliveBytes)
                     .concat(
                       Source.fromFuture(
                         exitStatusPromise
                           .future
-                          .map(exitStatusToBytes)
+                          .map(exitToBytes)
                       )
                     )
                 )
@@ -488,35 +746,31 @@ class JvmExecutor(
             processThread.setContextClassLoader(classLoader)
             processThread.start()
 
-            context.become(started(cls, processThreadGroup, stopInProgress))
+            context.become(started(cls, processThreadGroup, stopInProgress, signalled, abandon))
           } catch {
             case e: UnsupportedClassVersionError =>
               classLoader.close()
-              self ! ExitEarly(1, Some(if (e.getCause != null) e.getCause.toString else e.toString))
+              self ! ExitEarly(ExitStatus(1, ExitReason.LoadFailed, Some(if (e.getCause != null) e.getCause.toString else e.toString)))
             case NonFatal(e) =>
               classLoader.close()
-              self ! ExitEarly(1, Some(if (e.getCause != null) e.getCause.toString else e.toString))
+              self ! ExitEarly(ExitStatus(1, ExitReason.LoadFailed, Some(if (e.getCause != null) e.getCause.toString else e.toString)))
           }
         case Left(errors) =>
-          self ! ExitEarly(1, Some(errors.mkString(",")))
+          self ! ExitEarly(ExitStatus(1, ExitReason.LoadFailed, Some(errors.mkString(","))))
       }
 
     case _: StartProcess =>
-      self ! ExitEarly(128 + SIGINT, None)
+      self ! ExitEarly(ExitStatus(128 + SIGINT, ExitReason.Signaled))
 
     case _: SignalProcess =>
       context.become(starting(unstopped = false))
 
-    case ExitEarly(exitStatus, errorMessage) =>
-      out.success(
+    case ExitEarly(exitStatus) =>
+      presentBytes.trySuccess(ByteString.empty)
+      output.success(
         Source
           .single(
-            processIdToBytes(processId) ++
-              errorMessage.fold(ByteString.empty) { e =>
-                val errorBytes = ByteString(e)
-                StderrPrefix ++ sizeToBytes(errorBytes.length) ++ errorBytes
-              } ++
-              exitStatusToBytes(exitStatus)
+            processIdToBytes(processId) ++ exitToBytes(exitStatus)
           )
           .watchTermination() {
             case (m, done) =>
@@ -529,16 +783,22 @@ class JvmExecutor(
       )
   }
 
-  def started(mainClass: Class[_], processThreadGroup: ThreadGroup, stopInProgress: AtomicBoolean): Receive = {
+  def started(
+    mainClass: Class[_], processThreadGroup: ThreadGroup, stopInProgress: AtomicBoolean,
+    signalled: AtomicInteger, abandon: ExitStatus => Unit): Receive = {
     case SignalProcess(signal) =>
       if (!stopInProgress.get && !processThreadGroup.isDestroyed) { // Best effort
         try {
+          if (signal == SIGABRT || signal == SIGINT || signal == SIGTERM)
+            signalled.set(signal)
           new Thread(
             processThreadGroup, { () =>
             try {
               val signalMeth = mainClass.getMethod("trap", Integer.TYPE)
               signalMeth.invoke(null, signal.asInstanceOf[Object])
             } catch {
+              case _: NoSuchMethodException if signal == SIGINT || signal == SIGTERM =>
+                throw ExitException(128 + signal) // As the JVM would, run the shutdown hooks and exit
               case _: NoSuchMethodException =>
             }
           }: Runnable, s"${context.system.name}-trap"
@@ -577,6 +837,8 @@ class JvmExecutor(
         log.error(
           "RESOURCE RETENTION - Process {} has not called `System.exit` after {}.\nNote when using `trap`, `System.exit` must get called, even with 0, so that the process can be unloaded.",
           processId, exitTimeout)
+      if (signalled.get != 0)
+        abandon(ExitStatus(128 + signalled.get, ExitReason.Signaled))
       context.stop(self)
   }
 
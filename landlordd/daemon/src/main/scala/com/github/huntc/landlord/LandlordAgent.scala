@@ -0,0 +1,24 @@
+package com.github.huntc.landlord
+
+import java.lang.instrument.Instrumentation
+
+/**
+ * landlordd's own Java agent, which is declared by its jar and loaded by its start script via
+ * `-javaagent`. It retains the JVM's instrumentation, which landlordd requires in order to open
+ * and export packages of the JDK's modules to a process.
+ */
+object LandlordAgent {
+  @volatile private var retained: Option[Instrumentation] = None
+
+  /**
+   * The instrumentation of the JVM, provided that landlordd was started with its agent.
+   */
+  def instrumentation: Option[Instrumentation] =
+    retained
+
+  def premain(args: String, instrumentation: Instrumentation): Unit =
+    retained = Option(instrumentation)
+
+  def agentmain(args: String, instrumentation: Instrumentation): Unit =
+    retained = Option(instrumentation)
+}
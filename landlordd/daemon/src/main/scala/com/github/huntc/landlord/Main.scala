@@ -14,7 +14,7 @@ import java.util.concurrent.atomic.AtomicInteger
 
 import akka.stream.alpakka.unixdomainsocket.scaladsl.UnixDomainSocket
 
-import scala.concurrent.Promise
+import scala.concurrent.{ ExecutionContext, Future, Promise }
 import scala.concurrent.duration._
 import scala.util.{ Failure, Success }
 
@@ -149,49 +149,99 @@ object Main extends App {
 
   private final val ProcessIDPrefix = "process-"
 
+  /**
+   * Splits a source into its first `n` bytes and the remainder, the latter being available once
+   * the former has been received.
+   */
+  def splitAt(n: Int, source: Source[ByteString, NotUsed])(implicit mat: Materializer): Future[(ByteString, Source[ByteString, NotUsed])] = {
+    implicit val ec: ExecutionContext = mat.executionContext
+    source
+      .prefixAndTail(1)
+      .runWith(Sink.head)
+      .flatMap {
+        case (Seq(bytes), tail) if bytes.size >= n =>
+          val remaining = bytes.drop(n)
+          Future.successful(bytes.take(n) -> (if (remaining.nonEmpty) Source.single(remaining).concat(tail) else tail))
+        case (Seq(bytes), tail) =>
+          splitAt(n - bytes.size, tail).map { case (rest, remaining) => (bytes ++ rest) -> remaining }
+        case _ =>
+          Future.failed(new IllegalStateException(s"Expected $n more bytes"))
+      }
+  }
+
   def controlFlow(
     reaper: ActorRef,
-    launchInfoOp: (Source[ByteString, NotUsed], Promise[Source[ByteString, NotUsed]]) => (Int, Props),
+    protocol: Protocol,
+    launchInfoOp: (Protocol, Source[ByteString, NotUsed], Promise[Source[ByteString, NotUsed]]) => (Int, Props),
     sendKillOp: (ActorSelection, Int) => Unit
-  )(implicit system: ActorSystem, mat: Materializer): Flow[ByteString, ByteString, NotUsed] =
+  )(implicit system: ActorSystem, mat: Materializer): Flow[ByteString, ByteString, NotUsed] = {
+
+    import system.dispatcher
+
+    def command(negotiated: Option[Protocol], prefix: Seq[ByteString], tail: Source[ByteString, NotUsed]): Source[ByteString, NotUsed] =
+      prefix.headOption match {
+        case Some(firstBytes) if firstBytes.iterator.getByte == 'h' && negotiated.isEmpty =>
+          Source.fromFutureSource(
+            splitAt(Protocol.HelloSize, Source.single(firstBytes.drop(1)).concat(tail))
+              .map {
+                case (hello, remaining) =>
+                  val nextNegotiated = protocol.negotiate(Protocol.fromHello(hello))
+                  Source
+                    .single(protocol.toHello)
+                    .concat(
+                      Source.fromFutureSource(
+                        remaining
+                          .prefixAndTail(1)
+                          .runWith(Sink.head)
+                          .map {
+                            case (nextPrefix, nextTail) =>
+                              command(Some(nextNegotiated), nextPrefix, nextTail)
+                          }
+                      )
+                    )
+              }
+          ).mapMaterializedValue(_ => NotUsed)
+        case Some(firstBytes) if ProcessParameterParser.beginsLaunch(firstBytes.iterator.getByte, negotiated.getOrElse(Protocol.Legacy)) =>
+          val in = Source.single(firstBytes).concat(tail)
+          val out = Promise[Source[ByteString, NotUsed]]()
+          val (processId, jvmExecutorProps) = launchInfoOp(negotiated.getOrElse(Protocol.Legacy), in, out)
+          val jvmExecutor = system.actorOf(jvmExecutorProps, ProcessIDPrefix + processId)
+          reaper ! JvmExecutorReaper.Register(jvmExecutor)
+          Source.fromFutureSource(out.future).mapMaterializedValue(_ => NotUsed)
+        case Some(firstBytes) if firstBytes.iterator.getByte == 'k' =>
+          Source
+            .single(firstBytes.drop(1))
+            .concat(tail)
+            .fold(ByteString.empty) { (acc, bs) =>
+              if (acc.size + bs.size <= 8)
+                acc ++ bs
+              else
+                acc
+            }
+            .map { bs =>
+              val iter = bs.iterator
+              iter.getInt(ByteOrder.BIG_ENDIAN) -> iter.getInt(ByteOrder.BIG_ENDIAN)
+            }
+            .runForeach {
+              case (processId, signal) =>
+                sendKillOp(system.actorSelection(system.child(ProcessIDPrefix + processId)), signal)
+            }
+          Source.empty[ByteString]
+        case Some(_) =>
+          tail.runWith(Sink.ignore)
+          Source.single(ByteString("???"))
+        case None =>
+          Source.empty[ByteString]
+      }
 
     Flow[ByteString]
       .prefixAndTail(1)
       .map {
         case (prefix, tail) =>
-          prefix.headOption match {
-            case Some(firstBytes) if firstBytes.iterator.getByte == 'l' =>
-              val in = Source.single(firstBytes.drop(1)).concat(tail)
-              val out = Promise[Source[ByteString, NotUsed]]()
-              val (processId, jvmExecutorProps) = launchInfoOp(in, out)
-              val jvmExecutor = system.actorOf(jvmExecutorProps, ProcessIDPrefix + processId)
-              reaper ! JvmExecutorReaper.Register(jvmExecutor)
-              Source.fromFutureSource(out.future)
-            case Some(firstBytes) if firstBytes.iterator.getByte == 'k' =>
-              Source
-                .single(firstBytes.drop(1))
-                .concat(tail)
-                .fold(ByteString.empty) { (acc, bs) =>
-                  if (acc.size + bs.size <= 8)
-                    acc ++ bs
-                  else
-                    acc
-                }
-                .map { bs =>
-                  val iter = bs.iterator
-                  iter.getInt(ByteOrder.BIG_ENDIAN) -> iter.getInt(ByteOrder.BIG_ENDIAN)
-                }
-                .runForeach {
-                  case (processId, signal) =>
-                    sendKillOp(system.actorSelection(system.child(ProcessIDPrefix + processId)), signal)
-                }
-              Source.empty[ByteString]
-            case _ =>
-              tail.runWith(Sink.ignore)
-              Source.single(ByteString("???"))
-          }
+          command(None, prefix, tail)
       }
       .flatMapConcat(identity)
+  }
 
   /*
    * Main entry point.
@@ -216,6 +266,9 @@ object Main extends App {
       val properties = new ThreadGroupProperties(System.getProperties)
       System.setProperties(properties)
 
+      val environment = new ThreadGroupEnvironment(System.getenv())
+      val environmentInstalled = ThreadGroupEnvironment.install(environment)
+
       val securityManager = new ThreadGroupSecurityManager(System.getSecurityManager)
       System.setSecurityManager(securityManager)
 
@@ -226,16 +279,28 @@ object Main extends App {
 
       val reaper = system.actorOf(JvmExecutorReaper.props, "reaper")
 
-      def launchInfoOp(in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) = {
+      if (!environmentInstalled)
+        system.log.warning("Unable to install an environment per process; environment variables will not be forwarded")
+
+      val protocol =
+        Protocol(
+          Protocol.Version,
+          if (environmentInstalled) Protocol.Capabilities else Protocol.Capabilities - Protocol.EnvForwarding
+        )
+
+      def launchInfoOp(protocol: Protocol, in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) = {
         val processId = nextProcessId.getAndIncrement()
         processId -> JvmExecutor.props(
           processId,
-          properties, securityManager, config.useDefaultSecurityManager,
+          protocol,
+          properties, environment,
+          securityManager, config.useDefaultSecurityManager,
           stdin, config.stdinTimeout, stdout, stderr,
           in, out,
           config.exitTimeout, config.outputDrainTimeAtExit,
           config.heartbeatInterval,
           config.processDirPath.resolve(processId.toString),
+          config.processDirPath.resolve("content"),
           config.exposedProps
         )
       }
@@ -251,7 +316,7 @@ object Main extends App {
               .toMat(Sink.foreach { connection =>
                 system.log.debug("New unix connection {}", connection)
 
-                connection.handleWith(controlFlow(reaper, launchInfoOp, sendKillOp))
+                connection.handleWith(controlFlow(reaper, protocol, launchInfoOp, sendKillOp))
               })(Keep.left)
               .run
 
@@ -277,7 +342,7 @@ object Main extends App {
               .toMat(Sink.foreach { connection =>
                 system.log.debug("New tcp connection {}", connection)
 
-                connection.handleWith(controlFlow(reaper, launchInfoOp, sendKillOp))
+                connection.handleWith(controlFlow(reaper, protocol, launchInfoOp, sendKillOp))
 
               })(Keep.left)
               .run
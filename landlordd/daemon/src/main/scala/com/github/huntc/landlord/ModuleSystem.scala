@@ -0,0 +1,215 @@
+package com.github.huntc.landlord
+
+import java.lang.instrument.Instrumentation
+import java.nio.file.Path
+import java.util.{ Collections, Optional }
+
+import scala.collection.JavaConverters._
+import scala.util.Try
+
+/**
+ * Functions to run processes upon the module system, which is only available as of Java 9. As
+ * landlordd also runs upon Java 8, the module system is used via reflection.
+ *
+ * A process's module path is resolved into a layer of its own, whose parent is the boot layer.
+ * Its modules are defined to the one class loader, which the process's class path is loaded by the
+ * child of, so that the class path can use the modules' exported packages as it would with `java`.
+ */
+object ModuleSystem {
+  /**
+   * Whether the module system is available, i.e. landlordd runs upon Java 9 or later.
+   */
+  val Supported: Boolean =
+    Try(Class.forName("java.lang.ModuleLayer")).isSuccess
+
+  val AllUnnamed = "ALL-UNNAMED"
+
+  val AllModulePath = "ALL-MODULE-PATH"
+
+  /**
+   * The roots that `--add-modules` accepts which refer to modules of the boot layer, and so
+   * needn't be resolved again.
+   */
+  val BootRoots = Set("ALL-DEFAULT", "ALL-SYSTEM")
+
+  /**
+   * A package of a module that is to be opened or exported to other modules, as per `--add-opens`
+   * and `--add-exports`.
+   */
+  case class Directive(module: String, pkg: String, targets: Seq[String])
+
+  object Directive {
+    /**
+     * Parses a directive of the form `<module>/<package>=<target-module>(,<target-module>)*`.
+     */
+    def parse(value: String): Option[Directive] =
+      value.split("=", 2) match {
+        case Array(source, targets) =>
+          source.split("/", 2) match {
+            case Array(module, pkg) if module.nonEmpty && pkg.nonEmpty && targets.nonEmpty =>
+              Some(Directive(module, pkg, targets.split(",").toVector))
+            case _ =>
+              None
+          }
+        case _ =>
+          None
+      }
+  }
+
+  private lazy val ModuleClass = Class.forName("java.lang.Module")
+  private lazy val LayerClass = Class.forName("java.lang.ModuleLayer")
+  private lazy val ControllerClass = Class.forName("java.lang.ModuleLayer$Controller")
+  private lazy val ConfigurationClass = Class.forName("java.lang.module.Configuration")
+  private lazy val FinderClass = Class.forName("java.lang.module.ModuleFinder")
+  private lazy val ReferenceClass = Class.forName("java.lang.module.ModuleReference")
+  private lazy val DescriptorClass = Class.forName("java.lang.module.ModuleDescriptor")
+
+  /**
+   * The modules of a process, as defined by `defineLayer`.
+   */
+  final class Layer private[ModuleSystem] (private[ModuleSystem] val controller: AnyRef) {
+    private[ModuleSystem] val layer: AnyRef =
+      ControllerClass.getMethod("layer").invoke(controller)
+
+    /**
+     * The class loader that the modules of this layer are defined to, if any were resolved
+     * from the module path.
+     */
+    def loader: Option[ClassLoader] =
+      LayerClass
+        .getMethod("modules")
+        .invoke(layer)
+        .asInstanceOf[java.util.Set[AnyRef]]
+        .asScala
+        .headOption
+        .map(module => ModuleClass.getMethod("getClassLoader").invoke(module).asInstanceOf[ClassLoader])
+
+    /**
+     * Loads the main class of `module`, which is that given or otherwise the one that its
+     * descriptor declares. Its package is exported to landlordd so that it may be invoked.
+     */
+    def loadMainClass(module: String, mainClass: Option[String]): Class[_] = {
+      val found =
+        findModule(layer, module)
+          .getOrElse(throw new IllegalArgumentException(s"Module $module not found"))
+      val name =
+        mainClass
+          .orElse {
+            val descriptor = ModuleClass.getMethod("getDescriptor").invoke(found)
+            optional(DescriptorClass.getMethod("mainClass").invoke(descriptor)).map(_.asInstanceOf[String])
+          }
+          .getOrElse(throw new IllegalArgumentException(s"Module $module does not have a ModuleMainClass attribute, use -m <module>/<main-class>"))
+      val loader = LayerClass.getMethod("findLoader", classOf[String]).invoke(layer, module).asInstanceOf[ClassLoader]
+      val cls = Class.forName(name, false, loader)
+
+      if (ModuleClass.getMethod("getLayer").invoke(found) eq layer)
+        ControllerClass
+          .getMethod("addExports", ModuleClass, classOf[String], ModuleClass)
+          .invoke(controller, found, name.substring(0, name.lastIndexOf('.')), moduleOf(getClass))
+
+      cls
+    }
+  }
+
+  /**
+   * Resolves `roots` and the modules that they require from `modulePath`, or otherwise the boot
+   * layer, and defines them within a new layer.
+   */
+  def defineLayer(modulePath: Seq[Path], roots: Seq[String]): Layer = {
+    val finder = finderOf(modulePath)
+    val names =
+      roots
+        .filterNot(BootRoots.contains)
+        .flatMap {
+          case AllModulePath => moduleNames(finder)
+          case root          => Seq(root)
+        }
+        .distinct
+    val boot = LayerClass.getMethod("boot").invoke(null)
+    val configuration =
+      ConfigurationClass
+        .getMethod("resolve", FinderClass, FinderClass, classOf[java.util.Collection[_]])
+        .invoke(LayerClass.getMethod("configuration").invoke(boot), finder, finderOf(Seq.empty), names.asJava)
+
+    new Layer(
+      LayerClass
+        .getMethod("defineModulesWithOneLoader", ConfigurationClass, classOf[java.util.List[_]], classOf[ClassLoader])
+        .invoke(null, configuration, Collections.singletonList(boot), null))
+  }
+
+  /**
+   * Opens the package of each directive to its targets, where ALL-UNNAMED refers to the classes
+   * that `classLoader` loads, i.e. the process's class path. Packages of the process's own modules
+   * are opened via its `layer`, whereas those of the boot layer, e.g. `java.base`, require the
+   * instrumentation that landlordd's agent obtains (see LandlordAgent).
+   */
+  def addOpens(directives: Seq[Directive], layer: Option[Layer], classLoader: ClassLoader): Unit =
+    directives.foreach(update(_, opens = true, layer, classLoader))
+
+  /**
+   * Exports the package of each directive to its targets, as `addOpens` opens them.
+   */
+  def addExports(directives: Seq[Directive], layer: Option[Layer], classLoader: ClassLoader): Unit =
+    directives.foreach(update(_, opens = false, layer, classLoader))
+
+  private def update(directive: Directive, opens: Boolean, layer: Option[Layer], classLoader: ClassLoader): Unit = {
+    val searched = layer.fold(LayerClass.getMethod("boot").invoke(null))(_.layer)
+    def module(name: String): AnyRef =
+      findModule(searched, name).getOrElse(throw new IllegalArgumentException(s"Unknown module: $name"))
+
+    val source = module(directive.module)
+    val targets = directive.targets.map {
+      case AllUnnamed => classOf[ClassLoader].getMethod("getUnnamedModule").invoke(classLoader)
+      case target     => module(target)
+    }
+
+    layer.filter(l => ModuleClass.getMethod("getLayer").invoke(source) eq l.layer) match {
+      case Some(l) =>
+        val method = ControllerClass.getMethod(if (opens) "addOpens" else "addExports", ModuleClass, classOf[String], ModuleClass)
+        targets.foreach(target => method.invoke(l.controller, source, directive.pkg, target))
+
+      case None =>
+        val instrumentation =
+          LandlordAgent.instrumentation.getOrElse(
+            throw new IllegalStateException(s"Updating module ${directive.module} requires landlordd to be started with its agent"))
+        val updated = Collections.singletonMap(directive.pkg, new java.util.HashSet[AnyRef](targets.asJava))
+        val unchanged = Collections.emptyMap[String, java.util.Set[AnyRef]]
+
+        classOf[Instrumentation]
+          .getMethod(
+            "redefineModule",
+            ModuleClass, classOf[java.util.Set[_]], classOf[java.util.Map[_, _]], classOf[java.util.Map[_, _]],
+            classOf[java.util.Set[_]], classOf[java.util.Map[_, _]])
+          .invoke(
+            instrumentation,
+            source, Collections.emptySet[AnyRef], if (opens) unchanged else updated, if (opens) updated else unchanged,
+            Collections.emptySet[AnyRef], Collections.emptyMap[AnyRef, AnyRef])
+    }
+  }
+
+  private def finderOf(paths: Seq[Path]): AnyRef =
+    FinderClass.getMethod("of", classOf[Array[Path]]).invoke(null, paths.toArray)
+
+  private def moduleNames(finder: AnyRef): Seq[String] =
+    FinderClass
+      .getMethod("findAll")
+      .invoke(finder)
+      .asInstanceOf[java.util.Set[AnyRef]]
+      .asScala
+      .toVector
+      .map { reference =>
+        val descriptor = ReferenceClass.getMethod("descriptor").invoke(reference)
+        DescriptorClass.getMethod("name").invoke(descriptor).asInstanceOf[String]
+      }
+
+  private def findModule(layer: AnyRef, name: String): Option[AnyRef] =
+    optional(LayerClass.getMethod("findModule", classOf[String]).invoke(layer, name))
+
+  private def moduleOf(cls: Class[_]): AnyRef =
+    classOf[Class[_]].getMethod("getModule").invoke(cls)
+
+  private def optional(value: AnyRef): Option[AnyRef] = {
+    val o = value.asInstanceOf[Optional[AnyRef]]
+    if (o.isPresent) Some(o.get) else None
+  }
+}
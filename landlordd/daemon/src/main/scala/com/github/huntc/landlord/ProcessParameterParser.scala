@@ -15,13 +15,82 @@ object ProcessParameterParser {
 
   sealed abstract class ProcessInputPart
 
+  case class Console(rows: Int, cols: Int) extends ProcessInputPart
+
+  case class Environment(value: Map[String, String]) extends ProcessInputPart
+
+  case class Manifest(entries: Seq[(String, String)]) extends ProcessInputPart
+
   case class CommandLine(value: String) extends ProcessInputPart
 
-  case class Archive(value: Source[ByteString, AnyRef]) extends ProcessInputPart
+  case class Archive(value: Source[ByteString, AnyRef], gzipped: Boolean = false) extends ProcessInputPart
 
   case class Stdin(value: Source[ByteString, AnyRef]) extends ProcessInputPart
 
+  case class Signal(value: Int) extends ProcessInputPart
+
+  case class WindowSize(rows: Int, cols: Int) extends ProcessInputPart
+
   class UnexpectedEOS(suffix: String) extends RuntimeException("Unexpected end of stream while receiving " + suffix)
+
+  class UnexpectedFrame(frameType: Byte) extends RuntimeException("Unexpected frame: " + frameType.toChar)
+
+  class UnsupportedCompression(code: Int) extends RuntimeException("Unsupported compression: " + code)
+
+  val GzipCode = 1
+
+  /**
+   * Determines whether a frame of the given type begins the launch of a process, given the
+   * protocol that has been negotiated.
+   */
+  def beginsLaunch(frameType: Byte, protocol: Protocol): Boolean =
+    frameType == 'l' ||
+      frameType == 'L' && protocol.supports(Protocol.LengthPrefixedDescriptor) ||
+      frameType == 't' && protocol.supports(Protocol.FramedStdin) ||
+      frameType == 'z' && protocol.supports(Protocol.GzipCompression) ||
+      frameType == 'E' && protocol.supports(Protocol.EnvForwarding) ||
+      frameType == 'm' && protocol.supports(Protocol.ContentAddressing)
+
+  private[landlord] def peekInt(bytes: ByteString, at: Int): Option[Int] =
+    if (bytes.size >= at + 4)
+      Some(bytes.drop(at).iterator.getInt(ByteOrder.BIG_ENDIAN))
+    else
+      None
+
+  /**
+   * Decodes a count followed by that many length-prefixed UTF-8 fields from the start of
+   * `bytes`, returning them along with the number of bytes that they occupy. None is returned
+   * if more bytes are required.
+   */
+  private[landlord] def peekFields(bytes: ByteString): Option[(Seq[String], Int)] = {
+    @annotation.tailrec
+    def fields(remaining: Int, at: Int, acc: List[String]): Option[(Seq[String], Int)] =
+      if (remaining == 0)
+        Some(acc.reverse -> at)
+      else
+        peekInt(bytes, at) match {
+          case Some(size) if bytes.size >= at + 4 + size =>
+            fields(remaining - 1, at + 4 + size, bytes.slice(at + 4, at + 4 + size).utf8String :: acc)
+          case _ =>
+            None
+        }
+
+    peekInt(bytes, 0).flatMap(count => fields(count, 4, List.empty))
+  }
+
+  /**
+   * Decodes fields as `peekFields` does, splitting each into a name and value at its first '='.
+   */
+  private[landlord] def peekPairs(bytes: ByteString): Option[(Seq[(String, String)], Int)] =
+    peekFields(bytes).map {
+      case (fields, size) =>
+        fields.map { f =>
+          f.split("=", 2) match {
+            case Array(name, value) => name -> value
+            case Array(name)        => name -> ""
+          }
+        } -> size
+    }
 }
 
 /**
@@ -32,20 +101,49 @@ object ProcessParameterParser {
  *
  * The stream is presented as follows:
  *
- * 1. The first line (up until a LF) are the command line args to pass to the `java` command. Arguments
- *    are separated by a null byte, i.e. \u0000 in UTF-8. The arguments are decoded as UTF-8. Note
- *    that any non-JVM options that are to be passed to the program itself should follow a class name
- *    argument (which doesn't begin with a dash), per the `java` command's format.
+ * 0. Optionally, and only when the FramedStdin capability has been negotiated, 't' followed by the
+ *    rows and columns of the terminal that the process runs upon.
+ *
+ *    Optionally, and only when the EnvForwarding capability has been negotiated, 'E' followed by a
+ *    count of environment variables and then each as the size of a name=value pair followed by its
+ *    UTF-8 bytes.
+ *
+ *    Optionally, and only when the GzipCompression capability has been negotiated, 'z' followed by
+ *    the compression of the tar, where 1 is gzip.
+ *
+ *    Optionally, and only when the ContentAddressing capability has been negotiated, 'm' followed
+ *    by a count of class path entries and then each as the size of a name=digest pair followed by
+ *    its UTF-8 bytes. The tar then only holds those entries whose digest landlordd doesn't have,
+ *    as conveyed by its reply (see JvmExecutor).
+ *
+ * 1. The command line args to pass to the `java` command, in one of two forms:
+ *
+ *    'l' followed by a line (up until a LF) where arguments are separated by a null byte, i.e. \u0000
+ *    in UTF-8. The arguments are decoded as UTF-8. Note that any non-JVM options that are to be passed
+ *    to the program itself should follow a class name argument (which doesn't begin with a dash), per
+ *    the `java` command's format.
+ *
+ *    Full example: l-cp\u0000some.jar\u0000example.Hello\u0000-b\u0000http://127.0.0.1:8080/conn
  *
- *    Full example: -cp\u0000some.jar\u0000example.Hello\u0000-b\u0000http://127.0.0.1:8080/conn
+ *    'L' followed by a count of arguments and then each argument as its size followed by its
+ *    UTF-8 bytes, permitting arguments to contain null bytes and LFs. This form requires that
+ *    the LengthPrefixedDescriptor capability has been negotiated.
  *
  * 2. The next line represents the binary tar file output of the file system that the `java`
  *    command and its host program will ultimately read from e.g. containing the class files.
  *
+ *    When preceded by 'z', the tar is instead compressed and sent as a sequence of chunks, each
+ *    being its size followed by that many bytes, until a chunk with a size of 0.
+ *
  * 3. The stream then represents stdin until the stream is completed. The input is decoded as UTF-8.
  *
+ *    When the FramedStdin capability has been negotiated, the stream is instead a sequence of frames
+ *    until it is completed: 'i' followed by a size and that many bytes of stdin, 'c' upon the end of
+ *    stdin, 's' followed by a signal to send to the process, and 'w' followed by the rows and columns
+ *    of the client's window upon it changing.
+ *
  */
-class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionContext)
+class ProcessParameterParser(protocol: Protocol)(implicit mat: ActorMaterializer, ec: ExecutionContext)
   extends GraphStage[FlowShape[ByteString, ProcessParameterParser.ProcessInputPart]] {
 
   import ProcessParameterParser._
@@ -58,23 +156,96 @@ class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionConte
   override def createLogic(attr: Attributes): GraphStageLogic =
     new GraphStageLogic(shape) {
 
-      def receiveCommandLine(commandLine: StringBuilder)(bytes: ByteString): ByteString = {
-        val posn = bytes.indexOf('\n')
-        val (left, right) = bytes.splitAt(posn)
-        if (left.isEmpty) {
-          commandLine ++= right.utf8String
+      /**
+       * Continues with the bytes left over from the previous state, pulling for more if there
+       * are none.
+       */
+      def continue(carry: ByteString): ByteString = {
+        if (carry.nonEmpty || isClosed(in))
+          asyncReceive.invoke(())
+        else if (!hasBeenPulled(in))
+          pull(in)
+        carry
+      }
+
+      /**
+       * Awaits more bytes in order to complete what is being received, failing if there
+       * will be none.
+       */
+      def receiveMore(bytes: ByteString, receiving: String): ByteString = {
+        if (isClosed(in))
+          failStage(new UnexpectedEOS(receiving))
+        else if (!hasBeenPulled(in))
           pull(in)
-          ByteString.empty
+        bytes
+      }
+
+      def receiveFrame(bytes: ByteString): ByteString =
+        bytes.headOption match {
+          case Some(frameType) if frameType == 'l' =>
+            become(receiveCommandLine)
+            receive(bytes.drop(1))
+          case Some(frameType) if frameType == 't' && protocol.supports(Protocol.FramedStdin) =>
+            (peekInt(bytes, 1), peekInt(bytes, 5)) match {
+              case (Some(rows), Some(cols)) =>
+                emit(out, Console(rows, cols))
+                continue(bytes.drop(9))
+              case _ =>
+                receiveMore(bytes, "console")
+            }
+          case Some(frameType) if frameType == 'z' && protocol.supports(Protocol.GzipCompression) =>
+            peekInt(bytes, 1) match {
+              case Some(GzipCode) =>
+                gzipped = true
+                continue(bytes.drop(5))
+              case Some(code) =>
+                failStage(new UnsupportedCompression(code))
+                become(receiveFinished())
+                ByteString.empty
+              case None =>
+                receiveMore(bytes, "compression")
+            }
+          case Some(frameType) if frameType == 'E' && protocol.supports(Protocol.EnvForwarding) =>
+            peekPairs(bytes.drop(1)) match {
+              case Some((env, size)) =>
+                emit(out, Environment(env.toMap))
+                continue(bytes.drop(1 + size))
+              case None =>
+                receiveMore(bytes, "environment")
+            }
+          case Some(frameType) if frameType == 'm' && protocol.supports(Protocol.ContentAddressing) =>
+            peekPairs(bytes.drop(1)) match {
+              case Some((entries, size)) =>
+                emit(out, Manifest(entries))
+                continue(bytes.drop(1 + size))
+              case None =>
+                receiveMore(bytes, "manifest")
+            }
+          case Some(frameType) if frameType == 'L' && protocol.supports(Protocol.LengthPrefixedDescriptor) =>
+            peekFields(bytes.drop(1)) match {
+              case Some((args, size)) =>
+                emit(out, CommandLine(args.mkString("\u0000")))
+                becomeReceiveTar()
+                continue(bytes.drop(1 + size))
+              case None =>
+                receiveMore(bytes, "command line")
+            }
+          case Some(frameType) =>
+            failStage(new UnexpectedFrame(frameType))
+            become(receiveFinished())
+            ByteString.empty
+          case None =>
+            receiveMore(bytes, "command line")
+        }
+
+      def receiveCommandLine(bytes: ByteString): ByteString = {
+        val posn = bytes.indexOf('\n')
+        if (posn < 0) {
+          receiveMore(bytes, "command line")
         } else {
-          commandLine ++= left.utf8String
-          emit(out, CommandLine(commandLine.toString))
+          emit(out, CommandLine(bytes.take(posn).utf8String))
           becomeReceiveTar()
-          val carry = right.drop(1)
-          if (carry.nonEmpty)
-            asyncReceive.invoke(())
-          else
-            pull(in)
-          carry
+          continue(bytes.drop(posn + 1))
         }
       }
 
@@ -88,8 +259,11 @@ class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionConte
             }
             .toMat(Sink.head)(Keep.both)
             .run
-        emit(out, Archive(Source.fromFutureSource(ar)))
-        become(receiveTar(queue, ByteString.empty))
+        emit(out, Archive(Source.fromFutureSource(ar), gzipped))
+        if (gzipped)
+          become(receiveCompressedTar(queue))
+        else
+          become(receiveTar(queue, ByteString.empty))
       }
 
       private val RecordSize = 512
@@ -144,6 +318,40 @@ class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionConte
           bytes
       }
 
+      def receiveCompressedTar(queue: SourceQueueWithComplete[ByteString])(bytes: ByteString): ByteString =
+        peekInt(bytes, 0) match {
+          case Some(0) =>
+            queue.complete()
+            becomeReceiveStdin()
+            continue(bytes.drop(4))
+          case Some(size) if bytes.size >= 4 + size =>
+            val enqueued = new AtomicBoolean(false)
+            queue.offer(bytes.slice(4, 4 + size)).andThen {
+              case Success(QueueOfferResult.Enqueued) =>
+                enqueued.compareAndSet(false, true)
+                asyncReceive.invoke(())
+              case _ =>
+                asyncCancel.invoke(())
+            }
+            become(receiveCompressedTarQueuePending(queue, enqueued))
+            val carry = bytes.drop(4 + size)
+            if (carry.isEmpty && !isClosed(in) && !hasBeenPulled(in)) pull(in)
+            carry
+          case _ =>
+            if (isClosed(in)) queue.complete()
+            receiveMore(bytes, "archive")
+        }
+
+      def receiveCompressedTarQueuePending(
+        queue: SourceQueueWithComplete[ByteString],
+        enqueued: AtomicBoolean)(bytes: ByteString): ByteString =
+
+        if (enqueued.get()) {
+          become(receiveCompressedTar(queue))
+          receive(bytes)
+        } else
+          bytes
+
       def becomeReceiveStdin(): Unit = {
         val (queue, stdin) =
           Source
@@ -155,9 +363,77 @@ class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionConte
             .toMat(Sink.head)(Keep.both)
             .run
         emit(out, Stdin(Source.fromFutureSource(stdin)))
-        become(receiveStdin(queue))
+        if (protocol.supports(Protocol.FramedStdin))
+          become(receiveFramedStdin(Some(queue)))
+        else
+          become(receiveStdin(queue))
       }
 
+      def receiveFramedStdin(queue: Option[SourceQueueWithComplete[ByteString]])(bytes: ByteString): ByteString =
+        bytes.headOption match {
+          case Some(frameType) if frameType == 'i' && queue.isDefined =>
+            peekInt(bytes, 1) match {
+              case Some(size) if bytes.size >= 5 + size =>
+                val enqueued = new AtomicBoolean(false)
+                queue.get.offer(bytes.slice(5, 5 + size)).andThen {
+                  case Success(QueueOfferResult.Enqueued) =>
+                    enqueued.compareAndSet(false, true)
+                    asyncReceive.invoke(())
+                  case _ =>
+                    asyncCancel.invoke(())
+                }
+                become(receiveFramedStdinQueuePending(queue.get, enqueued))
+                val carry = bytes.drop(5 + size)
+                if (carry.isEmpty && !isClosed(in) && !hasBeenPulled(in)) pull(in)
+                carry
+              case _ =>
+                receiveMore(bytes, "stdin")
+            }
+          case Some(frameType) if frameType == 'c' =>
+            queue.foreach(_.complete())
+            become(receiveFramedStdin(None))
+            continue(bytes.drop(1))
+          case Some(frameType) if frameType == 's' =>
+            peekInt(bytes, 1) match {
+              case Some(signal) =>
+                emit(out, Signal(signal))
+                continue(bytes.drop(5))
+              case None =>
+                receiveMore(bytes, "signal")
+            }
+          case Some(frameType) if frameType == 'w' =>
+            (peekInt(bytes, 1), peekInt(bytes, 5)) match {
+              case (Some(rows), Some(cols)) =>
+                emit(out, WindowSize(rows, cols))
+                continue(bytes.drop(9))
+              case _ =>
+                receiveMore(bytes, "window size")
+            }
+          case Some(frameType) =>
+            queue.foreach(_.complete())
+            failStage(new UnexpectedFrame(frameType))
+            become(receiveFinished())
+            ByteString.empty
+          case None if isClosed(in) =>
+            queue.foreach(_.complete())
+            completeStage()
+            become(receiveFinished())
+            ByteString.empty
+          case None =>
+            if (!hasBeenPulled(in)) pull(in)
+            ByteString.empty
+        }
+
+      def receiveFramedStdinQueuePending(
+        queue: SourceQueueWithComplete[ByteString],
+        enqueued: AtomicBoolean)(bytes: ByteString): ByteString =
+
+        if (enqueued.get()) {
+          become(receiveFramedStdin(Some(queue)))
+          receive(bytes)
+        } else
+          bytes
+
       def receiveStdin(queue: SourceQueueWithComplete[ByteString])(bytes: ByteString): ByteString = {
         if (bytes.nonEmpty) {
           val enqueued = new AtomicBoolean(false)
@@ -200,10 +476,12 @@ class ProcessParameterParser(implicit mat: ActorMaterializer, ec: ExecutionConte
 
       def become(receiver: ByteString => ByteString): Unit =
         receive = receiver
-      private var receive: ByteString => ByteString = receiveCommandLine(new StringBuilder)
+      private var receive: ByteString => ByteString = receiveFrame
 
       private var carry: ByteString = ByteString.empty
 
+      private var gzipped: Boolean = false
+
       private var asyncReceive: AsyncCallback[Unit] = _
       private var asyncCancel: AsyncCallback[Unit] = _
 
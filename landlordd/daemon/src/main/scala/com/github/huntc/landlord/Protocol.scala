@@ -0,0 +1,92 @@
+package com.github.huntc.landlord
+
+import java.nio.ByteOrder
+
+import akka.util.ByteString
+
+object Protocol {
+  /**
+   * The version of the protocol that landlordd speaks. Version 0 is the legacy protocol,
+   * i.e. that of a client which doesn't send a hello.
+   */
+  val Version = 1
+
+  /**
+   * The size of a hello, excluding its 'h' prefix.
+   */
+  val HelloSize = 8
+
+  /**
+   * Optional protocol features, each represented by a bit within a hello.
+   */
+  sealed abstract class Capability(val bit: Int)
+
+  case object GzipCompression extends Capability(1 << 0)
+
+  case object EnvForwarding extends Capability(1 << 1)
+
+  case object ExitReasons extends Capability(1 << 2)
+
+  case object FramedStdin extends Capability(1 << 3)
+
+  case object LengthPrefixedDescriptor extends Capability(1 << 4)
+
+  case object Heartbeats extends Capability(1 << 5)
+
+  case object ContentAddressing extends Capability(1 << 7)
+
+  case object Modules extends Capability(1 << 8)
+
+  case object Assertions extends Capability(1 << 9)
+
+  case object JavaAgents extends Capability(1 << 10)
+
+  case object ResourceHints extends Capability(1 << 11)
+
+  /**
+   * The capabilities that landlordd implements. Those that a client advertises but that
+   * aren't known here are ignored. Modules are only supported when running upon Java 9 or later,
+   * and agents are given landlordd's instrumentation only when it was started with its own agent.
+   */
+  val Capabilities: Set[Capability] =
+    Set[Capability](
+      GzipCompression, EnvForwarding, ExitReasons, FramedStdin, LengthPrefixedDescriptor, Heartbeats, ContentAddressing,
+      Assertions, JavaAgents, ResourceHints) ++
+      (if (ModuleSystem.Supported) Set(Modules) else Set.empty)
+
+  val Legacy: Protocol =
+    Protocol(0, Set.empty)
+
+  /**
+   * Decodes the version and capabilities of a hello, excluding its 'h' prefix.
+   */
+  def fromHello(bytes: ByteString): Protocol = {
+    val iter = bytes.iterator
+    val version = iter.getInt(ByteOrder.BIG_ENDIAN)
+    val bits = iter.getInt(ByteOrder.BIG_ENDIAN)
+    Protocol(version, Capabilities.filter(c => (bits & c.bit) != 0))
+  }
+}
+
+/**
+ * A protocol version and the capabilities supported at it, as exchanged via a hello.
+ *
+ * A client may begin a connection with a hello, i.e. 'h' followed by its version and a bit
+ * set of its capabilities, each as a four byte big-endian integer. landlordd responds with
+ * its own, and the remainder of the connection then uses the lower of the two versions and
+ * only those capabilities that both support.
+ */
+case class Protocol(version: Int, capabilities: Set[Protocol.Capability]) {
+  def supports(capability: Protocol.Capability): Boolean =
+    capabilities.contains(capability)
+
+  def negotiate(client: Protocol): Protocol =
+    Protocol(math.min(version, client.version), capabilities.intersect(client.capabilities))
+
+  def toHello: ByteString =
+    ByteString.newBuilder
+      .putByte('h')
+      .putInt(version)(ByteOrder.BIG_ENDIAN)
+      .putInt(capabilities.foldLeft(0)(_ | _.bit))(ByteOrder.BIG_ENDIAN)
+      .result()
+}
@@ -5,6 +5,7 @@ import akka.stream.scaladsl.{ Source, StreamConverters }
 import akka.util.ByteString
 import java.io.{ BufferedInputStream, BufferedOutputStream }
 import java.nio.file.{ attribute, Files, Path }
+import java.util.zip.GZIPInputStream
 import org.apache.commons.compress.archivers.ArchiveStreamFactory
 import org.apache.commons.compress.archivers.tar.{ TarArchiveEntry, TarArchiveInputStream }
 import scala.concurrent.{ blocking, ExecutionContext, Future }
@@ -23,7 +24,8 @@ object TarStreamWriter {
   private[landlord] def writeTarStream(
     source: Source[ByteString, AnyRef],
     rootPath: Path,
-    blockingEc: ExecutionContext
+    blockingEc: ExecutionContext,
+    gzipped: Boolean = false
   )(implicit mat: Materializer): Future[Unit] = {
 
     val TarRecordSize = 512
@@ -36,8 +38,9 @@ object TarStreamWriter {
     rootPath.toFile.mkdirs()
 
     Future {
+      val sourceInput = source.runWith(StreamConverters.asInputStream(TarInputMaxBlockingTime))
       val is =
-        new BufferedInputStream(source.runWith(StreamConverters.asInputStream(TarInputMaxBlockingTime)), TarBufferSize)
+        new BufferedInputStream(if (gzipped) new GZIPInputStream(sourceInput) else sourceInput, TarBufferSize)
       try {
         val tarInput = new ArchiveStreamFactory().createArchiveInputStream(is).asInstanceOf[TarArchiveInputStream]
         try {
@@ -2,11 +2,13 @@ package com.github.huntc.landlord
 
 import java.io.{ FileDescriptor, IOException, InputStream, PrintStream, PrintWriter }
 import java.net.InetAddress
+import java.lang.reflect.{ Field, Modifier }
 import java.security.Permission
 import java.util.function.BiConsumer
-import java.util.{ Collection => JCollection, Enumeration => JEnumeration, Map => JMap, Properties, Set => JSet }
+import java.util.{ AbstractMap, Collections, Collection => JCollection, Enumeration => JEnumeration, Map => JMap, Properties, Set => JSet }
 
 import scala.collection.immutable.HashMap
+import scala.util.control.NonFatal
 
 /**
  * A ThreadGroupMapping trait provides a mutable thread safe map that keys
@@ -178,6 +180,51 @@ class ThreadGroupProperties(props: Properties)
     super.get.values()
 }
 
+/**
+ * Environment variables to be associated with the current thread's thread group.
+ */
+class ThreadGroupEnvironment(env: JMap[String, String])
+  extends AbstractMap[String, String]
+  with ThreadGroupMapping[JMap[String, String]] {
+
+  protected val _fallback: JMap[String, String] = env
+
+  override def containsKey(key: Any): Boolean =
+    super.get.containsKey(key)
+
+  override def entrySet(): JSet[JMap.Entry[String, String]] =
+    super.get.entrySet()
+
+  override def get(key: Any): String =
+    super.get.get(key)
+}
+
+object ThreadGroupEnvironment {
+  /**
+   * Installs the environment as that which `System.getenv` returns, which is otherwise
+   * a copy taken as the JVM starts. This relies upon the internals of the JDK, so false
+   * is returned if they don't permit it.
+   */
+  def install(environment: ThreadGroupEnvironment): Boolean =
+    try {
+      val field =
+        Class
+          .forName("java.lang.ProcessEnvironment")
+          .getDeclaredField("theUnmodifiableEnvironment")
+      field.setAccessible(true)
+
+      val modifiersField = classOf[Field].getDeclaredField("modifiers")
+      modifiersField.setAccessible(true)
+      modifiersField.setInt(field, field.getModifiers & ~Modifier.FINAL)
+
+      val unmodifiableEnvironment = Collections.unmodifiableMap[String, String](environment)
+      field.set(null, unmodifiableEnvironment)
+      field.get(null) eq unmodifiableEnvironment
+    } catch {
+      case NonFatal(_) => false
+    }
+}
+
 class ThreadGroupSecurityManager(s: SecurityManager)
   extends SecurityManager
   with ThreadGroupMapping[SecurityManager] {
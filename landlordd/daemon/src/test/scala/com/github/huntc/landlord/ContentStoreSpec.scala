@@ -0,0 +1,51 @@
+package com.github.huntc.landlord
+
+import java.nio.file.Files
+
+import org.scalatest._
+
+class ContentStoreSpec extends WordSpec with Matchers {
+  "The ContentStore" should {
+    "digest files and directories as the client does" in {
+      val root = Files.createTempDirectory("content-store-spec")
+      root.toFile.deleteOnExit()
+
+      Files.write(root.resolve("lib.jar"), "hello".getBytes("UTF-8"))
+      Files.createDirectories(root.resolve("tree/com/example"))
+      Files.write(root.resolve("tree/com/example/A.class"), "a".getBytes("UTF-8"))
+
+      ContentStore.digest(root.resolve("lib.jar")) shouldBe "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+      ContentStore.digest(root.resolve("tree")) shouldBe "1ed1b667564f4eb9af808359db5aa3a7a42d81ba0b2b12106c5a6467ee818727"
+    }
+
+    "keep entries that were sent and provide those that weren't" in {
+      val root = Files.createTempDirectory("content-store-spec")
+      root.toFile.deleteOnExit()
+
+      val storePath = root.resolve("content")
+      val libDigest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+      val treeDigest = "1ed1b667564f4eb9af808359db5aa3a7a42d81ba0b2b12106c5a6467ee818727"
+      val manifest = List("0" -> libDigest, "1" -> treeDigest, "2" -> ("0" * 64), "3" -> "../escape")
+
+      val first = root.resolve("1")
+      Files.write(Files.createDirectories(first).resolve("0"), "hello".getBytes("UTF-8"))
+      Files.createDirectories(first.resolve("1/com/example"))
+      Files.write(first.resolve("1/com/example/A.class"), "a".getBytes("UTF-8"))
+      Files.write(first.resolve("2"), "not as described".getBytes("UTF-8"))
+
+      ContentStore.present(storePath, manifest) shouldBe empty
+
+      ContentStore.materialize(storePath, first, manifest)
+
+      ContentStore.present(storePath, manifest) shouldBe List(libDigest, treeDigest)
+
+      val second = Files.createDirectories(root.resolve("2"))
+
+      ContentStore.materialize(storePath, second, manifest)
+
+      new String(Files.readAllBytes(second.resolve("0")), "UTF-8") shouldBe "hello"
+      new String(Files.readAllBytes(second.resolve("1/com/example/A.class")), "UTF-8") shouldBe "a"
+      Files.exists(second.resolve("2")) shouldBe false
+    }
+  }
+}
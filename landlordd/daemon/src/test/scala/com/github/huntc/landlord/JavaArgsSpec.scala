@@ -30,6 +30,59 @@ class JavaArgsSpec extends WordSpec with Matchers {
       assert(parsed.contains(JavaArgs(Seq.empty, Seq.empty, ClassExecutionMode("mainclass", Seq.empty), Seq("test1" -> "one", "test2" -> "two"))))
     }
 
+    "Parse module options" in {
+      val parsed = JavaArgs.parse(List(
+        "--add-modules=java.sql,m2",
+        "--add-opens=java.base/java.lang=ALL-UNNAMED",
+        "--add-exports=m1/p=m2,m3",
+        "-cp", "0",
+        "--module-path", "1:2",
+        "-m", "m1/p.Main", "arg"))
+      assert(parsed.contains(JavaArgs(
+        Seq("0"),
+        Seq.empty,
+        ModuleExecutionMode("m1", Some("p.Main"), Seq("arg")),
+        Seq.empty,
+        modulePath = Seq("1", "2"),
+        addModules = Seq("java.sql", "m2"),
+        addOpens = Seq(ModuleSystem.Directive("java.base", "java.lang", Seq("ALL-UNNAMED"))),
+        addExports = Seq(ModuleSystem.Directive("m1", "p", Seq("m2", "m3"))))))
+    }
+
+    "Return the main module without a main class" in {
+      val parsed = JavaArgs.parse(List("--module", "m1"))
+      assert(parsed.exists(_.mode == ModuleExecutionMode("m1", None, Seq.empty)))
+    }
+
+    "Fail when given invalid module options" in {
+      assert(JavaArgs.parse(List("-m")).left.exists(_ == Seq("-m requires module name")))
+      assert(JavaArgs.parse(List("--add-opens=java.base", "mainclass")).left.exists(_ == Seq("Invalid --add-opens value: java.base")))
+    }
+
+    "Parse agents, assertion switches and resource hints" in {
+      val parsed = JavaArgs.parse(List(
+        "-javaagent:2=verbose=true",
+        "-javaagent:3",
+        "-ea",
+        "-da:com.example...",
+        "-Xms64m",
+        "-Xmx2G",
+        "-Xss1000",
+        "mainclass"))
+      assert(parsed.contains(JavaArgs(
+        Seq.empty,
+        Seq.empty,
+        ClassExecutionMode("mainclass", Seq.empty),
+        Seq.empty,
+        agents = Seq("2" -> Some("verbose=true"), "3" -> None),
+        assertions = Seq("-ea", "-da:com.example..."),
+        resourceHints = ResourceHints(Some(64L << 20), Some(2L << 30), Some(1000L)))))
+    }
+
+    "Fail when given invalid resource hints" in {
+      assert(JavaArgs.parse(List("-Xmx2x", "mainclass")).left.exists(_ == Seq("Invalid size: -Xmx2x")))
+    }
+
     "Fail when given invalid flags" in {
       val parsed = JavaArgs.parse(List("-what", "mainclass"))
       assert(parsed.left.exists(_ == Seq("Unrecognized option: -what")))
@@ -6,13 +6,15 @@ import akka.stream.{ ActorMaterializer, ThrottleMode }
 import akka.stream.scaladsl.Source
 import akka.testkit._
 import java.io.ByteArrayOutputStream
+import java.net.URLClassLoader
 import java.nio.ByteOrder
 import java.nio.file.{ Files, Paths }
+import java.util.jar.{ Attributes, JarEntry, JarOutputStream, Manifest }
 
 import org.apache.commons.compress.archivers.tar.{ TarArchiveEntry, TarArchiveOutputStream }
 import org.scalatest._
 
-import scala.concurrent.{ ExecutionContext, Promise }
+import scala.concurrent.{ ExecutionContext, Future, Promise }
 import scala.concurrent.duration._
 
 class JvmExecutorSpec extends TestKit(ActorSystem("JvmExecutorSpec"))
@@ -42,6 +44,74 @@ class JvmExecutorSpec extends TestKit(ActorSystem("JvmExecutorSpec"))
     }
   }
 
+  "The assertion status" should {
+    def desiredAssertionStatus(switches: String*): Boolean = {
+      val classes = Paths.get(getClass.getResource("/example/Hello.class").toURI).getParent.getParent
+      val classLoader = new URLClassLoader(Array(classes.toUri.toURL), null)
+      try {
+        JvmExecutor.setAssertionStatus(classLoader, switches.toList)
+        classLoader.loadClass("example.Hello").desiredAssertionStatus()
+      } finally {
+        classLoader.close()
+      }
+    }
+
+    "be set for all classes, packages and classes" in {
+      assert(!desiredAssertionStatus())
+      assert(desiredAssertionStatus("-ea"))
+      assert(!desiredAssertionStatus("-ea", "-da:example..."))
+      assert(desiredAssertionStatus("-da", "-enableassertions:example.Hello"))
+      assert(!desiredAssertionStatus("-ea:com.example..."))
+    }
+  }
+
+  "The agent loader" should {
+    "start the Premain-Class of a jar with its options" in {
+      val base = Files.createTempDirectory("agent-loader-spec")
+      base.toFile.deleteOnExit()
+      val jar = base.resolve("agent.jar")
+      val manifest = new Manifest()
+      manifest.getMainAttributes.put(Attributes.Name.MANIFEST_VERSION, "1.0")
+      manifest.getMainAttributes.putValue("Premain-Class", "example.Agent")
+      val jos = new JarOutputStream(Files.newOutputStream(jar), manifest)
+      try {
+        jos.putNextEntry(new JarEntry("example/Agent.class"))
+        jos.write(Files.readAllBytes(Paths.get(getClass.getResource("/example/Agent.class").toURI)))
+        jos.closeEntry()
+      } finally {
+        jos.close()
+      }
+
+      val classLoader = new URLClassLoader(Array(jar.toUri.toURL), null)
+      try {
+        val agent = JvmExecutor.loadAgent(jar, Some("verbose"), classLoader)
+        agent.start()
+        assert(classLoader.loadClass("example.Agent").getField("options").get(null) == "verbose")
+      } finally {
+        classLoader.close()
+      }
+    }
+  }
+
+  "The exit reason encoder" should {
+    "convey the code, reason and message" in {
+      val bytes = JvmExecutor.exitReasonToBytes(
+        JvmExecutor.ExitStatus(1, JvmExecutor.ExitReason.LoadFailed, Some("boom"))
+      )
+      val iter = bytes.iterator
+      assert(iter.getByte == 'X')
+      assert(iter.getInt(ByteOrder.BIG_ENDIAN) == 1)
+      assert(iter.getInt(ByteOrder.BIG_ENDIAN) == JvmExecutor.ExitReason.LoadFailed)
+      assert(iter.getInt(ByteOrder.BIG_ENDIAN) == 4)
+      assert(iter.toByteString.utf8String == "boom")
+    }
+
+    "convey an empty message when there is none" in {
+      val bytes = JvmExecutor.exitReasonToBytes(JvmExecutor.ExitStatus(0, JvmExecutor.ExitReason.Exited))
+      assert(bytes.drop(9).iterator.getInt(ByteOrder.BIG_ENDIAN) == 0 && bytes.size == 13)
+    }
+  }
+
   "The JVMExecutor" should {
     "start a process that then outputs stdin, ends and shuts everything down" in {
       val stdin = new ThreadGroupInputStream(System.in)
@@ -98,7 +168,7 @@ class JvmExecutorSpec extends TestKit(ActorSystem("JvmExecutorSpec"))
       val in =
         Source(
           List(
-            ByteString(cl + "\n") ++
+            ByteString("l" + cl + "\n") ++
               ByteString(tar) ++
               ByteString(stdinStr),
             ByteString.empty
@@ -112,12 +182,15 @@ class JvmExecutorSpec extends TestKit(ActorSystem("JvmExecutorSpec"))
       val process =
         system.actorOf(JvmExecutor.props(
           123,
-          properties, securityManager, useDefaultSecurityManager = false,
+          Protocol.Legacy,
+          properties, new ThreadGroupEnvironment(System.getenv()),
+          securityManager, useDefaultSecurityManager = false,
           stdin, 3.seconds.dilated, stdout, stderr,
           in, out,
           12.seconds.dilated, 100.milliseconds.dilated,
           1.second,
           processDirPath,
+          processDirPath.resolve("content"),
           List.empty
         ))
 
@@ -161,6 +234,84 @@ class JvmExecutorSpec extends TestKit(ActorSystem("JvmExecutorSpec"))
 
       outputOk
     }
+
+    def signalProcess(className: String, signal: Int, exitTimeout: FiniteDuration): Future[Assertion] = {
+      val securityManager =
+        System.getSecurityManager match {
+          case s: ThreadGroupSecurityManager =>
+            s
+          case other =>
+            val s = new ThreadGroupSecurityManager(other)
+            System.setSecurityManager(s)
+            s
+        }
+
+      val classFile = s"example/$className.class"
+      val tar = {
+        val bos = new ByteArrayOutputStream()
+        val tos = new TarArchiveOutputStream(bos, 10240)
+        try {
+          List("classes/", "classes/example/").foreach { dir =>
+            tos.putArchiveEntry(new TarArchiveEntry(dir))
+            tos.closeArchiveEntry()
+          }
+          val data = Files.readAllBytes(Paths.get(getClass.getResource(s"/$classFile").toURI))
+          val te = new TarArchiveEntry(s"classes/$classFile")
+          te.setSize(data.length.toLong)
+          tos.putArchiveEntry(te)
+          tos.write(data)
+          tos.closeArchiveEntry()
+          tos.finish()
+        } finally {
+          tos.close()
+        }
+        bos.toByteArray
+      }
+
+      val in = Source.single(ByteString(s"l-cp\u0000classes\u0000example.$className\n") ++ ByteString(tar))
+      val out = Promise[Source[ByteString, akka.NotUsed]]()
+      val processDirPath = Files.createTempDirectory("jvm-executor-spec")
+      processDirPath.toFile.deleteOnExit()
+
+      val process =
+        system.actorOf(JvmExecutor.props(
+          124,
+          Protocol(Protocol.Version, Set(Protocol.ExitReasons)),
+          new ThreadGroupProperties(System.getProperties), new ThreadGroupEnvironment(System.getenv()),
+          securityManager, useDefaultSecurityManager = false,
+          new ThreadGroupInputStream(System.in), 3.seconds.dilated,
+          new ThreadGroupPrintStream(System.out), new ThreadGroupPrintStream(System.err),
+          in, out,
+          exitTimeout, 100.milliseconds.dilated,
+          1.second,
+          processDirPath,
+          processDirPath.resolve("content"),
+          List.empty
+        ))
+
+      out.future
+        .flatMap { outSource =>
+          process ! JvmExecutor.SignalProcess(signal)
+
+          outSource.runFold(ByteString.empty)(_ ++ _)
+        }(ExecutionContext.Implicits.global)
+        .map { bytes =>
+          val exitBytes = JvmExecutor.exitReasonToBytes(JvmExecutor.ExitStatus(128 + signal, JvmExecutor.ExitReason.Signaled))
+          assert(bytes.takeRight(exitBytes.size) == exitBytes)
+        }(ExecutionContext.Implicits.global)
+    }
+
+    "convey the exit of a process that exits upon its trap as being signaled" in {
+      signalProcess("Count", JvmExecutor.SIGINT, 12.seconds.dilated)
+    }
+
+    "stop a process without a trap upon a terminating signal, as being signaled" in {
+      signalProcess("Sleep", JvmExecutor.SIGTERM, 12.seconds.dilated)
+    }
+
+    "convey a process that is stopped once it hasn't exited in time as being signaled" in {
+      signalProcess("Stubborn", JvmExecutor.SIGINT, 1.second.dilated)
+    }
   }
 
   "activeThreads" should {
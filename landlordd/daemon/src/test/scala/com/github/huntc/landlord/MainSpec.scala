@@ -23,7 +23,9 @@ class MainSpec extends TestKit(ActorSystem("MainSpec"))
 
   implicit val ma: ActorMaterializer = ActorMaterializer()
 
-  def emptyLaunchInfoOp(in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) =
+  val protocol = Protocol(Protocol.Version, Protocol.Capabilities)
+
+  def emptyLaunchInfoOp(protocol: Protocol, in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) =
     0 -> Props.empty
 
   def emptySendKillOp(jvmExecutor: ActorSelection, signal: Int): Unit =
@@ -33,7 +35,7 @@ class MainSpec extends TestKit(ActorSystem("MainSpec"))
     "process input for launching a process" in {
       val reaper = TestProbe()
 
-      def launchInfoOp(in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) =
+      def launchInfoOp(protocol: Protocol, in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) =
         123 -> Props(new Actor {
           override def preStart(): Unit = {
             in
@@ -48,11 +50,58 @@ class MainSpec extends TestKit(ActorSystem("MainSpec"))
       val result =
         Source
           .single(ByteString("lsomeinput"))
-          .via(Main.controlFlow(reaper.ref, launchInfoOp, emptySendKillOp))
+          .via(Main.controlFlow(reaper.ref, protocol, launchInfoOp, emptySendKillOp))
           .runWith(Sink.head)
 
       reaper.expectMsgClass(classOf[Main.JvmExecutorReaper.Register])
-      result.map(r => assert(r.utf8String == "someinput"))
+      result.map(r => assert(r.utf8String == "lsomeinput"))
+    }
+
+    "respond to a hello and then launch a process with the negotiated protocol" in {
+      val reaper = TestProbe()
+
+      val negotiated = Promise[Protocol]()
+      def launchInfoOp(protocol: Protocol, in: Source[ByteString, NotUsed], out: Promise[Source[ByteString, NotUsed]]): (Int, Props) = {
+        negotiated.success(protocol)
+        123 -> Props(new Actor {
+          override def preStart(): Unit = {
+            in
+              .runWith(Sink.head)
+              .foreach(input => out.success(Source.single(input)))
+          }
+          def receive: Receive = {
+            case _ =>
+          }
+        })
+      }
+
+      val hello = ByteString.newBuilder.putByte('h').putInts(Array(2, Protocol.LengthPrefixedDescriptor.bit | 1 << 30))(ByteOrder.BIG_ENDIAN).result()
+      val result =
+        Source(List(hello.take(3), hello.drop(3) ++ ByteString("Lsomeinput")))
+          .via(Main.controlFlow(reaper.ref, protocol, launchInfoOp, emptySendKillOp))
+          .runFold(ByteString.empty)(_ ++ _)
+
+      reaper.expectMsgClass(classOf[Main.JvmExecutorReaper.Register])
+      for {
+        r <- result
+        p <- negotiated.future
+      } yield {
+        assert(r == protocol.toHello ++ ByteString("Lsomeinput"))
+        assert(p == Protocol(Protocol.Version, Set(Protocol.LengthPrefixedDescriptor)))
+      }
+    }
+
+    "receive ??? when launching with a length-prefixed descriptor without a hello" in {
+      val reaper = TestProbe()
+
+      val result =
+        Source
+          .single(ByteString("Lsomeinput"))
+          .via(Main.controlFlow(reaper.ref, protocol, emptyLaunchInfoOp, emptySendKillOp))
+          .runWith(Sink.head)
+
+      reaper.expectNoMessage(1.second.dilated)
+      result.map(r => assert(r.utf8String == "???"))
     }
 
     "receive a process id and signal when killing a process" in {
@@ -64,7 +113,7 @@ class MainSpec extends TestKit(ActorSystem("MainSpec"))
 
       Source
         .single(ByteString.newBuilder.putByte('k').putInts(Array(123, 15))(ByteOrder.BIG_ENDIAN).result())
-        .via(Main.controlFlow(reaper.ref, emptyLaunchInfoOp, sendKillOp))
+        .via(Main.controlFlow(reaper.ref, protocol, emptyLaunchInfoOp, sendKillOp))
         .runWith(Sink.ignore)
 
       reaper.expectNoMessage(1.second.dilated)
@@ -77,7 +126,7 @@ class MainSpec extends TestKit(ActorSystem("MainSpec"))
       val result =
         Source
           .single(ByteString.newBuilder.putByte('z').result())
-          .via(Main.controlFlow(reaper.ref, emptyLaunchInfoOp, emptySendKillOp))
+          .via(Main.controlFlow(reaper.ref, protocol, emptyLaunchInfoOp, emptySendKillOp))
           .runWith(Sink.head)
 
       reaper.expectNoMessage(1.second.dilated)
@@ -0,0 +1,42 @@
+package com.github.huntc.landlord
+
+import java.lang.reflect.InvocationTargetException
+import java.net.{ URL, URLClassLoader }
+
+import org.scalatest._
+
+class ModuleSystemSpec extends WordSpec with Matchers {
+  import ModuleSystem._
+
+  "A directive" should {
+    "be parsed from a module, package and targets" in {
+      Directive.parse("java.base/java.lang=ALL-UNNAMED,m1") shouldBe Some(Directive("java.base", "java.lang", Seq("ALL-UNNAMED", "m1")))
+    }
+
+    "require each of its parts" in {
+      Directive.parse("java.base/java.lang") shouldBe None
+      Directive.parse("java.base=ALL-UNNAMED") shouldBe None
+      Directive.parse("/java.lang=ALL-UNNAMED") shouldBe None
+      Directive.parse("java.base/java.lang=") shouldBe None
+    }
+  }
+
+  "The module system" should {
+    "resolve roots from the boot layer" in {
+      assume(Supported)
+      defineLayer(Seq.empty, Seq("java.sql", "ALL-DEFAULT")).loader shouldBe None
+    }
+
+    "fail to resolve unknown modules" in {
+      assume(Supported)
+      an[InvocationTargetException] should be thrownBy defineLayer(Seq.empty, Seq("com.example.unknown"))
+    }
+
+    "require landlordd's agent to open the packages of the JDK's modules" in {
+      assume(Supported && LandlordAgent.instrumentation.isEmpty)
+      val classLoader = new URLClassLoader(Array.empty[URL], null)
+      an[IllegalStateException] should be thrownBy
+        addOpens(Seq(Directive("java.base", "java.lang", Seq(AllUnnamed))), None, classLoader)
+    }
+  }
+}
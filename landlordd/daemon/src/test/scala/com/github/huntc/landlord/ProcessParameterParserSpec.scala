@@ -1,6 +1,8 @@
 package com.github.huntc.landlord
 
 import java.io.ByteArrayOutputStream
+import java.nio.ByteOrder
+import java.util.zip.GZIPOutputStream
 
 import akka.Done
 import akka.actor.ActorSystem
@@ -45,16 +47,16 @@ class ProcessParameterParserSpec extends TestKit(ActorSystem("ProcessParameterPa
       val emittedEnough = Promise[Done]
       Source
         .single(
-          ByteString(cl + "\n") ++
+          ByteString("l" + cl + "\n") ++
             ByteString(tar) ++
             ByteString(stdinStr)
         )
         .merge(Source.fromFuture(emittedEnough.future).map(_ => ByteString.empty))
-        .via(new ProcessParameterParser)
+        .via(new ProcessParameterParser(Protocol.Legacy))
         .runFoldAsync(0 -> succeed) {
           case ((ordinal, _), ProcessParameterParser.CommandLine(v)) =>
             Future.successful(1 -> assert(ordinal == 0 && v == cl))
-          case ((ordinal, _), ProcessParameterParser.Archive(v)) =>
+          case ((ordinal, _), ProcessParameterParser.Archive(v, _)) =>
             val complete = v.runFold(0L)(_ + _.size)
             complete.map(tarSize => 2 -> assert(ordinal == 1 && tarSize == TarBlockSize))
           case ((ordinal, _), ProcessParameterParser.Stdin(v)) =>
@@ -73,5 +75,118 @@ class ProcessParameterParserSpec extends TestKit(ActorSystem("ProcessParameterPa
           case (_, lastAssertion)               => lastAssertion
         }
     }
+
+    "produce a flow of ProcessInputParts given a console, environment, length-prefixed args and framed stdin" in {
+      val protocol = Protocol(Protocol.Version, Set(Protocol.EnvForwarding, Protocol.FramedStdin, Protocol.LengthPrefixedDescriptor))
+
+      val env = List("GREETING=hi", "EXPR=a=b")
+
+      val args = List("-cp", "some.jar", "example.Hello", "line one\nline two")
+
+      val tar = {
+        val bos = new ByteArrayOutputStream()
+        val tos = new TarArchiveOutputStream(bos)
+        try {
+          tos.flush()
+          tos.finish()
+        } finally {
+          tos.close()
+        }
+        bos.toByteArray
+      }
+
+      val stdinStr = "some stdin\n"
+
+      def intBytes(i: Int): ByteString =
+        ByteString.newBuilder.putInt(i)(ByteOrder.BIG_ENDIAN).result()
+
+      def fieldsBytes(fields: List[String]): ByteString =
+        intBytes(fields.size) ++ fields.map(f => intBytes(ByteString(f).size) ++ ByteString(f)).reduce(_ ++ _)
+
+      val input =
+        ByteString("t") ++ intBytes(24) ++ intBytes(80) ++
+          ByteString("E") ++ fieldsBytes(env) ++
+          ByteString("L") ++ fieldsBytes(args) ++
+          ByteString(tar) ++
+          ByteString("i") ++ intBytes(stdinStr.length) ++ ByteString(stdinStr) ++
+          ByteString("s") ++ intBytes(2) ++
+          ByteString("w") ++ intBytes(24) ++ intBytes(80) ++
+          ByteString("c")
+
+      Source(input.grouped(7).toList)
+        .via(new ProcessParameterParser(protocol))
+        .runFoldAsync(List.empty[Any]) {
+          case (parts, ProcessParameterParser.CommandLine(v)) =>
+            Future.successful(parts :+ v)
+          case (parts, ProcessParameterParser.Archive(v, _)) =>
+            v.runFold(0L)(_ + _.size).map(parts :+ _)
+          case (parts, ProcessParameterParser.Stdin(v)) =>
+            v.runFold("")(_ ++ _.utf8String).map(parts :+ _)
+          case (parts, part) =>
+            Future.successful(parts :+ part)
+        }
+        .map { parts =>
+          assert(
+            parts == List(
+              ProcessParameterParser.Console(24, 80),
+              ProcessParameterParser.Environment(Map("GREETING" -> "hi", "EXPR" -> "a=b")),
+              args.mkString("\u0000"),
+              tar.length.toLong,
+              stdinStr,
+              ProcessParameterParser.Signal(2),
+              ProcessParameterParser.WindowSize(24, 80)
+            )
+          )
+        }
+    }
+
+    "produce a flow of ProcessInputParts given a manifest and a tar compressed with gzip" in {
+      val protocol = Protocol(Protocol.Version, Set(Protocol.GzipCompression, Protocol.ContentAddressing, Protocol.FramedStdin, Protocol.LengthPrefixedDescriptor))
+
+      val manifest = List("0" -> ("a" * 64), "1" -> ("b" * 64))
+
+      val args = List("-cp", "some.jar", "example.Hello")
+
+      val compressedTar = {
+        val bos = new ByteArrayOutputStream()
+        val tos = new TarArchiveOutputStream(new GZIPOutputStream(bos))
+        try {
+          tos.flush()
+          tos.finish()
+        } finally {
+          tos.close()
+        }
+        ByteString(bos.toByteArray)
+      }
+
+      def intBytes(i: Int): ByteString =
+        ByteString.newBuilder.putInt(i)(ByteOrder.BIG_ENDIAN).result()
+
+      def fieldsBytes(fields: List[String]): ByteString =
+        intBytes(fields.size) ++ fields.map(f => intBytes(ByteString(f).size) ++ ByteString(f)).reduce(_ ++ _)
+
+      val input =
+        ByteString("z") ++ intBytes(1) ++
+          ByteString("m") ++ fieldsBytes(manifest.map { case (name, digest) => name + "=" + digest }) ++
+          ByteString("L") ++ fieldsBytes(args) ++
+          compressedTar.grouped(10).map(chunk => intBytes(chunk.size) ++ chunk).reduce(_ ++ _) ++ intBytes(0) ++
+          ByteString("c")
+
+      Source(input.grouped(7).toList)
+        .via(new ProcessParameterParser(protocol))
+        .runFoldAsync(List.empty[Any]) {
+          case (parts, ProcessParameterParser.CommandLine(v)) =>
+            Future.successful(parts :+ v)
+          case (parts, ProcessParameterParser.Archive(v, gzipped)) =>
+            v.runFold(ByteString.empty)(_ ++ _).map(parts :+ gzipped :+ _)
+          case (parts, ProcessParameterParser.Stdin(v)) =>
+            v.runFold("")(_ ++ _.utf8String).map(parts :+ _)
+          case (parts, part) =>
+            Future.successful(parts :+ part)
+        }
+        .map { parts =>
+          assert(parts == List(ProcessParameterParser.Manifest(manifest), args.mkString("\u0000"), true, compressedTar, ""))
+        }
+    }
   }
 }
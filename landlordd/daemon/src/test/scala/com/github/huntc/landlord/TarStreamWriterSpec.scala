@@ -7,6 +7,7 @@ import akka.stream.scaladsl.Source
 import akka.testkit.TestKit
 import java.io.ByteArrayOutputStream
 import java.nio.file.Files
+import java.util.zip.GZIPOutputStream
 import org.apache.commons.compress.archivers.{ ArchiveException, ArchiveStreamFactory }
 import org.apache.commons.compress.archivers.tar.{ TarArchiveEntry, TarArchiveOutputStream }
 import org.scalatest._
@@ -58,6 +59,35 @@ class TarStreamWriterSpec extends TestKit(ActorSystem("TarStreamWriterSpec"))
       }
     }
 
+    "Write out a valid stream of Tar input compressed with gzip" in {
+      implicit val mat: ActorMaterializer = ActorMaterializer()
+      val rootPath = Files.createTempDirectory("TarStreamWriterSpec")
+      rootPath.toFile.deleteOnExit()
+      val source =
+        Source.single {
+          val bos = new ByteArrayOutputStream()
+          val tos = new TarArchiveOutputStream(new GZIPOutputStream(bos))
+          try {
+            val te = new TarArchiveEntry("foo")
+            val data = "some-content".getBytes("UTF-8")
+            te.setSize(data.length.toLong)
+            tos.putArchiveEntry(te)
+            tos.write(data)
+            tos.closeArchiveEntry()
+            tos.flush()
+            tos.finish()
+          } finally {
+            tos.close()
+          }
+          ByteString(bos.toByteArray)
+        }
+      val blockingEc = scala.concurrent.ExecutionContext.Implicits.global
+      TarStreamWriter.writeTarStream(source, rootPath, blockingEc, gzipped = true).map { _ =>
+        val file = rootPath.resolve("foo")
+        assert(Files.size(file) == 12)
+      }
+    }
+
     "Reject an invalid stream of Tar input by failing the future" in {
       implicit val mat: ActorMaterializer = ActorMaterializer()
       val rootPath = Files.createTempDirectory("TarStreamWriterSpec")
@@ -2,7 +2,7 @@ package com.github.huntc.landlord
 
 import akka.util.ByteString
 import java.io.{ ByteArrayInputStream, ByteArrayOutputStream, PrintStream }
-import java.util.Properties
+import java.util.{ Collections, Properties }
 import org.scalatest._
 import scala.collection.JavaConverters._
 
@@ -118,6 +118,20 @@ class ThreadGroupMappingsSpec extends WordSpec with Matchers {
     }
   }
 
+  "A thread group's environment" should {
+    "return the fallback environment" in {
+      val threadGroupEnvironment = new ThreadGroupEnvironment(Collections.singletonMap("GREETING", "hi"))
+      threadGroupEnvironment.get("GREETING") shouldBe "hi"
+    }
+
+    "return an overridden environment when accessed via an inited thread" in {
+      val threadGroupEnvironment = new ThreadGroupEnvironment(Collections.singletonMap("GREETING", "hi"))
+      threadGroupEnvironment.init(Collections.singletonMap("GREETING", "hello"))
+      threadGroupEnvironment.get("GREETING") shouldBe "hello"
+      threadGroupEnvironment.asScala.toMap shouldBe Map("GREETING" -> "hello")
+    }
+  }
+
   "A thread group's security manager" should {
     "permit exit" in {
       val threadGroupSecurityManager =
@@ -0,0 +1,13 @@
+package example;
+
+/**
+ * A test agent that records the options that it was started with. Used to test that
+ * `landlordd` starts the agents of a program before its main method.
+ */
+public class Agent {
+    public static volatile String options;
+
+    public static void premain(String agentArgs) {
+        options = agentArgs;
+    }
+}
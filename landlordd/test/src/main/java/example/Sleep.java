@@ -0,0 +1,15 @@
+package example;
+
+/**
+ * A test program that sleeps until it is interrupted, and has no `trap`.
+ * Used to test that `landlordd` stops a program upon a terminating signal
+ * as the JVM would.
+ */
+public class Sleep {
+    public static void main(String[] args) {
+        try {
+            Thread.sleep(Long.MAX_VALUE);
+        } catch (InterruptedException ignored) {
+        }
+    }
+}
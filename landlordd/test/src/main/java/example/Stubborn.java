@@ -0,0 +1,16 @@
+package example;
+
+/**
+ * A test program that ignores the signals it receives for a while.
+ * Used to test that `landlordd` conveys the exit of a program that it
+ * stops once it hasn't exited in time.
+ */
+public class Stubborn {
+    public static void main(String[] args) throws InterruptedException {
+        Thread.sleep(10000);
+    }
+
+    @SuppressWarnings("unused")
+    public static void trap(int signal) {
+    }
+}